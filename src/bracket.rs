@@ -4,6 +4,7 @@ use std::fmt::Debug;
 pub enum BracketType {
     Round,
     Curly,
+    Square,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -24,6 +25,7 @@ impl Debug for Bracket {
     }
 }
 
+#[derive(Default)]
 pub struct BracketStack {
     stack: Vec<BracketType>,
 }
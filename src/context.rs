@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::io::{stdout, Write};
+
+use crate::parser::Expression;
+use crate::profile::Profile;
+use crate::values::builtins::{monotonic_clock, Clock, Sleep};
+
+/// State of an evaluation shared by the runtime and the built-ins, passed along with the
+/// variables to every evaluated expression
+pub struct Context {
+    /// Counts of evaluated expressions, collected only when profiling
+    pub profile: Option<Profile>,
+    /// With IO disabled, built-ins accessing files fail, for running untrusted scripts
+    pub io_enabled: bool,
+    /// In sandbox mode side-effecting built-ins are removed, so programs are pure
+    pub sandbox: bool,
+    /// In strict types mode integers and floats are not implicitly converted to each other in
+    /// arithmetic and comparisons, mixing them is an error
+    pub strict_types: bool,
+    /// Where `print` and `debug` write, standard output by default
+    pub output: Box<dyn Write>,
+    /// Caps the total size of tuples, lists, strings and bytes built while running, counted in
+    /// elements, characters and bytes, for untrusted programs; exceeding it is a runtime error
+    pub memory_limit: Option<usize>,
+    /// Size of the values built so far, values going out of scope don't free it. It's reset
+    /// between top-level evaluations, like the lines of `--per-line` and the REPL.
+    pub allocated: usize,
+    /// Read by `now`, can be replaced with a fake one in tests
+    pub clock: Clock,
+    /// Called by `sleep`, `std::thread::sleep` unless replaced with a no-op in tests
+    pub sleep: Sleep,
+    /// Names declared `global` in each user-defined function call in progress, innermost last
+    pub global_names: Vec<HashSet<String>>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            profile: None,
+            io_enabled: true,
+            sandbox: false,
+            strict_types: false,
+            output: Box::new(stdout()),
+            memory_limit: None,
+            allocated: 0,
+            clock: monotonic_clock(),
+            sleep: Box::new(std::thread::sleep),
+            global_names: Vec::new(),
+        }
+    }
+}
+
+impl Context {
+    pub fn record_eval(&mut self, expr: &Expression) {
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(expr);
+        }
+    }
+
+    /// Marks the variable to be written back to the caller when the current function call
+    /// ends, outside of functions all variables are global already
+    pub fn declare_global(&mut self, name: String) {
+        if let Some(frame) = self.global_names.last_mut() {
+            frame.insert(name);
+        }
+    }
+}
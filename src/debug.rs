@@ -1,87 +1,410 @@
+use std::rc::Rc;
+
 use crate::{
-    parser::Expression,
+    parser::{BinaryOp, Expression, UnaryOp},
+    span::Span,
     values::{function::Function, Value},
+    visitor::Visitor,
 };
 
 pub fn print_tree(expr: &Expression) {
     println!("{}", format_tree(expr));
 }
 
+pub fn print_tree_with_spans(expr: &Expression) {
+    println!("{}", format_tree_with_spans(expr));
+}
+
+pub fn print_tree_with_lines(expr: &Expression, code: &str) {
+    println!("{}", format_tree_with_lines(expr, code));
+}
+
 pub fn format_tree(expr: &Expression) -> String {
-    match expr {
-        Expression::Value(bv) => {
-            let bv_clone = bv.clone();
-            let v = bv_clone.as_ref();
-            match v {
-                Value::Function(Function::UserDefined(func)) => format_subexpressions(
-                    &format!("Function {}({:?})", func.name, func.params),
-                    [&func.body].iter().map(|&e| e),
-                    1,
-                ),
-                _ => format!("{:?}", v),
+    format_node(expr, false)
+}
+
+/// Same as `format_tree`, but each node is annotated with its source code span
+pub fn format_tree_with_spans(expr: &Expression) -> String {
+    format_node(expr, true)
+}
+
+/// Same as `format_tree_with_spans`, additionally annotating each top-level statement of the
+/// program with its source line numbers, to find statements of a large program in the tree
+pub fn format_tree_with_lines(expr: &Expression, code: &str) -> String {
+    let Expression::Scope { body, .. } = expr else {
+        return format_tree_with_spans(expr);
+    };
+    let line_number = |idx: usize| code[..idx.min(code.len())].matches('\n').count() + 1;
+    let subtrees: Vec<String> = body
+        .iter()
+        .map(|statement| {
+            let span = statement.span();
+            let (first, last) = (line_number(span.start), line_number(span.end));
+            let lines = if first == last {
+                format!("line {}", first)
+            } else {
+                format!("lines {}-{}", first, last)
+            };
+            let tree = format_node(statement, true);
+            match tree.split_once('\n') {
+                Some((title, subtrees)) => format!("{} ({})\n{}", title, lines, subtrees),
+                None => format!("{} ({})", tree, lines),
             }
+        })
+        .collect();
+    format_subtrees(&format!("┬ Scope ─ [{:?}]", expr.span()), &subtrees)
+}
+
+fn format_node(expr: &Expression, with_spans: bool) -> String {
+    let mut formatter = TreeFormatter {
+        with_spans,
+        tree: String::new(),
+    };
+    formatter.visit(expr);
+    let tree = formatter.tree;
+    if !with_spans {
+        return tree;
+    }
+    match tree.split_once('\n') {
+        Some((title, subtrees)) => format!("{} [{:?}]\n{}", title, expr.span(), subtrees),
+        None => format!("{} [{:?}]", tree, expr.span()),
+    }
+}
+
+/// Formats the visited node into `tree`, with its children formatted recursively
+struct TreeFormatter {
+    with_spans: bool,
+    tree: String,
+}
+
+impl TreeFormatter {
+    fn set_subtrees(&mut self, title: &str, subexprs: &[&Expression]) {
+        let subtrees: Vec<String> = subexprs
+            .iter()
+            .map(|expr| format_node(expr, self.with_spans))
+            .collect();
+        self.tree = format_subtrees(title, &subtrees);
+    }
+}
+
+impl Visitor for TreeFormatter {
+    fn visit_value(&mut self, value: &Rc<Value>, _span: Span) {
+        match value.as_ref() {
+            Value::Function(Function::UserDefined(func)) => self.set_subtrees(
+                &format!("Function {}({})", func.name, format_pattern(&func.params)),
+                &[&func.body],
+            ),
+            v => self.tree = format!("{:?}", v),
+        }
+    }
+
+    fn visit_variable(&mut self, name: &str, _span: Span) {
+        self.tree = name.to_string();
+    }
+
+    fn visit_binary_operation(
+        &mut self,
+        op: BinaryOp,
+        left: &Expression,
+        right: &Expression,
+        _span: Span,
+    ) {
+        self.set_subtrees(&format!("{:?}", op), &[left, right]);
+    }
+
+    fn visit_unary_operation(&mut self, op: UnaryOp, operand: &Expression, _span: Span) {
+        self.set_subtrees(&format!("{:?}", op), &[operand]);
+    }
+
+    fn visit_scope(&mut self, body: &[Expression], _is_returnable: bool, _span: Span) {
+        self.set_subtrees("┬ Scope ─", &body.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_list(&mut self, elements: &[Expression], _span: Span) {
+        self.set_subtrees("List", &elements.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        if_true: &Expression,
+        if_false: Option<&Expression>,
+        _span: Span,
+    ) {
+        match if_false {
+            Some(if_false) => self.set_subtrees("IfElse", &[condition, if_true, if_false]),
+            None => self.set_subtrees("If", &[condition, if_true]),
+        }
+    }
+
+    fn visit_while(
+        &mut self,
+        label: Option<&str>,
+        condition: &Expression,
+        body: &Expression,
+        if_completed: Option<&Expression>,
+        _span: Span,
+    ) {
+        match if_completed {
+            Some(if_completed) => self.set_subtrees(
+                &labeled_title("WhileElse", label),
+                &[condition, body, if_completed],
+            ),
+            None => self.set_subtrees(&labeled_title("While", label), &[condition, body]),
+        }
+    }
+
+    fn visit_repeat(
+        &mut self,
+        label: Option<&str>,
+        count: &Expression,
+        body: &Expression,
+        _span: Span,
+    ) {
+        self.set_subtrees(&labeled_title("Repeat", label), &[count, body]);
+    }
+
+    fn visit_typeswitch(&mut self, value: &Expression, arms: &[(String, Expression)], _span: Span) {
+        self.set_subtrees(&typeswitch_title(arms), &typeswitch_children(value, arms));
+    }
+
+    fn visit_break(&mut self, label: Option<&str>, _span: Span) {
+        self.tree = labeled_title("Break", label);
+    }
+
+    fn visit_slice(
+        &mut self,
+        collection: &Expression,
+        parts: [Option<&Expression>; 3],
+        is_slice: bool,
+        _span: Span,
+    ) {
+        let children: Vec<&Expression> = [Some(collection)]
+            .into_iter()
+            .chain(parts)
+            .flatten()
+            .collect();
+        self.set_subtrees(&slice_title(parts, is_slice), &children);
+    }
+}
+
+/// AST as a GraphViz graph with a node per expression, labeled like in `format_tree`, and edges
+/// from each node to its children, in order. Render with e.g. `dot -Tsvg`.
+pub fn format_dot(expr: &Expression) -> String {
+    let mut formatter = DotFormatter {
+        lines: Vec::new(),
+        node_count: 0,
+        last_node: 0,
+    };
+    formatter.visit(expr);
+    format!("digraph AST {{\n{}\n}}", formatter.lines.join("\n"))
+}
+
+/// Appends declarations of the visited node, its children and the edges between them to `lines`
+struct DotFormatter {
+    lines: Vec<String>,
+    node_count: usize,
+    last_node: usize,
+}
+
+impl DotFormatter {
+    fn add_node(&mut self, label: &str, children: &[&Expression]) {
+        let node = self.node_count;
+        self.node_count += 1;
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        self.lines
+            .push(format!("    n{} [label=\"{}\"];", node, label));
+        for child in children {
+            self.visit(child);
+            self.lines
+                .push(format!("    n{} -> n{};", node, self.last_node));
+        }
+        self.last_node = node;
+    }
+}
+
+impl Visitor for DotFormatter {
+    fn visit_value(&mut self, value: &Rc<Value>, _span: Span) {
+        match value.as_ref() {
+            Value::Function(Function::UserDefined(func)) => self.add_node(
+                &format!("Function {}({})", func.name, format_pattern(&func.params)),
+                &[&func.body],
+            ),
+            v => self.add_node(&format!("{:?}", v), &[]),
         }
-        Expression::Variable(name) => format!("{}", name),
-        Expression::BinaryOperation { op, left, right } => format_subexpressions(
-            &format!("{:?}", op),
-            [left, right].iter().map(|&e| e.as_ref()),
-            2,
-        ),
-        Expression::UnaryOperation { op, operand } => format_subexpressions(
-            &format!("{:?}", op),
-            [operand].iter().map(|&e| e.as_ref()),
-            1,
-        ),
-        Expression::Scope {
-            body,
-            is_returnable: _,
-        } => format_subexpressions("┬ Scope ─", body.iter(), body.len()),
-        Expression::If {
-            condition,
-            if_true,
-            if_false,
+    }
+
+    fn visit_variable(&mut self, name: &str, _span: Span) {
+        self.add_node(name, &[]);
+    }
+
+    fn visit_binary_operation(
+        &mut self,
+        op: BinaryOp,
+        left: &Expression,
+        right: &Expression,
+        _span: Span,
+    ) {
+        self.add_node(&format!("{:?}", op), &[left, right]);
+    }
+
+    fn visit_unary_operation(&mut self, op: UnaryOp, operand: &Expression, _span: Span) {
+        self.add_node(&format!("{:?}", op), &[operand]);
+    }
+
+    fn visit_scope(&mut self, body: &[Expression], _is_returnable: bool, _span: Span) {
+        self.add_node("Scope", &body.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_list(&mut self, elements: &[Expression], _span: Span) {
+        self.add_node("List", &elements.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        if_true: &Expression,
+        if_false: Option<&Expression>,
+        _span: Span,
+    ) {
+        match if_false {
+            Some(if_false) => self.add_node("IfElse", &[condition, if_true, if_false]),
+            None => self.add_node("If", &[condition, if_true]),
+        }
+    }
+
+    fn visit_while(
+        &mut self,
+        label: Option<&str>,
+        condition: &Expression,
+        body: &Expression,
+        if_completed: Option<&Expression>,
+        _span: Span,
+    ) {
+        match if_completed {
+            Some(if_completed) => self.add_node(
+                &labeled_title("WhileElse", label),
+                &[condition, body, if_completed],
+            ),
+            None => self.add_node(&labeled_title("While", label), &[condition, body]),
+        }
+    }
+
+    fn visit_repeat(
+        &mut self,
+        label: Option<&str>,
+        count: &Expression,
+        body: &Expression,
+        _span: Span,
+    ) {
+        self.add_node(&labeled_title("Repeat", label), &[count, body]);
+    }
+
+    fn visit_typeswitch(&mut self, value: &Expression, arms: &[(String, Expression)], _span: Span) {
+        self.add_node(&typeswitch_title(arms), &typeswitch_children(value, arms));
+    }
+
+    fn visit_break(&mut self, label: Option<&str>, _span: Span) {
+        self.add_node(&labeled_title("Break", label), &[]);
+    }
+
+    fn visit_slice(
+        &mut self,
+        collection: &Expression,
+        parts: [Option<&Expression>; 3],
+        is_slice: bool,
+        _span: Span,
+    ) {
+        let children: Vec<&Expression> = [Some(collection)]
+            .into_iter()
+            .chain(parts)
+            .flatten()
+            .collect();
+        self.add_node(&slice_title(parts, is_slice), &children);
+    }
+}
+
+/// `Index` or `Slice` with the present parts, like `Slice start::step`, the children are the
+/// collection and the present parts
+fn slice_title(parts: [Option<&Expression>; 3], is_slice: bool) -> String {
+    if !is_slice {
+        return "Index".into();
+    }
+    let part_names: Vec<&str> = parts
+        .iter()
+        .zip(["start", "end", "step"])
+        .map(|(part, name)| if part.is_some() { name } else { "" })
+        .collect();
+    format!("Slice {}", part_names.join(":"))
+}
+
+/// Node title with the loop label, if there is one
+fn labeled_title(title: &str, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{} {}", title, label),
+        None => title.into(),
+    }
+}
+
+/// Arm type names in the node title, the children are the switched value and the arm bodies
+fn typeswitch_title(arms: &[(String, Expression)]) -> String {
+    let type_names: Vec<&str> = arms
+        .iter()
+        .map(|(type_name, _)| type_name.as_str())
+        .collect();
+    format!("TypeSwitch {}", type_names.join(", "))
+}
+
+fn typeswitch_children<'a>(
+    value: &'a Expression,
+    arms: &'a [(String, Expression)],
+) -> Vec<&'a Expression> {
+    [value]
+        .into_iter()
+        .chain(arms.iter().map(|(_, body)| body))
+        .collect()
+}
+
+/// Assignment pattern (e.g. function parameters) in a source-like form: `a, (b, c)`
+pub(crate) fn format_pattern(pattern: &Expression) -> String {
+    match pattern {
+        Expression::Variable(name, _) => name.clone(),
+        Expression::Value(v, _) => format!("{}", v),
+        Expression::BinaryOperation {
+            op: op @ (BinaryOp::FormTuple | BinaryOp::AppendToTuple),
+            left,
+            right,
+            span: _,
         } => {
-            if let Some(if_false) = if_false {
-                format_subexpressions(
-                    &format!("IfElse"),
-                    [condition, if_true, if_false].iter().map(|&e| e.as_ref()),
-                    3,
-                )
+            let format_element = |element: &Expression| match element {
+                Expression::BinaryOperation {
+                    op: BinaryOp::FormTuple | BinaryOp::AppendToTuple,
+                    ..
+                } => format!("({})", format_pattern(element)),
+                _ => format_pattern(element),
+            };
+            // the left side of an append is the tuple being extended, not a nested one
+            let left = if *op == BinaryOp::AppendToTuple {
+                format_pattern(left)
             } else {
-                format_subexpressions(
-                    &format!("If"),
-                    [condition, if_true].iter().map(|&e| e.as_ref()),
-                    2,
-                )
-            }
+                format_element(left)
+            };
+            format!("{}, {}", left, format_element(right))
         }
-        Expression::While {
-            condition,
-            body,
-            if_completed: _,
-        } => format_subexpressions(
-            &format!("While"),
-            [condition, body].iter().map(|&e| e.as_ref()),
-            2,
-        ),
-    }
-}
-
-fn format_subexpressions<'a>(
-    title: &str,
-    subexpr_iter: impl Iterator<Item = &'a Expression>,
-    subexpr_count: usize,
-) -> String {
+        other => format_tree(other).replace('\n', " "),
+    }
+}
+
+fn format_subtrees(title: &str, subtrees: &[String]) -> String {
     let mut res: String = title.into();
     res.push('\n');
-    for (idx, expr) in subexpr_iter.enumerate() {
-        let is_last_subexpr = idx >= subexpr_count - 1;
+    for (idx, expr_tree) in subtrees.iter().enumerate() {
+        let is_last_subexpr = idx >= subtrees.len() - 1;
         let (pre_first, pre_other) = if !is_last_subexpr {
             ("├─", "│ ")
         } else {
             ("└─", "  ")
         };
-        let expr_tree = format_tree(expr);
         let expr_tree_lines: Vec<&str> = expr_tree.lines().collect();
         for (line_idx, &line) in expr_tree_lines.iter().enumerate() {
             res.push_str(if line_idx == 0 { pre_first } else { pre_other });
@@ -93,3 +416,98 @@ fn format_subexpressions<'a>(
     }
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, tokenize};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("func foo(a) { a }", "Function foo(a)")]
+    #[case("func foo(a, b) { a }", "Function foo(a, b)")]
+    #[case("func foo(a, b, c) { a }", "Function foo(a, b, c)")]
+    #[case("func foo(a, (b, c)) { a }", "Function foo(a, (b, c))")]
+    #[case("func foo((a, b), c) { a }", "Function foo((a, b), c)")]
+    fn test_format_tree_function_params(#[case] code: &str, #[case] expected_title: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let tree = format_tree(&ast);
+        let function_line = tree.lines().find(|line| line.contains("Function")).unwrap();
+        assert!(
+            function_line.ends_with(expected_title),
+            "{} doesn't end with {}",
+            function_line,
+            expected_title
+        );
+    }
+
+    #[test]
+    fn test_format_dot() {
+        let tokens = tokenize("1 + 2").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let dot = format_dot(&ast);
+        assert!(dot.starts_with("digraph AST {\n"));
+        assert!(dot.ends_with("\n}"));
+        for line in [
+            "n0 [label=\"Scope\"];",
+            "n1 [label=\"Add\"];",
+            "n2 [label=\"Int(1)\"];",
+            "n3 [label=\"Int(2)\"];",
+            "n0 -> n1;",
+            "n1 -> n2;",
+            "n1 -> n3;",
+        ] {
+            assert!(dot.contains(line), "{} not found in\n{}", line, dot);
+        }
+        assert_eq!(dot.matches("->").count(), 3);
+    }
+
+    #[test]
+    fn test_format_while_else() {
+        let tokens = tokenize("while false 1 else 2").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            format_tree(&ast),
+            "┬ Scope ─\n└─WhileElse\n  ├─Bool(false)\n  ├─Int(1)\n  └─Int(2)"
+        );
+        let dot = format_dot(&ast);
+        assert!(dot.contains("n1 [label=\"WhileElse\"];"));
+        for line in ["n1 -> n2;", "n1 -> n3;", "n1 -> n4;"] {
+            assert!(dot.contains(line), "{} not found in\n{}", line, dot);
+        }
+    }
+
+    #[test]
+    fn test_format_dot_escapes_labels() {
+        let tokens = tokenize(r#"print("a\\b")"#).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert!(format_dot(&ast).contains(r#"[label="String(\"a\\\\b\")"];"#));
+    }
+
+    #[test]
+    fn test_format_tree_with_lines() {
+        let code = "a = 1;\nb = {\n  a\n}";
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let tree = format_tree_with_lines(&ast, code);
+        let titles: Vec<&str> = tree
+            .lines()
+            .filter(|line| line.contains("Assign"))
+            .collect();
+        assert_eq!(
+            titles,
+            ["├─Assign [0..5] (line 1)", "└─Assign [7..18] (lines 2-4)"]
+        );
+    }
+
+    #[test]
+    fn test_format_tree_with_spans() {
+        let tokens = tokenize("1 + 2").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            format_tree_with_spans(&ast),
+            "┬ Scope ─ [0..5]\n└─Add [0..5]\n  ├─Int(1) [0..1]\n  └─Int(2) [4..5]"
+        );
+    }
+}
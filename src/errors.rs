@@ -1,13 +1,36 @@
-use std::{error::Error, fmt::Display};
+use std::{cell::Cell, error::Error, fmt::Display};
 
 use itertools::Itertools;
+use unicode_width::UnicodeWidthStr;
 
 use crate::debug::format_tree;
 use crate::parser::Expression;
+use crate::span::Span;
 use crate::tokenizer::untokenize;
 use crate::tokenizer::Token;
 use crate::tokenizer::TokenType;
 
+thread_local! {
+    static COLORED_ERRORS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes error messages use ANSI colors: red message, dimmed source context and yellow caret
+pub fn set_colored_errors(colored: bool) {
+    COLORED_ERRORS.with(|flag| flag.set(colored));
+}
+
+const RED: &str = "31";
+const DIM: &str = "2";
+const BRIGHT_YELLOW: &str = "93";
+
+fn painted(text: &str, style: &str) -> String {
+    if COLORED_ERRORS.with(Cell::get) {
+        format!("\x1b[{}m{}\x1b[0m", style, text)
+    } else {
+        text.to_string()
+    }
+}
+
 #[derive(Debug)]
 pub struct TokenizerError<'a> {
     pub code: &'a str,
@@ -19,34 +42,119 @@ impl Error for TokenizerError<'_> {}
 
 impl Display for TokenizerError<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let &start_offset = &self.code[..self.error_char_idx]
-            .chars()
-            .rev()
-            .enumerate()
-            .find(|&(_, ch)| ch == '\n')
-            .map(|(idx, _)| idx)
-            .unwrap_or(self.error_char_idx);
+        write!(
+            f,
+            "Tokenizer error\n{} {}",
+            format_code_context(
+                self.code,
+                Span::new(self.error_char_idx, self.error_char_idx + 1)
+            ),
+            painted(&self.errmsg, RED)
+        )
+    }
+}
 
-        let &end_offset = &self.code[self.error_char_idx..]
-            .chars()
-            .enumerate()
-            .find(|&(_, ch)| ch == '\n')
-            .map(|(idx, _)| idx)
-            .unwrap_or(self.code.len() - self.error_char_idx);
+/// Longer explanations of error classes with examples, looked up by `--explain`. Errors don't
+/// carry these codes, each explanation starts with the header and message of the error it covers
+const ERROR_EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E0001",
+        "Tokenizer error: the source contains a character that can't start any token.
 
-        let code_context_line =
-            &self.code[self.error_char_idx - start_offset..self.error_char_idx + end_offset];
+Only ASCII is allowed outside of string literals, and some characters only make sense as
+part of an operator, like \"?\" in \"??\" and \"?.\" or \"|\" in \"|>\".
 
-        let mut pointing_arrow_line = " ".repeat(start_offset);
+    x = 1 $ 2      # error
+    x = 1 + 2      # ok",
+    ),
+    (
+        "E0002",
+        "Parser error: the tokens don't form a valid expression.
 
-        pointing_arrow_line.push_str("^");
+Usually an operator is missing between two operands, an operand is missing after an
+operator, or a bracket is never closed. Run with --check-brackets to see all unmatched
+brackets at once.
 
-        write!(
-            f,
-            "Tokenizer error\n> {}\n  {} {}",
-            code_context_line, pointing_arrow_line, self.errmsg
-        )
+    a = (1 + 2     # error: unclosed bracket
+    a = 1 2        # error: binary operator expected
+    a = (1 + 2)    # ok",
+    ),
+    (
+        "E0003",
+        "Runtime error: reference to non-existent variable.
+
+A variable is read before anything is assigned to it. Variables of the enclosing code are
+visible in blocks and functions, but assignments in a function don't leave it.
+
+    print(x)       # error
+    x = 1; print(x)  # ok",
+    ),
+    (
+        "E0004",
+        "Runtime error: operation is not defined for the operand types.
+
+Operators and built-in functions only accept some combinations of types, for example
+strings can be multiplied by an integer but not added to a number. Use type(x) to check
+what a value is and str(x) or float(x) to convert it.
+
+    \"a\" + 1        # error
+    \"a\" + str(1)   # ok",
+    ),
+    (
+        "E0005",
+        "Runtime error: division by zero.
+
+Division and \"%\" fail when the divisor is an exact zero, an integer or a rational number.
+Floating point division by zero follows IEEE 754 instead and gives inf, -inf or nan. Check
+the divisor first or divide by a float if an infinite result is expected.
+
+    1 / 0          # error
+    1 / 0.0        # inf
+    if (d != 0) 1 / d else inf  # ok",
+    ),
+    (
+        "E0006",
+        "Runtime error: value is not callable.
+
+Only functions can be called. Brackets right after a value are read as a call, so a
+multiplication needs an explicit \"*\".
+
+    x = 2; x(3)    # error
+    x = 2; x * (3) # ok",
+    ),
+];
+
+/// Explanation of an error code like \"E0002\", for `--explain`
+pub fn explain_error_code(code: &str) -> Option<&'static str> {
+    ERROR_EXPLANATIONS
+        .iter()
+        .find(|(known_code, _)| known_code.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// Source line containing the start of the span with an arrow line pointing at the span
+pub fn format_code_context(code: &str, span: Span) -> String {
+    let line_start = code[..span.start].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = code[span.start..]
+        .find('\n')
+        .map_or(code.len(), |idx| span.start + idx);
+    let code_context_line = &code[line_start..line_end];
+
+    // the span end may point inside a multibyte char, e.g. for a one-byte span at a non-ASCII char
+    let mut arrow_end = span.end.clamp(span.start, line_end);
+    while !code.is_char_boundary(arrow_end) {
+        arrow_end += 1;
     }
+    // offsets are in display columns, so the arrow is under the right glyph after wide chars
+    let mut pointing_arrow_line = " ".repeat(code[line_start..span.start].width());
+    let arrow_len = code[span.start..arrow_end].width().max(1);
+    pointing_arrow_line.push_str(&painted(&"^".repeat(arrow_len), BRIGHT_YELLOW));
+
+    format!(
+        "> {}\n  {}",
+        painted(code_context_line, DIM),
+        pointing_arrow_line
+    )
 }
 
 #[cfg(test)]
@@ -72,15 +180,22 @@ mod tokenizer_error_tests {
         15,
         "Tokenizer error\n> line 3\n   ^ example error"
     )]
+    #[case("é$", 2, "Tokenizer error\n> é$\n   ^ example error")]
+    #[case(
+        "日本 = 1 $",
+        11,
+        "Tokenizer error\n> 日本 = 1 $\n           ^ example error"
+    )]
+    #[case("ab日c", 2, "Tokenizer error\n> ab日c\n    ^^ example error")]
     fn test_tokenizer_error_display(
         #[case] code: &str,
         #[case] error_char_idx: usize,
         #[case] expected_formatted_error: &str,
     ) {
         let e = TokenizerError {
-            code: code,
+            code,
             errmsg: "example error".into(),
-            error_char_idx: error_char_idx,
+            error_char_idx,
         };
         assert_eq!(format!("{}", e), expected_formatted_error);
     }
@@ -121,16 +236,18 @@ impl Display for ParserError<'_> {
             &self.tokens[self.error_token_idx - start_offset..=self.error_token_idx],
             true,
         );
-        let code_context_err = untokenize(&vec![self.tokens[self.error_token_idx].clone()], true);
+        let code_context_err = untokenize(&[self.tokens[self.error_token_idx].clone()], true);
         let mut pointing_arrow_line =
             " ".repeat(code_context_pre_err.len() - code_context_err.len());
 
-        pointing_arrow_line.push_str(&"^".repeat(code_context_err.len()));
+        pointing_arrow_line.push_str(&painted(&"^".repeat(code_context_err.len()), BRIGHT_YELLOW));
 
         write!(
             f,
             "Parser error\n> {}\n  {} {}",
-            code_context_line, pointing_arrow_line, self.errmsg
+            painted(&code_context_line, DIM),
+            pointing_arrow_line,
+            painted(&self.errmsg, RED)
         )
     }
 }
@@ -143,8 +260,26 @@ pub struct RuntimeError {
 
 impl Error for RuntimeError {}
 
-impl Display for RuntimeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl RuntimeError {
+    /// Location of the failing expression in the source code
+    pub fn span(&self) -> Option<Span> {
+        self.traceback.first().map(|expr| expr.span())
+    }
+
+    /// Like Display, but also points at the failing expression in the original source
+    pub fn format_with_source(&self, code: &str) -> String {
+        match self.span() {
+            Some(span) if span.start < code.len() => format!(
+                "Runtime error\n{} {}\nTraceback:\n{}",
+                format_code_context(code, span),
+                painted(&self.errmsg, RED),
+                self.format_traceback()
+            ),
+            _ => format!("{}", self),
+        }
+    }
+
+    fn format_traceback(&self) -> String {
         let mut traceback_dump: String = String::new();
         let indent = 4;
         for (idx, expression) in self.traceback.iter().enumerate() {
@@ -163,10 +298,17 @@ impl Display for RuntimeError {
             expression_dump.push('\n');
             traceback_dump.push_str(&expression_dump);
         }
+        traceback_dump
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "Runtime error: {}\nTraceback:\n{}",
-            self.errmsg, traceback_dump,
+            painted(&self.errmsg, RED),
+            self.format_traceback(),
         )
     }
 }
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    context::Context,
+    parser::{BinaryOp, Expression, UnaryOp},
+    runtime::eval,
+    span::Span,
+    values::{
+        function::{Function, UserDefinedFunction},
+        Value,
+    },
+    visitor::Visitor,
+};
+
+/// Copy of the program with arithmetic, comparison and logic operations on number and bool
+/// literals replaced by their results, including inside function bodies. Operations that fail
+/// (e.g. `1 / 0`) are kept as is, so the error is still reported when the program runs.
+pub fn fold_constants(program: &Expression) -> Expression {
+    ConstantFolder { folded: None }.fold(program)
+}
+
+/// Rebuilds the visited node into `folded`, with its children folded recursively
+struct ConstantFolder {
+    folded: Option<Expression>,
+}
+
+impl ConstantFolder {
+    fn fold(&mut self, expr: &Expression) -> Expression {
+        self.visit(expr);
+        self.folded
+            .take()
+            .expect("every visit method sets the folded node")
+    }
+
+    fn fold_boxed(&mut self, expr: &Expression) -> Box<Expression> {
+        Box::new(self.fold(expr))
+    }
+}
+
+fn is_constant(expr: &Expression) -> bool {
+    match expr {
+        Expression::Value(value, _) => matches!(
+            value.as_ref(),
+            Value::Int(_)
+                | Value::BigInt(_)
+                | Value::Rational { .. }
+                | Value::Float(_)
+                | Value::Complex { .. }
+                | Value::Bool(_)
+        ),
+        _ => false,
+    }
+}
+
+fn try_eval(expr: Expression) -> Expression {
+    match eval(&expr, &mut HashMap::new(), &mut Context::default()) {
+        Ok(value) => Expression::Value(value, expr.span()),
+        Err(_) => expr,
+    }
+}
+
+impl Visitor for ConstantFolder {
+    fn visit_value(&mut self, value: &Rc<Value>, span: Span) {
+        let value = match value.as_ref() {
+            Value::Function(Function::UserDefined(func)) => Rc::new(Value::Function(
+                Function::UserDefined(UserDefinedFunction {
+                    name: func.name.clone(),
+                    params: func.params.clone(),
+                    body: self.fold(&func.body),
+                }),
+            )),
+            _ => value.clone(),
+        };
+        self.folded = Some(Expression::Value(value, span));
+    }
+
+    fn visit_variable(&mut self, name: &str, span: Span) {
+        self.folded = Some(Expression::Variable(name.into(), span));
+    }
+
+    fn visit_binary_operation(
+        &mut self,
+        op: BinaryOp,
+        left: &Expression,
+        right: &Expression,
+        span: Span,
+    ) {
+        let folded = Expression::BinaryOperation {
+            op,
+            left: self.fold_boxed(left),
+            right: self.fold_boxed(right),
+            span,
+        };
+        let is_foldable_op = matches!(
+            op,
+            BinaryOp::Add
+                | BinaryOp::Sub
+                | BinaryOp::Mul
+                | BinaryOp::Div
+                | BinaryOp::Mod
+                | BinaryOp::Pow
+                | BinaryOp::IsEq
+                | BinaryOp::IsNotEq
+                | BinaryOp::IsLt
+                | BinaryOp::IsGt
+                | BinaryOp::And
+                | BinaryOp::Or
+        );
+        self.folded = Some(match &folded {
+            Expression::BinaryOperation { left, right, .. }
+                if is_foldable_op && is_constant(left) && is_constant(right) =>
+            {
+                try_eval(folded)
+            }
+            _ => folded,
+        });
+    }
+
+    fn visit_unary_operation(&mut self, op: UnaryOp, operand: &Expression, span: Span) {
+        let operand = self.fold_boxed(operand);
+        let is_foldable = op == UnaryOp::Neg && is_constant(&operand);
+        let folded = Expression::UnaryOperation { op, operand, span };
+        self.folded = Some(if is_foldable {
+            try_eval(folded)
+        } else {
+            folded
+        });
+    }
+
+    fn visit_scope(&mut self, body: &[Expression], is_returnable: bool, span: Span) {
+        self.folded = Some(Expression::Scope {
+            body: body.iter().map(|expr| self.fold(expr)).collect(),
+            is_returnable,
+            span,
+        });
+    }
+
+    fn visit_list(&mut self, elements: &[Expression], span: Span) {
+        self.folded = Some(Expression::List {
+            elements: elements.iter().map(|expr| self.fold(expr)).collect(),
+            span,
+        });
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        if_true: &Expression,
+        if_false: Option<&Expression>,
+        span: Span,
+    ) {
+        self.folded = Some(Expression::If {
+            condition: self.fold_boxed(condition),
+            if_true: self.fold_boxed(if_true),
+            if_false: if_false.map(|expr| self.fold_boxed(expr)),
+            span,
+        });
+    }
+
+    fn visit_while(
+        &mut self,
+        label: Option<&str>,
+        condition: &Expression,
+        body: &Expression,
+        if_completed: Option<&Expression>,
+        span: Span,
+    ) {
+        self.folded = Some(Expression::While {
+            label: label.map(String::from),
+            condition: self.fold_boxed(condition),
+            body: self.fold_boxed(body),
+            if_completed: if_completed.map(|expr| self.fold_boxed(expr)),
+            span,
+        });
+    }
+
+    fn visit_repeat(
+        &mut self,
+        label: Option<&str>,
+        count: &Expression,
+        body: &Expression,
+        span: Span,
+    ) {
+        self.folded = Some(Expression::Repeat {
+            label: label.map(String::from),
+            count: self.fold_boxed(count),
+            body: self.fold_boxed(body),
+            span,
+        });
+    }
+
+    fn visit_typeswitch(&mut self, value: &Expression, arms: &[(String, Expression)], span: Span) {
+        self.folded = Some(Expression::TypeSwitch {
+            value: self.fold_boxed(value),
+            arms: arms
+                .iter()
+                .map(|(type_name, body)| (type_name.clone(), self.fold(body)))
+                .collect(),
+            span,
+        });
+    }
+
+    fn visit_slice(
+        &mut self,
+        collection: &Expression,
+        [start, end, step]: [Option<&Expression>; 3],
+        is_slice: bool,
+        span: Span,
+    ) {
+        self.folded = Some(Expression::Slice {
+            collection: self.fold_boxed(collection),
+            start: start.map(|expr| self.fold_boxed(expr)),
+            end: end.map(|expr| self.fold_boxed(expr)),
+            step: step.map(|expr| self.fold_boxed(expr)),
+            is_slice,
+            span,
+        });
+    }
+
+    fn visit_break(&mut self, label: Option<&str>, span: Span) {
+        self.folded = Some(Expression::Break {
+            label: label.map(String::from),
+            span,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{debug::format_tree, parse, tokenize};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("2 * 3 + x", "┬ Scope ─\n└─Add\n  ├─Int(6)\n  └─x")]
+    #[case("-(1 + 1) < 0 and true", "┬ Scope ─\n└─Bool(true)")]
+    #[case("1 / 0", "┬ Scope ─\n└─Div\n  ├─Int(1)\n  └─Int(0)")]
+    #[case(
+        "a = (1, 2 ^ 3)",
+        "┬ Scope ─\n└─Assign\n  ├─a\n  └─FormTuple\n    ├─Int(1)\n    └─Int(8)"
+    )]
+    #[case(
+        "func f(a) a * (2 + 2)",
+        "┬ Scope ─\n└─Assign\n  ├─f\n  └─Function f(a)\n    └─Mul\n      ├─a\n      └─Int(4)"
+    )]
+    fn test_fold_constants(#[case] code: &str, #[case] expected_tree: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(format_tree(&fold_constants(&ast)), expected_tree);
+    }
+
+    #[test]
+    fn test_folded_program_evaluates_the_same() {
+        let code = "func f(n) if (n < 2 * 1) 1 else n * f(n - (3 - 2)); f(5) + 2 ^ 2";
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            eval(
+                &fold_constants(&ast),
+                &mut HashMap::new(),
+                &mut Context::default()
+            )
+            .unwrap(),
+            eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap()
+        );
+    }
+}
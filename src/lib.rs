@@ -0,0 +1,20 @@
+pub mod bracket;
+pub mod context;
+pub mod debug;
+pub mod errors;
+pub mod fold;
+pub mod lint;
+pub mod parser;
+pub mod profile;
+pub mod repl;
+pub mod runtime;
+pub mod span;
+pub mod tokenizer;
+pub mod values;
+pub mod visitor;
+
+pub use context::Context;
+pub use parser::parse;
+pub use runtime::eval;
+pub use tokenizer::tokenize;
+pub use values::builtins::register_builtin;
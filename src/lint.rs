@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use crate::errors::format_code_context;
+use crate::parser::{BinaryOp, Expression};
+use crate::span::Span;
+use crate::values::{function::Function, Value};
+
+/// Variable that is assigned to but never read
+#[derive(Debug, PartialEq)]
+pub struct UnusedVariable {
+    pub name: String,
+    /// First assignment target
+    pub span: Span,
+}
+
+impl UnusedVariable {
+    pub fn format_with_source(&self, code: &str) -> String {
+        format!(
+            "Warning\n{} variable \"{}\" is assigned but never used",
+            format_code_context(code, self.span),
+            self.name
+        )
+    }
+}
+
+/// Finds variables assigned at the top level or in a function body and never read. Functions
+/// see their caller's variables, so a read inside any function body counts for every scope.
+/// Function parameters are not reported.
+pub fn unused_variables(program: &Expression) -> Vec<UnusedVariable> {
+    let mut scopes = vec![program];
+    collect_function_bodies(program, &mut scopes);
+
+    let mut read_in_functions = HashSet::new();
+    for body in &scopes[1..] {
+        collect_reads(body, &mut read_in_functions);
+    }
+
+    let mut unused = Vec::new();
+    for scope in scopes {
+        let mut reads = read_in_functions.clone();
+        collect_reads(scope, &mut reads);
+        let mut assignments = Vec::new();
+        collect_assignments(scope, &mut assignments);
+        let mut reported = HashSet::new();
+        for (name, span) in assignments {
+            if !reads.contains(name) && reported.insert(name) {
+                unused.push(UnusedVariable {
+                    name: name.to_string(),
+                    span,
+                });
+            }
+        }
+    }
+    unused
+}
+
+fn user_function(expr: &Expression) -> Option<&Expression> {
+    match expr {
+        Expression::Value(value, _) => match value.as_ref() {
+            Value::Function(Function::UserDefined(func)) => Some(&func.body),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn collect_function_bodies<'a>(expr: &'a Expression, bodies: &mut Vec<&'a Expression>) {
+    if let Some(body) = user_function(expr) {
+        bodies.push(body);
+        collect_function_bodies(body, bodies);
+    }
+    for subexpr in expr.subexpressions() {
+        collect_function_bodies(subexpr, bodies);
+    }
+}
+
+fn collect_reads<'a>(expr: &'a Expression, reads: &mut HashSet<&'a str>) {
+    match expr {
+        Expression::Variable(name, _) => {
+            reads.insert(name);
+        }
+        Expression::BinaryOperation {
+            op: BinaryOp::Assign,
+            left: _, // assignment targets are not reads
+            right,
+            span: _,
+        } => collect_reads(right, reads),
+        _ => {
+            for subexpr in expr.subexpressions() {
+                collect_reads(subexpr, reads);
+            }
+        }
+    }
+}
+
+fn collect_assignments<'a>(expr: &'a Expression, assignments: &mut Vec<(&'a str, Span)>) {
+    if let Expression::BinaryOperation {
+        op: BinaryOp::Assign,
+        left,
+        right,
+        span: _,
+    } = expr
+    {
+        collect_pattern_variables(left, assignments);
+        collect_assignments(right, assignments);
+    } else {
+        for subexpr in expr.subexpressions() {
+            collect_assignments(subexpr, assignments);
+        }
+    }
+}
+
+/// Variables bound by an assignment pattern, e.g. all of `a`, `b` and `c` in `a, (b, c) = ...`
+fn collect_pattern_variables<'a>(pattern: &'a Expression, variables: &mut Vec<(&'a str, Span)>) {
+    if let Expression::Variable(name, span) = pattern {
+        variables.push((name, *span));
+    }
+    for subexpr in pattern.subexpressions() {
+        collect_pattern_variables(subexpr, variables);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, tokenize};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("a = 1; 2", vec!["a"])]
+    #[case("a = 1; a", vec![])]
+    #[case("a = 1; a = 2; 3", vec!["a"])]
+    #[case("a, (b, c) = (1, (2, 3)); a + c", vec!["b"])]
+    #[case("a = b = 1; a", vec!["b"])]
+    #[case("func f(x) { y = 1; 2 }; f(1)", vec!["y"])]
+    #[case("func f(x, unused) { x }; f(1, 2)", vec![])]
+    #[case("func unused(x) { x }; 1", vec!["unused"])]
+    #[case("func fact(n) { if n < 2 { 1 } else { n * fact(n - 1) } }; fact(5)", vec![])]
+    #[case("scale = 2; func f(x) { x * scale }; f(1)", vec![])]
+    #[case("i = 0; while i < 3 { i = i + 1 }", vec![])]
+    fn test_unused_variables(#[case] code: &str, #[case] expected_names: Vec<&str>) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let names: Vec<String> = unused_variables(&ast)
+            .into_iter()
+            .map(|unused| unused.name)
+            .collect();
+        assert_eq!(names, expected_names);
+    }
+
+    #[test]
+    fn test_unused_variable_format() {
+        let code = "x = 1;\nunused = 2; x";
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let unused = unused_variables(&ast);
+        assert_eq!(
+            unused[0].format_with_source(code),
+            "Warning\n> unused = 2; x\n  ^^^^^^ variable \"unused\" is assigned but never used"
+        );
+    }
+}
@@ -1,32 +1,109 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{stdin, stdout, BufRead, IsTerminal},
+    path::PathBuf,
+    rc::Rc,
+};
 
-use crate::{
-    debug::print_tree,
-    parser::parse,
+use calculator::{
+    context::Context,
+    debug::{format_dot, print_tree, print_tree_with_lines, print_tree_with_spans},
+    errors::{explain_error_code, set_colored_errors},
+    lint::unused_variables,
+    parser::{check_brackets, parse_with_recovery},
+    profile::Profile,
+    repl,
     runtime::eval,
-    tokenizer::{tokenize, untokenize},
+    tokenizer::{insert_newline_separators, tokenize, untokenize_with_source_map},
+    values::Value,
 };
 
-mod bracket;
-mod debug;
-mod errors;
-mod parser;
-mod runtime;
-mod tokenizer;
-mod values;
-
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use itertools::Itertools;
 
 #[derive(Parser)]
-#[command(name = "calculator")]
+#[command(name = "calculator", version)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
+    /// Print the syntax tree and the result, -vv adds source spans, -vvv line numbers
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
 
-    filename: PathBuf,
+    /// Print top-level variable bindings after the program finishes
+    #[arg(long)]
+    dump_scope: bool,
+
+    /// Warn about variables that are assigned but never read
+    #[arg(long)]
+    warn_unused: bool,
+
+    /// Don't implicitly convert between integers and floats
+    #[arg(long)]
+    strict_types: bool,
+
+    /// Treat line breaks that end a statement as `;`
+    #[arg(long)]
+    newline_separators: bool,
+
+    /// Report all unmatched brackets before parsing
+    #[arg(long)]
+    check_brackets: bool,
+
+    /// Run the program once per line of stdin, available as the LINE variable; other variables
+    /// persist between lines
+    #[arg(long)]
+    per_line: bool,
+
+    /// Disable built-ins that access files, for untrusted programs
+    #[arg(long)]
+    no_io: bool,
+
+    /// Disable all side-effecting built-ins, like printing and file access
+    #[arg(long)]
+    sandbox: bool,
+
+    /// Abort when the total size of built tuples, lists and strings exceeds this many
+    /// elements and characters, counted separately for each line with --per-line
+    #[arg(long, value_name = "SIZE")]
+    memory_limit: Option<usize>,
+
+    /// Print every token with its type, lexeme and byte span instead of running the program
+    #[arg(long)]
+    dump_tokens_with_spans: bool,
+
+    /// Print the syntax tree in GraphViz DOT format instead of running the program
+    #[arg(long)]
+    ast_dot: bool,
+
+    /// Print how many times each kind of expression was evaluated
+    #[arg(long)]
+    profile: bool,
+
+    /// When to use colors in error messages
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Print a longer explanation of an error class, E0001 to E0006, and exit
+    #[arg(long, value_name = "ERROR_CODE")]
+    explain: Option<String>,
+
+    /// Don't save and load interactive mode history
+    #[arg(long)]
+    no_history: bool,
+
+    /// Program to run, interactive mode is started if omitted
+    filename: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    /// Only when printing to a terminal
+    Auto,
+    Always,
+    Never,
 }
 
 #[derive(Subcommand)]
@@ -34,13 +111,51 @@ enum Commands {
     Fmt {
         #[arg(short, long)]
         minified: bool,
+
+        /// Print formatted and original positions of every token, one per line
+        #[arg(long)]
+        source_map: bool,
     },
 }
 
 fn main() {
     let args = Cli::parse();
 
-    let code = fs::read_to_string(&args.filename).expect("Failed to read input file");
+    set_colored_errors(match args.color {
+        ColorChoice::Auto => stdout().is_terminal(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    });
+    let mut ctx = Context {
+        profile: args.profile.then(Profile::default),
+        io_enabled: !args.no_io,
+        sandbox: args.sandbox,
+        strict_types: args.strict_types,
+        memory_limit: args.memory_limit,
+        ..Default::default()
+    };
+
+    if let Some(code) = args.explain {
+        match explain_error_code(&code) {
+            Some(explanation) => println!("{}", explanation),
+            None => println!("no such error code \"{}\"", code),
+        }
+        return;
+    }
+
+    let Some(filename) = args.filename else {
+        let history_file = if args.no_history {
+            None
+        } else {
+            repl::default_history_file()
+        };
+        if let Err(e) = repl::run(history_file.as_deref(), &mut ctx) {
+            println!("Interactive mode error: {}", e);
+        }
+        return;
+    };
+
+    let code = fs::read_to_string(&filename).expect("Failed to read input file");
 
     let tokenizer_result = tokenize(&code);
     let tokens = match tokenizer_result {
@@ -48,35 +163,93 @@ fn main() {
             println!("{}", e);
             return;
         }
+        Ok(tokens) if args.newline_separators => insert_newline_separators(&code, &tokens),
         Ok(tokens) => tokens,
     };
     if args.verbose > 0 {
         println!("Tokens:\n{:?}", &tokens);
     }
+    if args.dump_tokens_with_spans {
+        for token in &tokens {
+            println!("{:?} {:?} {:?}", token.t, token.lexeme, token.span);
+        }
+        return;
+    }
 
-    if let Some(Commands::Fmt { minified }) = args.command {
-        let formatted = untokenize(&tokens, minified);
-        fs::write(&args.filename, formatted).expect("Failed to write formatted code to file");
+    if let Some(Commands::Fmt {
+        minified,
+        source_map,
+    }) = args.command
+    {
+        let (formatted, formatted_source_map) = untokenize_with_source_map(&tokens, minified);
+        if source_map {
+            for (token, formatted_span) in tokens.iter().zip(formatted_source_map.formatted_spans) {
+                println!("{:?} {:?}", formatted_span, token.span);
+            }
+        }
+        fs::write(&filename, formatted).expect("Failed to write formatted code to file");
         return;
     }
 
-    let parser_result = parse(&tokens);
+    if args.check_brackets {
+        let bracket_errors = check_brackets(&tokens);
+        if !bracket_errors.is_empty() {
+            println!("{}", bracket_errors.iter().join("\n\n"));
+            return;
+        }
+    }
+
+    let parser_result = parse_with_recovery(&tokens);
     let expression = match parser_result {
-        Err(e) => {
-            println!("{}", e);
+        Err(errors) => {
+            println!("{}", errors.iter().join("\n\n"));
             return;
         }
         Ok(exprs) => exprs,
     };
     if args.verbose > 0 {
         println!("AST:");
-        print_tree(&expression);
+        if args.verbose > 2 {
+            print_tree_with_lines(&expression, &code);
+        } else if args.verbose > 1 {
+            print_tree_with_spans(&expression);
+        } else {
+            print_tree(&expression);
+        }
     }
 
-    let eval_result = eval(&expression, &mut HashMap::new());
+    if args.ast_dot {
+        println!("{}", format_dot(&expression));
+        return;
+    }
+
+    if args.warn_unused {
+        for unused in unused_variables(&expression) {
+            eprintln!("{}", unused.format_with_source(&code));
+        }
+    }
+
+    let mut vars = HashMap::new();
+    let eval_result = if args.per_line {
+        let mut result = Ok(Rc::new(Value::Nothing));
+        for line in stdin().lock().lines().map_while(Result::ok) {
+            vars.insert("LINE".into(), Rc::new(Value::String(line)));
+            ctx.allocated = 0;
+            result = eval(&expression, &mut vars, &mut ctx);
+            if result.is_err() {
+                break;
+            }
+        }
+        result
+    } else {
+        eval(&expression, &mut vars, &mut ctx)
+    };
+    if let Some(profile) = ctx.profile {
+        println!("{}", profile);
+    }
     let result = match eval_result {
         Err(e) => {
-            println!("{}", e);
+            println!("{}", e.format_with_source(&code));
             return;
         }
         Ok(vs) => vs,
@@ -85,4 +258,10 @@ fn main() {
     if args.verbose > 0 {
         println!("Resulting value:\n{:?}", result);
     }
+
+    if args.dump_scope {
+        for (name, value) in vars.iter().sorted_by_key(|(name, _)| *name) {
+            println!("{} = {}", name, value);
+        }
+    }
 }
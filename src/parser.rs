@@ -1,12 +1,14 @@
 use crate::{
     bracket::{Bracket, BracketSide, BracketStack, BracketType},
     errors::ParserError,
+    span::Span,
     tokenizer::{Token, TokenType},
     values::{
         function::{Function, UserDefinedFunction},
-        Value,
+        Int, Value,
     },
 };
+use num_bigint::BigInt;
 use std::{cmp::min, rc::Rc};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,20 +17,40 @@ pub enum BinaryOp {
     Sub,
     Mul,
     Div,
+    /// Remainder for numbers, printf-like formatting when the left operand is a string
+    Mod,
     Pow,
     Assign,
     IsEq,
+    IsNotEq,
     IsGt,
     IsLt,
+    /// `a in b` is true if the collection `b` has an element equal to `a`, or if the string
+    /// `b` has the substring `a`
+    In,
     FunctionCall,
     FormTuple,
     AppendToTuple,
+    Coalesce,
+    /// `a ?. f` calls `f(a)`, or evaluates to nothing without evaluating `f` if `a` is nothing
+    OptionalChain,
+    /// `a |> f` calls `f(a)`, for left-to-right pipelines like `x |> exp |> log`
+    Pipe,
+    And,
+    Or,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnaryOp {
     Neg,
     Return,
+    /// `let x = 1` as a statement of a block is an assignment that's undone when the block
+    /// ends, restoring the outer `x` if there was one. Top-level and function body statements
+    /// are never undone, as those scopes end with the program or the call anyway.
+    Let,
+    /// `global x` or `global a, b` in a function makes its assignments to the variables visible
+    /// outside of the call, which otherwise works on a copy of the caller's variables
+    Global,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -37,19 +59,39 @@ enum Op {
     Binary(BinaryOp),
 }
 
-const ORDER_OF_PRECEDENCE: [Op; 13] = [
+/// Operators from the loosest to the tightest binding. Negation binds looser than power,
+/// so `-2 ^ 2 == -4`, but is still allowed as the power's right operand: `2 ^ -1 == 0.5`.
+/// Assignment and power are right-associative (`2 ^ 3 ^ 2 == 2 ^ 9`), the rest are
+/// left-associative, function calls included, so `f(a)(b)` calls the result of `f(a)`.
+/// Assignment binds looser than everything except `return`, so `x = a < b` assigns the
+/// comparison result, and evaluates to the assigned value, which makes
+/// `while (x = next()) { ... }` assign and test the condition in one step. Comparing the
+/// assigned value needs parentheses, as in `while (x = next()) != () { ... }`, otherwise `x`
+/// gets the comparison result. Operands are evaluated left to right, so the assignment happens
+/// before the comparison.
+const ORDER_OF_PRECEDENCE: [Op; 23] = [
     Op::Unary(UnaryOp::Return),
+    Op::Unary(UnaryOp::Let),
+    Op::Unary(UnaryOp::Global),
     Op::Binary(BinaryOp::Assign),
     Op::Binary(BinaryOp::FormTuple),
+    Op::Binary(BinaryOp::Pipe),
+    Op::Binary(BinaryOp::Coalesce),
+    Op::Binary(BinaryOp::Or),
+    Op::Binary(BinaryOp::And),
     Op::Binary(BinaryOp::IsEq),
+    Op::Binary(BinaryOp::IsNotEq),
     Op::Binary(BinaryOp::IsLt),
     Op::Binary(BinaryOp::IsGt),
+    Op::Binary(BinaryOp::In),
     Op::Binary(BinaryOp::Add),
     Op::Binary(BinaryOp::Sub),
     Op::Binary(BinaryOp::Mul),
     Op::Binary(BinaryOp::Div),
+    Op::Binary(BinaryOp::Mod),
     Op::Unary(UnaryOp::Neg),
     Op::Binary(BinaryOp::Pow),
+    Op::Binary(BinaryOp::OptionalChain),
     Op::Binary(BinaryOp::FunctionCall),
 ];
 
@@ -64,66 +106,290 @@ impl Op {
     }
 
     fn is_rtl(&self) -> bool {
-        *self == Op::Binary(BinaryOp::Assign) || *self == Op::Binary(BinaryOp::FunctionCall)
+        matches!(self, Op::Binary(BinaryOp::Assign | BinaryOp::Pow))
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    Value(Rc<Value>),
-    Variable(String),
+    Value(Rc<Value>, Span),
+    Variable(String, Span),
     BinaryOperation {
         op: BinaryOp,
         left: Box<Expression>,
         right: Box<Expression>,
+        span: Span,
     },
     UnaryOperation {
         op: UnaryOp,
         operand: Box<Expression>,
+        span: Span,
     },
+    /// The whole program or a `{ ... }` block. A block evaluates to its last expression (nothing
+    /// if it's empty or ends with `;`, as in `{1;}`) and shares variables with the enclosing
+    /// code, so it can be an operand: `{a = 1; a} * 2 == 2`. A `return` in a block that isn't
+    /// returnable skips the rest of the surrounding expression and unwinds to the enclosing
+    /// function body or the program.
     Scope {
         body: Vec<Expression>,
         is_returnable: bool, // = can be returned from
+        span: Span,
+    },
+    List {
+        elements: Vec<Expression>,
+        span: Span,
     },
     If {
         condition: Box<Expression>,
         if_true: Box<Expression>,
         if_false: Option<Box<Expression>>,
+        span: Span,
     },
+    /// Loops can be labeled, like `outer: while ...`, so that `break outer` in a nested loop
+    /// exits this one too
     While {
+        label: Option<String>,
         condition: Box<Expression>,
         body: Box<Expression>,
         if_completed: Option<Box<Expression>>,
+        span: Span,
+    },
+    Repeat {
+        label: Option<String>,
+        count: Box<Expression>,
+        body: Box<Expression>,
+        span: Span,
+    },
+    /// `x[i]` is the element at the index and `x[start:end:step]` is a slice of a tuple, list,
+    /// string or bytes, as in Python: negative indices count from the end, any slice part can
+    /// be omitted and a negative step goes backwards, so `x[::-1]` is reversed
+    Slice {
+        collection: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        step: Option<Box<Expression>>,
+        is_slice: bool, // = has colons, `x[i]` is an element access
+        span: Span,
+    },
+    /// `break` exits the innermost loop, `break outer` exits the loop labeled `outer` and all
+    /// loops nested in it
+    Break {
+        label: Option<String>,
+        span: Span,
+    },
+    /// `typeswitch x { integer: a, string: b, _: c }` evaluates the first arm named after the
+    /// type of `x` (as in `type(x)`), `_` matches any type. Nothing if no arm matches.
+    TypeSwitch {
+        value: Box<Expression>,
+        arms: Vec<(String, Expression)>,
+        span: Span,
     },
 }
 
+impl Expression {
+    /// Location of the expression in the source code
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Value(_, span)
+            | Expression::Variable(_, span)
+            | Expression::BinaryOperation { span, .. }
+            | Expression::UnaryOperation { span, .. }
+            | Expression::Scope { span, .. }
+            | Expression::List { span, .. }
+            | Expression::If { span, .. }
+            | Expression::While { span, .. }
+            | Expression::Repeat { span, .. }
+            | Expression::TypeSwitch { span, .. }
+            | Expression::Slice { span, .. }
+            | Expression::Break { span, .. } => *span,
+        }
+    }
+
+    /// Direct child expressions, not including bodies of function values
+    pub fn subexpressions(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Value(..) | Expression::Variable(..) | Expression::Break { .. } => {
+                Vec::new()
+            }
+            Expression::BinaryOperation { left, right, .. } => vec![left, right],
+            Expression::UnaryOperation { operand, .. } => vec![operand],
+            Expression::Scope { body, .. } => body.iter().collect(),
+            Expression::List { elements, .. } => elements.iter().collect(),
+            Expression::If {
+                condition,
+                if_true,
+                if_false,
+                ..
+            } => [Some(condition), Some(if_true), if_false.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|e| e.as_ref())
+                .collect(),
+            Expression::While {
+                condition,
+                body,
+                if_completed,
+                ..
+            } => [Some(condition), Some(body), if_completed.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|e| e.as_ref())
+                .collect(),
+            Expression::Repeat { count, body, .. } => vec![count, body],
+            Expression::TypeSwitch { value, arms, .. } => [value.as_ref()]
+                .into_iter()
+                .chain(arms.iter().map(|(_, body)| body))
+                .collect(),
+            Expression::Slice {
+                collection,
+                start,
+                end,
+                step,
+                ..
+            } => [
+                Some(collection),
+                start.as_ref(),
+                end.as_ref(),
+                step.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|e| e.as_ref())
+            .collect(),
+        }
+    }
+}
+
 pub fn parse<'a>(tokens: &'a [Token<'a>]) -> Result<Expression, ParserError<'a>> {
     parse_scope(tokens, true)
 }
 
+/// Like `parse`, but after an error skips to the end of the failing top-level expression and
+/// goes on, so that errors in independent expressions are all reported at once
+pub fn parse_with_recovery<'a>(
+    tokens: &'a [Token<'a>],
+) -> Result<Expression, Vec<ParserError<'a>>> {
+    parse_scope_collecting_errors(tokens, true, true)
+}
+
 pub fn parse_scope<'a>(
     tokens: &'a [Token<'a>],
     is_returnable: bool,
 ) -> Result<Expression, ParserError<'a>> {
+    parse_scope_collecting_errors(tokens, is_returnable, false)
+        .map_err(|mut errors| errors.swap_remove(0))
+}
+
+/// Every unmatched closing bracket and unclosed opening bracket in the tokens. Unlike parsing,
+/// doesn't stop at the first one, so it's useful as a quick preflight for large programs
+pub fn check_brackets<'a>(tokens: &'a [Token<'a>]) -> Vec<ParserError<'a>> {
+    let mut bracket_stack = BracketStack::new();
+    let mut opening_idxs = Vec::new();
+    let mut errors = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let TokenType::Bracket(bracket) = token.t else {
+            continue;
+        };
+        match bracket_stack.update(bracket) {
+            Ok(()) if bracket.side == BracketSide::Opening => opening_idxs.push(i),
+            Ok(()) => {
+                opening_idxs.pop();
+            }
+            Err(errmsg) => errors.push(ParserError {
+                tokens,
+                errmsg,
+                error_token_idx: i,
+            }),
+        }
+    }
+    errors.extend(opening_idxs.into_iter().map(|i| ParserError {
+        tokens,
+        errmsg: "unclosed bracket".into(),
+        error_token_idx: i,
+    }));
+    errors.sort_by_key(|e| e.error_token_idx);
+    errors
+}
+
+/// Without `recover`, stops at the first error
+fn parse_scope_collecting_errors<'a>(
+    tokens: &'a [Token<'a>],
+    is_returnable: bool,
+    recover: bool,
+) -> Result<Expression, Vec<ParserError<'a>>> {
     let mut body: Vec<Expression> = Vec::new();
+    let mut errors = Vec::new();
+    // a trailing `;` is followed by an empty statement, so the scope evaluates to nothing
+    let ends_with_separator = tokens.last().is_some_and(|tok| tok.t == TokenType::ExprEnd);
     let mut i = 0;
-    while i < tokens.len() {
-        let expr: Expression;
-        (expr, i) = consume_expression(tokens, i, None, false)?;
+    while i < tokens.len() || (i == tokens.len() && ends_with_separator) {
+        match consume_expression(tokens, i, None, Termination::ExprEnd) {
+            Ok((expr, end)) => {
+                body.push(expr);
+                i = end;
+            }
+            Err(error) => {
+                errors.push(error);
+                if !recover {
+                    break;
+                }
+                i = find_expression_end(tokens, i);
+            }
+        }
         i += 1; // skipping expression end
-        body.push(expr);
     }
-    return Ok(Expression::Scope {
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let span = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => Span::new(first.span.start, last.span.end),
+        _ => Span::empty_at(0),
+    };
+    Ok(Expression::Scope {
         body,
         is_returnable,
-    });
+        span,
+    })
+}
+
+/// Index of the `ExprEnd` token finishing the expression started at `start`, ignoring the ones
+/// nested in brackets. If a bracket is never closed, the first `ExprEnd` is taken instead, and
+/// the number of tokens if there's none at all.
+fn find_expression_end(tokens: &[Token], start: usize) -> usize {
+    let mut bracket_stack = BracketStack::new();
+    let mut first_expr_end = None;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token.t {
+            TokenType::ExprEnd if bracket_stack.is_empty() => return i,
+            TokenType::ExprEnd => {
+                first_expr_end.get_or_insert(i);
+            }
+            // unmatched brackets are already reported, they only need to be skipped
+            TokenType::Bracket(bracket) => bracket_stack.update(bracket).unwrap_or_default(),
+            _ => {}
+        }
+    }
+    first_expr_end.unwrap_or(tokens.len())
+}
+
+/// Where `consume_expression` stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Termination {
+    /// At `;` or the end of the tokens, any other token that can't continue the expression
+    /// is an error
+    ExprEnd,
+    /// Also before a token that can't continue the expression, like the `else` after a body
+    UnexpectedToken,
+    /// Also before `[`, for an `if` or `while` condition, a `repeat` count or a `func`
+    /// declaration: `if (a) [1] else [2]` has a list literal body, it doesn't index `(a)`
+    Header,
 }
 
 fn consume_expression<'a>(
     tokens: &'a [Token<'a>],
     i: usize,
     outer_op: Option<Op>,
-    terminate_on_unexpected_token: bool,
+    termination: Termination,
 ) -> Result<(Expression, usize), ParserError<'a>> {
     let mut result: Option<Expression> = None;
     let mut left: Option<Expression>;
@@ -142,16 +408,49 @@ fn consume_expression<'a>(
             if i >= tokens.len() || tokens[i].t == TokenType::ExprEnd {
                 return Ok((left, min(i, tokens.len())));
             }
+            // `x[i]` and `x[a:b]` are postfix and bind like a function call, so `f(x)[0]`
+            // indexes the call result
+            if tokens[i].t
+                == TokenType::Bracket(Bracket {
+                    type_: BracketType::Square,
+                    side: BracketSide::Opening,
+                })
+            {
+                if outer_op == Some(Op::Binary(BinaryOp::FunctionCall))
+                    || termination == Termination::Header
+                {
+                    return Ok((left, i));
+                }
+                let j = bracketed_group_end(tokens, i)?;
+                result = Some(parse_slice(left, tokens, i, j)?);
+                i = j;
+                continue;
+            }
             let next_binary_op = match tokens[i].t {
                 TokenType::Plus => BinaryOp::Add,
                 TokenType::Minus => BinaryOp::Sub,
                 TokenType::Star => BinaryOp::Mul,
                 TokenType::Slash => BinaryOp::Div,
+                TokenType::Percent => BinaryOp::Mod,
+                // `mod` is a regular identifier, so `mod(7, 3)` still calls the builtin,
+                // but right after an operand it's read as an operator: `7 mod 3`
+                TokenType::Identifier if tokens[i].lexeme == "mod" => BinaryOp::Mod,
                 TokenType::Caret => BinaryOp::Pow,
                 TokenType::Equals => BinaryOp::Assign,
                 TokenType::DoubleEquals => BinaryOp::IsEq,
+                TokenType::BangEquals => BinaryOp::IsNotEq,
                 TokenType::LeftAngle => BinaryOp::IsLt,
                 TokenType::RightAngle => BinaryOp::IsGt,
+                TokenType::DoubleQuestion => BinaryOp::Coalesce,
+                TokenType::QuestionDot => BinaryOp::OptionalChain,
+                TokenType::Pipe => BinaryOp::Pipe,
+                TokenType::And => BinaryOp::And,
+                TokenType::Or => BinaryOp::Or,
+                TokenType::In => BinaryOp::In,
+                // `a not in b` is parsed as a negated `a in b`
+                TokenType::Not if tokens.get(i + 1).map(|tok| tok.t) == Some(TokenType::In) => {
+                    BinaryOp::In
+                }
                 TokenType::Comma => {
                     let mut repeating_comma_op = None;
                     if let Some(prev_op) = prev_op {
@@ -168,18 +467,34 @@ fn consume_expression<'a>(
                     side: BracketSide::Opening,
                 }) => BinaryOp::FunctionCall,
                 _ => {
-                    if terminate_on_unexpected_token {
+                    if termination != Termination::ExprEnd {
                         return Ok((left, i));
                     }
                     return Err(ParserError {
-                        tokens: tokens,
+                        tokens,
                         errmsg: "expression end or binary operator expected here".into(),
                         error_token_idx: i,
                     });
                 }
             };
+            // compound assignment `a and= b` is parsed as `a = a and b`
+            let compound_op = if matches!(next_binary_op, BinaryOp::And | BinaryOp::Or)
+                && tokens.get(i + 1).map(|tok| tok.t) == Some(TokenType::Equals)
+            {
+                Some(next_binary_op)
+            } else {
+                None
+            };
+            let next_binary_op = if compound_op.is_some() {
+                BinaryOp::Assign
+            } else {
+                next_binary_op
+            };
+            let is_negated = tokens[i].t == TokenType::Not;
             let next_op_token_count: usize = if next_binary_op == BinaryOp::FunctionCall {
                 0
+            } else if compound_op.is_some() || is_negated {
+                2
             } else {
                 1
             };
@@ -190,44 +505,85 @@ fn consume_expression<'a>(
                 }
             }
             prev_op = Some(op);
-            let right: Expression;
-            (right, i) = consume_expression(
-                tokens,
-                i + next_op_token_count,
-                Some(op),
-                terminate_on_unexpected_token,
-            )?;
-            result = Some(Expression::BinaryOperation {
+            let mut right: Expression;
+            (right, i) =
+                consume_expression(tokens, i + next_op_token_count, Some(op), termination)?;
+            if let Some(compound_op) = compound_op {
+                right = Expression::BinaryOperation {
+                    op: compound_op,
+                    span: Span::new(left.span().start, consumed_until(tokens, i)),
+                    left: Box::new(left.clone()),
+                    right: Box::new(right),
+                };
+            }
+            let span = Span::new(left.span().start, consumed_until(tokens, i));
+            let mut operation = Expression::BinaryOperation {
                 op: next_binary_op,
+                span,
                 left: Box::new(left),
                 right: Box::new(right),
-            });
+            };
+            if is_negated {
+                operation = Expression::UnaryOperation {
+                    op: UnaryOp::Neg,
+                    operand: Box::new(operation),
+                    span,
+                };
+            }
+            result = Some(operation);
         } else {
             if i >= tokens.len() || tokens[i].t == TokenType::ExprEnd {
-                return Ok((Expression::Value(Rc::new(Value::Nothing)), i));
+                return Ok((
+                    Expression::Value(Rc::new(Value::Nothing), empty_span_at(tokens, i)),
+                    i,
+                ));
             }
             let next_unary_op = match tokens[i].t {
                 TokenType::Minus => UnaryOp::Neg,
                 TokenType::Bang => UnaryOp::Neg,
                 TokenType::Return => UnaryOp::Return,
+                // same as `!`, so it binds tighter than comparisons: `not a == b` is `(not a) == b`
+                TokenType::Not => UnaryOp::Neg,
+                TokenType::Let => UnaryOp::Let,
+                TokenType::Global => UnaryOp::Global,
                 _ => {
                     return Err(ParserError {
-                        tokens: tokens,
+                        tokens,
                         errmsg: "operand or unary operator expected here".into(),
                         error_token_idx: i,
                     })
                 }
             };
+            let op_idx = i;
+            let op_start = tokens[i].span.start;
             let operand: Expression;
-            (operand, i) = consume_expression(
-                tokens,
-                i + 1,
-                Some(Op::Unary(next_unary_op)),
-                terminate_on_unexpected_token,
-            )?;
+            (operand, i) =
+                consume_expression(tokens, i + 1, Some(Op::Unary(next_unary_op)), termination)?;
+            let is_assignment = matches!(
+                operand,
+                Expression::BinaryOperation {
+                    op: BinaryOp::Assign,
+                    ..
+                }
+            );
+            if next_unary_op == UnaryOp::Let && !is_assignment {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: "assignment expected after \"let\"".into(),
+                    error_token_idx: op_idx,
+                });
+            }
+            if next_unary_op == UnaryOp::Global && !is_variable_list(&operand) {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: "variable names expected after \"global\"".into(),
+                    error_token_idx: op_idx,
+                });
+            }
             result = Some(Expression::UnaryOperation {
                 op: next_unary_op,
                 operand: Box::new(operand),
+                span: Span::new(op_start, consumed_until(tokens, i)),
             })
         }
     }
@@ -239,7 +595,7 @@ fn consume_operand<'a>(
 ) -> Result<(Option<Expression>, usize), ParserError<'a>> {
     let advance_if_type = |idx: usize, t: TokenType| {
         if idx < tokens.len() && tokens[idx].t == t {
-            return idx + 1;
+            idx + 1
         } else {
             idx
         }
@@ -253,84 +609,134 @@ fn consume_operand<'a>(
         TokenType::ExprEnd => Ok((None, i)),
         TokenType::Number => {
             let includes_dot = next.lexeme.chars().find(|&ch| ch == '.').is_some();
-            let value = if includes_dot {
+            let value = if let Some(imaginary) = next.lexeme.strip_suffix('i') {
+                if let Ok(im) = imaginary.parse::<f32>() {
+                    Value::Complex { re: 0.0, im }
+                } else {
+                    return Err(ParserError {
+                        tokens,
+                        errmsg: "not a valid imaginary number".into(),
+                        error_token_idx: i,
+                    });
+                }
+            } else if includes_dot {
                 if let Ok(f) = next.lexeme.parse::<f32>() {
                     Value::Float(f)
                 } else {
                     return Err(ParserError {
-                        tokens: tokens,
+                        tokens,
                         errmsg: "not a valid floating point number".into(),
                         error_token_idx: i,
                     });
                 }
             } else {
-                if let Ok(i) = next.lexeme.parse::<i32>() {
+                if let Ok(i) = next.lexeme.parse::<Int>() {
                     Value::Int(i)
+                } else if let Ok(bi) = next.lexeme.parse::<BigInt>() {
+                    Value::BigInt(bi)
                 } else {
                     return Err(ParserError {
-                        tokens: tokens,
+                        tokens,
                         errmsg: "not a valid integer".into(),
                         error_token_idx: i,
                     });
                 }
             };
-            return Ok((Some(Expression::Value(Rc::new(value))), i + 1));
+            Ok((Some(Expression::Value(Rc::new(value), next.span)), i + 1))
         }
-        TokenType::StringLiteral => Ok((
-            Some(Expression::Value(Rc::new(Value::String(
-                next.lexeme[1..next.lexeme.len() - 1].into(),
-            )))),
+        TokenType::StringLiteral => match unescape(&next.lexeme[1..next.lexeme.len() - 1], 1) {
+            Ok(s) => Ok((
+                Some(Expression::Value(Rc::new(Value::String(s)), next.span)),
+                i + 1,
+            )),
+            Err(errmsg) => Err(ParserError {
+                tokens,
+                errmsg,
+                error_token_idx: i,
+            }),
+        },
+        TokenType::BytesLiteral => match unescape_bytes(&next.lexeme[2..next.lexeme.len() - 1]) {
+            Ok(bytes) => Ok((
+                Some(Expression::Value(Rc::new(Value::Bytes(bytes)), next.span)),
+                i + 1,
+            )),
+            Err(errmsg) => Err(ParserError {
+                tokens,
+                errmsg,
+                error_token_idx: i,
+            }),
+        },
+        TokenType::BoolLiteral => Ok((
+            Some(Expression::Value(
+                Rc::new(Value::Bool(next.lexeme.to_lowercase() == "true")),
+                next.span,
+            )),
             i + 1,
         )),
-        TokenType::BoolLiteral => Ok((
-            Some(Expression::Value(Rc::new(Value::Bool(
-                next.lexeme.to_lowercase() == "true",
-            )))),
+        TokenType::Identifier
+            if tokens.get(i + 1).map(|tok| tok.t) == Some(TokenType::Colon)
+                && matches!(
+                    tokens.get(i + 2).map(|tok| tok.t),
+                    Some(TokenType::While | TokenType::Repeat)
+                ) =>
+        {
+            let (mut loop_expr, j) = consume_operand(tokens, i + 2)?;
+            match &mut loop_expr {
+                Some(Expression::While { label, span, .. })
+                | Some(Expression::Repeat { label, span, .. }) => {
+                    *label = Some(next.lexeme.to_owned());
+                    *span = Span::new(next.span.start, span.end);
+                }
+                _ => unreachable!("while and repeat tokens always start a loop"),
+            }
+            Ok((loop_expr, j))
+        }
+        TokenType::Identifier => Ok((
+            Some(Expression::Variable(next.lexeme.to_owned(), next.span)),
             i + 1,
         )),
-        TokenType::Identifier => Ok((Some(Expression::Variable(next.lexeme.to_owned())), i + 1)),
+        TokenType::Break => {
+            let label = tokens
+                .get(i + 1)
+                .filter(|tok| tok.t == TokenType::Identifier);
+            let end = label.map_or(next.span.end, |tok| tok.span.end);
+            Ok((
+                Some(Expression::Break {
+                    label: label.map(|tok| tok.lexeme.to_owned()),
+                    span: Span::new(next.span.start, end),
+                }),
+                if label.is_some() { i + 2 } else { i + 1 },
+            ))
+        }
         TokenType::Bracket(Bracket {
             type_: bracket_type,
             side: BracketSide::Opening,
         }) => {
-            let mut bracket_stack = BracketStack::new();
-            bracket_stack
-                .update(Bracket {
-                    type_: bracket_type,
-                    side: BracketSide::Opening,
-                })
-                .unwrap();
-            let mut j = i + 1;
-            while j < tokens.len() && !bracket_stack.is_empty() {
-                let tt = &tokens[j].t;
-                if let TokenType::Bracket(b) = tt {
-                    if let Err(update_errmsg) = bracket_stack.update(*b) {
-                        return Err(ParserError {
-                            tokens: tokens,
-                            errmsg: update_errmsg,
-                            error_token_idx: j,
-                        });
-                    }
-                }
-                j += 1;
-            }
-            if !bracket_stack.is_empty() {
-                return Err(ParserError {
-                    tokens: tokens,
-                    errmsg: "unclosed bracket".into(),
-                    error_token_idx: i,
-                });
-            }
-
+            let j = bracketed_group_end(tokens, i)?;
             let bracketed_tokens = &tokens[i + 1..j - 1];
-            if bracketed_tokens.len() == 0 {
-                return Ok((Some(Expression::Value(Rc::new(Value::Nothing))), j));
+            if bracket_type == BracketType::Square {
+                return Ok((
+                    Some(Expression::List {
+                        elements: parse_list_elements(bracketed_tokens)?,
+                        span: Span::new(next.span.start, tokens[j - 1].span.end),
+                    }),
+                    j,
+                ));
+            }
+            if bracketed_tokens.is_empty() {
+                return Ok((
+                    Some(Expression::Value(
+                        Rc::new(Value::Nothing),
+                        Span::new(next.span.start, tokens[j - 1].span.end),
+                    )),
+                    j,
+                ));
             }
 
             let bracketed_expr = match bracket_type {
                 BracketType::Round => {
                     let (expr, last_expr_token_offset_idx) =
-                        consume_expression(bracketed_tokens, 0, None, false)?;
+                        consume_expression(bracketed_tokens, 0, None, Termination::ExprEnd)?;
                     if last_expr_token_offset_idx < bracketed_tokens.len() - 1 {
                         return Err(ParserError {
                             tokens: bracketed_tokens,
@@ -341,58 +747,114 @@ fn consume_operand<'a>(
                     expr
                 }
                 BracketType::Curly => parse_scope(bracketed_tokens, false)?,
+                BracketType::Square => unreachable!("list literals are parsed above"),
             };
-            return Ok((Some(bracketed_expr), j));
+            Ok((Some(bracketed_expr), j))
         }
         t if t == TokenType::If || t == TokenType::While => {
             let mut j = i + 1;
             let condition: Expression;
-            (condition, j) = consume_expression(tokens, j, None, true)?;
+            (condition, j) = consume_expression(tokens, j, None, Termination::Header)?;
             if tokens[j].t == TokenType::ExprEnd {
                 j += 1;
             }
             let body: Expression;
-            (body, j) = consume_expression(tokens, j, None, true)?;
+            (body, j) = consume_expression(tokens, j, None, Termination::UnexpectedToken)?;
 
             let possible_else_idx = advance_if_type(j, TokenType::ExprEnd);
             let possible_else_body_start_idx = advance_if_type(possible_else_idx, TokenType::Else);
             let body_after_else = if possible_else_body_start_idx > possible_else_idx {
                 let expr: Expression;
-                (expr, j) = consume_expression(tokens, possible_else_body_start_idx, None, false)?;
+                (expr, j) = consume_expression(
+                    tokens,
+                    possible_else_body_start_idx,
+                    None,
+                    Termination::ExprEnd,
+                )?;
                 Some(Box::new(expr))
             } else {
                 None
             };
+            let span = Span::new(next.span.start, consumed_until(tokens, j));
             let res = if t == TokenType::If {
                 Expression::If {
                     condition: Box::new(condition),
                     if_true: Box::new(body),
                     if_false: body_after_else,
+                    span,
                 }
             } else {
                 Expression::While {
+                    label: None,
                     condition: Box::new(condition),
                     body: Box::new(body),
                     if_completed: body_after_else,
+                    span,
                 }
             };
             Ok((Some(res), j))
         }
+        TokenType::Repeat => {
+            let mut j = i + 1;
+            let count: Expression;
+            (count, j) = consume_expression(tokens, j, None, Termination::Header)?;
+            j = advance_if_type(j, TokenType::ExprEnd);
+            let body: Expression;
+            (body, j) = consume_expression(tokens, j, None, Termination::UnexpectedToken)?;
+            Ok((
+                Some(Expression::Repeat {
+                    label: None,
+                    count: Box::new(count),
+                    body: Box::new(body),
+                    span: Span::new(next.span.start, consumed_until(tokens, j)),
+                }),
+                j,
+            ))
+        }
+        TokenType::TypeSwitch => {
+            let mut j = i + 1;
+            let value: Expression;
+            (value, j) = consume_expression(tokens, j, None, Termination::UnexpectedToken)?;
+            j = advance_if_type(j, TokenType::ExprEnd);
+            let is_block_start = tokens.get(j).map(|tok| tok.t)
+                == Some(TokenType::Bracket(Bracket {
+                    type_: BracketType::Curly,
+                    side: BracketSide::Opening,
+                }));
+            if !is_block_start {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: "typeswitch arms in curly brackets expected here".into(),
+                    error_token_idx: min(j, tokens.len() - 1),
+                });
+            }
+            let arms_start = j;
+            j = bracketed_group_end(tokens, arms_start)?;
+            Ok((
+                Some(Expression::TypeSwitch {
+                    value: Box::new(value),
+                    arms: parse_typeswitch_arms(&tokens[arms_start + 1..j - 1])?,
+                    span: Span::new(next.span.start, consumed_until(tokens, j)),
+                }),
+                j,
+            ))
+        }
         TokenType::Func => {
             let mut j = i + 1;
             let func_declaration_expr: Expression;
-            (func_declaration_expr, j) = consume_expression(tokens, j, None, true)?;
-            let (func_name, func_params) = if let Expression::BinaryOperation {
+            (func_declaration_expr, j) = consume_expression(tokens, j, None, Termination::Header)?;
+            let (func_name, func_name_span, func_params) = if let Expression::BinaryOperation {
                 op: BinaryOp::FunctionCall,
                 left,
                 right,
+                span: _,
             } = func_declaration_expr
             {
-                if let Expression::Variable(func_name) = left.clone().as_ref() {
-                    (func_name.clone(), *right.clone())
+                if let Expression::Variable(func_name, func_name_span) = left.as_ref() {
+                    (func_name.clone(), *func_name_span, *right)
                 } else {
                     return Err(ParserError {
-                        tokens: tokens,
+                        tokens,
                         errmsg: "functon name expected here".into(),
                         error_token_idx: i + 1,
                     });
@@ -408,36 +870,345 @@ fn consume_operand<'a>(
             j = advance_if_type(j, TokenType::ExprEnd);
 
             let mut func_body: Expression;
-            (func_body, j) = consume_expression(tokens, j, None, false)?;
+            (func_body, j) = consume_expression(tokens, j, None, Termination::ExprEnd)?;
             func_body = match func_body {
                 Expression::Scope {
                     body,
                     is_returnable: _,
+                    span,
                 } => Expression::Scope {
-                    body: body,
+                    body,
                     is_returnable: true,
+                    span,
                 },
                 other => other,
             };
-            return Ok((
+            let span = Span::new(next.span.start, consumed_until(tokens, j));
+            Ok((
                 Some(Expression::BinaryOperation {
                     op: BinaryOp::Assign,
-                    left: Box::new(Expression::Variable(func_name.clone())),
-                    right: Box::new(Expression::Value(Rc::new(Value::Function(
-                        Function::UserDefined(UserDefinedFunction {
-                            name: func_name,
-                            params: func_params.clone(),
-                            body: func_body,
-                        }),
-                    )))),
+                    left: Box::new(Expression::Variable(func_name.clone(), func_name_span)),
+                    right: Box::new(Expression::Value(
+                        Rc::new(Value::Function(Function::UserDefined(
+                            UserDefinedFunction {
+                                name: func_name,
+                                params: func_params,
+                                body: func_body,
+                            },
+                        ))),
+                        span,
+                    )),
+                    span,
                 }),
                 j,
-            ));
+            ))
         }
         _ => Ok((None, i)),
     }
 }
 
+/// Decodes escape sequences in a string literal body: `\n`, `\t`, `\\`, `\"`,
+/// `\xHH` and `\u{H..}` (one to six hex digits, must be a valid code point). A backslash
+/// before any other character is kept as is, so `"C:\dir"` needs no escaping. Errors point at
+/// the escape by its offset in the literal, the body starts at `body_offset` characters into it.
+fn unescape(raw: &str, body_offset: usize) -> Result<String, String> {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            decoded.push(ch);
+            continue;
+        }
+        let escape_offset =
+            body_offset + raw[..raw.len() - chars.as_str().len() - 1].chars().count();
+        let with_offset = |errmsg: String| format!("{} at offset {}", errmsg, escape_offset);
+        let escape = chars
+            .next()
+            .ok_or_else(|| with_offset("unfinished escape sequence".into()))?;
+        let code_point = match escape {
+            'n' => '\n' as u32,
+            't' => '\t' as u32,
+            '\\' | '"' => escape as u32,
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                parse_hex(&hex)
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| with_offset(format!("invalid escape sequence \"\\x{}\"", hex)))?
+            }
+            'u' => {
+                let rest = chars.as_str();
+                let hex = rest
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .map(|(hex, _)| hex)
+                    .filter(|hex| (1..=6).contains(&hex.len()))
+                    .ok_or_else(|| {
+                        with_offset("invalid escape sequence \"\\u\", expected \\u{...}".into())
+                    })?;
+                let code_point = parse_hex(hex).ok_or_else(|| {
+                    with_offset(format!("invalid escape sequence \"\\u{{{}}}\"", hex))
+                })?;
+                chars = rest[hex.len() + 2..].chars();
+                code_point
+            }
+            other => {
+                decoded.push('\\');
+                decoded.push(other);
+                continue;
+            }
+        };
+        decoded.push(char::from_u32(code_point).ok_or_else(|| {
+            with_offset(format!(
+                "invalid code point {:#x} in escape sequence",
+                code_point
+            ))
+        })?);
+    }
+    Ok(decoded)
+}
+
+/// Same escapes as in string literals, with every character limited to a single byte
+fn unescape_bytes(raw: &str) -> Result<Vec<u8>, String> {
+    if !raw.is_ascii() {
+        return Err("bytes literal can only contain ASCII characters".into());
+    }
+    unescape(raw, 2)?
+        .chars()
+        .map(|ch| {
+            u8::try_from(ch)
+                .map_err(|_| format!("code point {:#x} doesn't fit in a byte", u32::from(ch)))
+        })
+        .collect()
+}
+
+fn parse_hex(digits: &str) -> Option<u32> {
+    if digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        u32::from_str_radix(digits, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// Index right after the bracket closing the opening one at `i`
+fn bracketed_group_end<'a>(tokens: &'a [Token<'a>], i: usize) -> Result<usize, ParserError<'a>> {
+    let mut bracket_stack = BracketStack::new();
+    let mut j = i;
+    while j < tokens.len() {
+        if let TokenType::Bracket(b) = tokens[j].t {
+            if let Err(update_errmsg) = bracket_stack.update(b) {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: update_errmsg,
+                    error_token_idx: j,
+                });
+            }
+        }
+        j += 1;
+        if bracket_stack.is_empty() {
+            return Ok(j);
+        }
+    }
+    Err(ParserError {
+        tokens,
+        errmsg: "unclosed bracket".into(),
+        error_token_idx: i,
+    })
+}
+
+/// `a` or `a, b, c`
+fn is_variable_list(expr: &Expression) -> bool {
+    match expr {
+        Expression::Variable(..) => true,
+        Expression::BinaryOperation {
+            op: BinaryOp::FormTuple | BinaryOp::AppendToTuple,
+            left,
+            right,
+            ..
+        } => is_variable_list(left) && is_variable_list(right),
+        _ => false,
+    }
+}
+
+/// Index or slice in the square brackets from `open_idx` up to `end_idx`, with the slice
+/// parts separated by colons outside of nested brackets
+fn parse_slice<'a>(
+    collection: Expression,
+    tokens: &'a [Token<'a>],
+    open_idx: usize,
+    end_idx: usize,
+) -> Result<Expression, ParserError<'a>> {
+    let bracketed_tokens = &tokens[open_idx + 1..end_idx - 1];
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut part_start = 0;
+    for (idx, tok) in bracketed_tokens.iter().enumerate() {
+        match tok.t {
+            TokenType::Bracket(Bracket {
+                side: BracketSide::Opening,
+                ..
+            }) => depth += 1,
+            TokenType::Bracket(Bracket {
+                side: BracketSide::Closing,
+                ..
+            }) => depth -= 1,
+            TokenType::Colon if depth == 0 => {
+                parts.push(&bracketed_tokens[part_start..idx]);
+                part_start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&bracketed_tokens[part_start..]);
+    if parts.len() > 3 {
+        return Err(ParserError {
+            tokens,
+            errmsg: "slice has at most three parts: start, end and step".into(),
+            error_token_idx: open_idx,
+        });
+    }
+    let mut parsed_parts = Vec::new();
+    for part in parts.iter() {
+        if part.is_empty() {
+            parsed_parts.push(None);
+            continue;
+        }
+        let (expr, last_expr_token_offset_idx) =
+            consume_expression(part, 0, None, Termination::ExprEnd)?;
+        if last_expr_token_offset_idx < part.len() {
+            return Err(ParserError {
+                tokens: part,
+                errmsg: "slice parts must be single expressions".into(),
+                error_token_idx: last_expr_token_offset_idx,
+            });
+        }
+        parsed_parts.push(Some(Box::new(expr)));
+    }
+    let is_slice = parsed_parts.len() > 1;
+    if !is_slice && parsed_parts[0].is_none() {
+        return Err(ParserError {
+            tokens,
+            errmsg: "index or slice expected in the square brackets".into(),
+            error_token_idx: open_idx,
+        });
+    }
+    parsed_parts.resize(3, None);
+    let step = parsed_parts.pop().flatten();
+    let end = parsed_parts.pop().flatten();
+    let start = parsed_parts.pop().flatten();
+    Ok(Expression::Slice {
+        span: Span::new(collection.span().start, tokens[end_idx - 1].span.end),
+        collection: Box::new(collection),
+        start,
+        end,
+        step,
+        is_slice,
+    })
+}
+
+/// Comma-separated elements of a list literal, a trailing comma is allowed
+fn parse_list_elements<'a>(tokens: &'a [Token<'a>]) -> Result<Vec<Expression>, ParserError<'a>> {
+    let mut elements = Vec::new();
+    let mut i = skip_comments(tokens, 0);
+    while i < tokens.len() {
+        let element: Expression;
+        (element, i) = consume_expression(
+            tokens,
+            i,
+            Some(Op::Binary(BinaryOp::FormTuple)),
+            Termination::ExprEnd,
+        )?;
+        elements.push(element);
+        i = skip_comments(tokens, i);
+        if i < tokens.len() {
+            if tokens[i].t != TokenType::Comma {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: "list elements must be separated by commas".into(),
+                    error_token_idx: i,
+                });
+            }
+            i = skip_comments(tokens, i + 1);
+        }
+    }
+    Ok(elements)
+}
+
+/// Comma-separated `type name: expression` arms of a typeswitch, a trailing comma is allowed.
+/// Type names with spaces, like "built-in function", are written as string literals.
+fn parse_typeswitch_arms<'a>(
+    tokens: &'a [Token<'a>],
+) -> Result<Vec<(String, Expression)>, ParserError<'a>> {
+    let mut arms = Vec::new();
+    let mut i = skip_comments(tokens, 0);
+    while i < tokens.len() {
+        let type_name = match tokens[i].t {
+            TokenType::Identifier => tokens[i].lexeme.to_owned(),
+            TokenType::StringLiteral => {
+                let lexeme = tokens[i].lexeme;
+                unescape(&lexeme[1..lexeme.len() - 1], 1).map_err(|errmsg| ParserError {
+                    tokens,
+                    errmsg,
+                    error_token_idx: i,
+                })?
+            }
+            _ => {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: "type name expected here".into(),
+                    error_token_idx: i,
+                })
+            }
+        };
+        i = skip_comments(tokens, i + 1);
+        if tokens.get(i).map(|tok| tok.t) != Some(TokenType::Colon) {
+            return Err(ParserError {
+                tokens,
+                errmsg: "\":\" expected after the type name".into(),
+                error_token_idx: min(i, tokens.len() - 1),
+            });
+        }
+        let body: Expression;
+        (body, i) = consume_expression(
+            tokens,
+            i + 1,
+            Some(Op::Binary(BinaryOp::FormTuple)),
+            Termination::UnexpectedToken,
+        )?;
+        arms.push((type_name, body));
+        i = skip_comments(tokens, i);
+        if i < tokens.len() {
+            if tokens[i].t != TokenType::Comma {
+                return Err(ParserError {
+                    tokens,
+                    errmsg: "typeswitch arms must be separated by commas".into(),
+                    error_token_idx: i,
+                });
+            }
+            i = skip_comments(tokens, i + 1);
+        }
+    }
+    Ok(arms)
+}
+
+/// Source position right after the last non-comment token before `i`
+fn consumed_until(tokens: &[Token], i: usize) -> usize {
+    tokens[..min(i, tokens.len())]
+        .iter()
+        .rev()
+        .find(|tok| tok.t != TokenType::Comment)
+        .map(|tok| tok.span.end)
+        .unwrap_or(0)
+}
+
+/// Zero-length span where the token `i` starts (or where the last token ends)
+fn empty_span_at(tokens: &[Token], i: usize) -> Span {
+    if let Some(tok) = tokens.get(i) {
+        Span::empty_at(tok.span.start)
+    } else {
+        Span::empty_at(tokens.last().map(|tok| tok.span.end).unwrap_or(0))
+    }
+}
+
 fn skip_comments(tokens: &[Token], i: usize) -> usize {
     let mut i = i;
     while i < tokens.len() && tokens[i].t == TokenType::Comment {
@@ -445,3 +1216,194 @@ fn skip_comments(tokens: &[Token], i: usize) -> usize {
     }
     i
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize;
+
+    #[test]
+    fn test_parse_with_recovery_reports_all_errors() {
+        let code = "a = * 1;\nb = 2;\nc = (3;\nd = { 1 2 };\ne = 4";
+        let tokens = tokenize(code).unwrap();
+        let errors = parse_with_recovery(&tokens).unwrap_err();
+        let errmsgs: Vec<&str> = errors.iter().map(|e| e.errmsg.as_str()).collect();
+        assert_eq!(
+            errmsgs,
+            [
+                "operand or unary operator expected here",
+                "unclosed bracket",
+                "expression end or binary operator expected here",
+            ]
+        );
+        assert_eq!(parse(&tokens).unwrap_err().errmsg, errmsgs[0]);
+    }
+
+    #[test]
+    fn test_typeswitch_syntax_errors() {
+        let parse_error = |code| parse(&tokenize(code).unwrap()).unwrap_err().errmsg;
+        assert_eq!(
+            parse_error("typeswitch x 1"),
+            "typeswitch arms in curly brackets expected here"
+        );
+        assert_eq!(
+            parse_error("typeswitch x { 1: 2 }"),
+            "type name expected here"
+        );
+        assert_eq!(
+            parse_error("typeswitch x { integer 1 }"),
+            "\":\" expected after the type name"
+        );
+        assert_eq!(
+            parse_error("typeswitch x { integer: 1 string: 2 }"),
+            "typeswitch arms must be separated by commas"
+        );
+    }
+
+    #[test]
+    fn test_not_in_is_negated_in() {
+        let tokens = tokenize("a not in b").unwrap();
+        let Expression::Scope { body, .. } = parse(&tokens).unwrap() else {
+            panic!("program is a scope");
+        };
+        assert!(matches!(
+            &body[0],
+            Expression::UnaryOperation { op: UnaryOp::Neg, operand, .. }
+                if matches!(operand.as_ref(), Expression::BinaryOperation { op: BinaryOp::In, .. })
+        ));
+    }
+
+    #[test]
+    fn test_let_requires_assignment() {
+        assert_eq!(
+            parse(&tokenize("let x").unwrap()).unwrap_err().errmsg,
+            "assignment expected after \"let\""
+        );
+    }
+
+    #[test]
+    fn test_global_requires_variable_names() {
+        assert_eq!(
+            parse(&tokenize("global x + 1").unwrap())
+                .unwrap_err()
+                .errmsg,
+            "variable names expected after \"global\""
+        );
+    }
+
+    #[test]
+    fn test_invalid_string_escapes() {
+        for (code, expected_errmsg) in [
+            (r#""\x4""#, r#"invalid escape sequence "\x4" at offset 1"#),
+            (
+                r#""ab\xzz""#,
+                r#"invalid escape sequence "\xzz" at offset 3"#,
+            ),
+            (
+                r#""\u{110000}""#,
+                "invalid code point 0x110000 in escape sequence at offset 1",
+            ),
+            (
+                r#""é\u41""#,
+                r#"invalid escape sequence "\u", expected \u{...} at offset 2"#,
+            ),
+            (r#"b"a\xz""#, r#"invalid escape sequence "\xz" at offset 3"#),
+            (r#"b"\u{100}""#, "code point 0x100 doesn't fit in a byte"),
+            (
+                "b\"\u{e9}\"",
+                "bytes literal can only contain ASCII characters",
+            ),
+        ] {
+            let tokens = tokenize(code).unwrap();
+            let error = parse(&tokens).unwrap_err();
+            assert_eq!(error.errmsg, expected_errmsg);
+            assert_eq!(error.error_token_idx, 0);
+        }
+    }
+
+    #[test]
+    fn test_slice_syntax() {
+        let tree = |code| crate::debug::format_tree(&parse(&tokenize(code).unwrap()).unwrap());
+        assert_eq!(
+            tree("x[1:][::-1]"),
+            "┬ Scope ─\n└─Slice ::step\n  ├─Slice start::\n  │ ├─x\n  │ └─Int(1)\n  └─Neg\n    └─Int(1)"
+        );
+        assert_eq!(
+            tree("-f(a)[0]"),
+            "┬ Scope ─\n└─Neg\n  └─Index\n    ├─FunctionCall\n    │ ├─f\n    │ └─a\n    └─Int(0)"
+        );
+        let parse_error = |code| parse(&tokenize(code).unwrap()).unwrap_err().errmsg;
+        assert_eq!(
+            parse_error("x[]"),
+            "index or slice expected in the square brackets"
+        );
+        assert_eq!(
+            parse_error("x[1:2:3:4]"),
+            "slice has at most three parts: start, end and step"
+        );
+    }
+
+    #[test]
+    fn test_list_literal_after_header_is_not_indexing() {
+        let tree = |code| crate::debug::format_tree(&parse(&tokenize(code).unwrap()).unwrap());
+        assert_eq!(
+            tree("if (true) [1] else [2]"),
+            "┬ Scope ─\n└─IfElse\n  ├─Bool(true)\n  ├─List\n  │ └─Int(1)\n  └─List\n    └─Int(2)"
+        );
+        assert_eq!(
+            tree("func f(x) [x]"),
+            "┬ Scope ─\n└─Assign\n  ├─f\n  └─Function f(x)\n    └─List\n      └─x"
+        );
+        assert_eq!(
+            tree("while (false) [1]"),
+            "┬ Scope ─\n└─While\n  ├─Bool(false)\n  └─List\n    └─Int(1)"
+        );
+        assert_eq!(
+            tree("repeat (2) [1]"),
+            "┬ Scope ─\n└─Repeat\n  ├─Int(2)\n  └─List\n    └─Int(1)"
+        );
+        // inside the brackets and in the body indexing still works
+        assert_eq!(
+            tree("if (x[0]) y[1]"),
+            "┬ Scope ─\n└─If\n  ├─Index\n  │ ├─x\n  │ └─Int(0)\n  └─Index\n    ├─y\n    └─Int(1)"
+        );
+    }
+
+    #[test]
+    fn test_labeled_loop() {
+        let tokens = tokenize("outer: while true { repeat(2) break outer }").unwrap();
+        assert_eq!(
+            crate::debug::format_tree(&parse(&tokens).unwrap()),
+            "┬ Scope ─\n└─While outer\n  ├─Bool(true)\n  └─┬ Scope ─\n    └─Repeat\n      ├─Int(2)\n      └─Break outer"
+        );
+    }
+
+    #[test]
+    fn test_check_brackets_reports_all_errors() {
+        let code = "a = (1 + 2));\nb = [1, (2];\nc = { 3 }";
+        let tokens = tokenize(code).unwrap();
+        let errors: Vec<(&str, String)> = check_brackets(&tokens)
+            .into_iter()
+            .map(|e| (tokens[e.error_token_idx].lexeme, e.errmsg))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (")", "unmatched closing bracket".to_string()),
+                ("[", "unclosed bracket".to_string()),
+                ("(", "unclosed bracket".to_string()),
+                ("]", "unmatched closing bracket".to_string()),
+            ]
+        );
+        assert!(check_brackets(&tokenize("f([1, {2}])").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_parse_with_recovery_without_errors() {
+        let tokens = tokenize("a = 1; { b = 2; a + b }").unwrap();
+        assert_eq!(
+            parse_with_recovery(&tokens).unwrap(),
+            parse(&tokens).unwrap()
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::parser::Expression;
+
+/// Number of evaluations of each expression kind, with binary and unary operations
+/// counted per operator
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub counts: HashMap<String, usize>,
+}
+
+impl Profile {
+    pub fn record(&mut self, expr: &Expression) {
+        *self.counts.entry(expression_kind(expr)).or_default() += 1;
+    }
+}
+
+fn expression_kind(expr: &Expression) -> String {
+    match expr {
+        Expression::Value(..) => "Value".into(),
+        Expression::Variable(..) => "Variable".into(),
+        Expression::BinaryOperation { op, .. } => format!("{:?}", op),
+        Expression::UnaryOperation { op, .. } => format!("{:?}", op),
+        Expression::Scope { .. } => "Scope".into(),
+        Expression::List { .. } => "List".into(),
+        Expression::If { .. } => "If".into(),
+        Expression::While { .. } => "While".into(),
+        Expression::Repeat { .. } => "Repeat".into(),
+        Expression::TypeSwitch { .. } => "TypeSwitch".into(),
+        Expression::Slice { is_slice: true, .. } => "Slice".into(),
+        Expression::Slice { .. } => "Index".into(),
+        Expression::Break { .. } => "Break".into(),
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut counts: Vec<(&String, &usize)> = self.counts.iter().collect();
+        counts
+            .sort_by(|(name1, count1), (name2, count2)| count2.cmp(count1).then(name1.cmp(name2)));
+        write!(f, "Evaluations:")?;
+        for (name, count) in counts {
+            write!(f, "\n  {:<16}{:>10}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::Context, eval, parse, tokenize};
+
+    #[test]
+    fn test_profile_loop() {
+        let tokens = tokenize("i = 0; while i < 3 { i = i + 1 }").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut ctx = Context {
+            profile: Some(Profile::default()),
+            ..Default::default()
+        };
+        eval(&ast, &mut HashMap::new(), &mut ctx).unwrap();
+        let profile = ctx.profile.unwrap();
+        assert_eq!(profile.counts["While"], 1);
+        assert_eq!(profile.counts["Add"], 3);
+        assert_eq!(profile.counts["IsLt"], 4);
+        assert_eq!(profile.counts["Assign"], 4);
+    }
+
+    #[test]
+    fn test_profile_display() {
+        let profile = Profile {
+            counts: HashMap::from([("Add".into(), 3), ("While".into(), 1), ("IsLt".into(), 4)]),
+        };
+        assert_eq!(
+            format!("{}", profile),
+            "Evaluations:\n  IsLt                     4\n  Add                      3\n  While                    1"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::FileHistory, validate::Validator, Editor, Helper,
+};
+
+use crate::{
+    context::Context,
+    parser::parse,
+    runtime::eval,
+    tokenizer::{tokenize, Token, TokenType},
+    values::{builtins::builtin_names, Value},
+};
+
+const PROMPT: &str = ">>> ";
+const HISTORY_FILE_NAME: &str = ".calculator_history";
+
+/// History file in the user's home directory, if it can be located
+pub fn default_history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME))
+}
+
+type ReplEditor = Editor<ReplHelper, FileHistory>;
+
+/// Tab-completion of built-in functions and variables defined so far
+struct ReplHelper {
+    builtin_names: Vec<String>,
+    variable_names: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let word_start = line[..pos]
+            .rfind(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        Ok((
+            word_start,
+            complete_word(
+                &line[word_start..pos],
+                &self.builtin_names,
+                &self.variable_names,
+            ),
+        ))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+impl Highlighter for ReplHelper {}
+impl Validator for ReplHelper {}
+impl Helper for ReplHelper {}
+
+/// Sorted names of built-ins and variables starting with the prefix
+fn complete_word(prefix: &str, builtin_names: &[String], variable_names: &[String]) -> Vec<String> {
+    let mut completions: Vec<String> = builtin_names
+        .iter()
+        .chain(variable_names)
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect();
+    completions.sort();
+    completions.dedup();
+    completions
+}
+
+/// Interactive read-eval-print loop; variables persist between the entered lines
+pub fn run(history_file: Option<&Path>, ctx: &mut Context) -> rustyline::Result<()> {
+    let mut editor = open_editor(history_file, ctx)?;
+    let mut vars: HashMap<String, Rc<Value>> = HashMap::new();
+    loop {
+        match editor.readline(PROMPT) {
+            Ok(line) => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                record_history_entry(&mut editor, &line, history_file)?;
+                if let Some(output) = eval_line(&line, &mut vars, ctx) {
+                    println!("{}", output);
+                }
+                if let Some(helper) = editor.helper_mut() {
+                    helper.variable_names = vars.keys().cloned().collect();
+                }
+            }
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn open_editor(history_file: Option<&Path>, ctx: &Context) -> rustyline::Result<ReplEditor> {
+    let mut editor = ReplEditor::new()?;
+    editor.set_helper(Some(ReplHelper {
+        builtin_names: builtin_names(ctx),
+        variable_names: Vec::new(),
+    }));
+    if let Some(path) = history_file {
+        if path.exists() {
+            editor.load_history(path)?;
+        }
+    }
+    Ok(editor)
+}
+
+fn record_history_entry(
+    editor: &mut ReplEditor,
+    line: &str,
+    history_file: Option<&Path>,
+) -> rustyline::Result<()> {
+    editor.add_history_entry(line)?;
+    if let Some(path) = history_file {
+        editor.append_history(path)?;
+    }
+    Ok(())
+}
+
+/// Whether the line ends with an explicit `;` (comments aside), which means its value shouldn't
+/// be shown
+fn is_output_suppressed(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .rev()
+        .find(|token| token.t != TokenType::Comment)
+        .is_some_and(|token| token.t == TokenType::ExprEnd)
+}
+
+/// Text to be shown for the line: its value or an error, nothing for Nothing or when the output
+/// is suppressed with a trailing `;`
+fn eval_line(
+    line: &str,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Option<String> {
+    let tokens = match tokenize(line) {
+        Ok(tokens) => tokens,
+        Err(e) => return Some(e.to_string()),
+    };
+    let expression = match parse(&tokens) {
+        Ok(expression) => expression,
+        Err(e) => return Some(e.to_string()),
+    };
+    ctx.allocated = 0;
+    match eval(&expression, vars, ctx) {
+        Ok(_) if is_output_suppressed(&tokens) => None,
+        Ok(value) if *value == Value::Nothing => None,
+        Ok(value) => Some(value.to_string()),
+        Err(e) => Some(e.format_with_source(line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_appended_and_reloaded() {
+        let history_file =
+            std::env::temp_dir().join(format!("calculator_history_test_{}", std::process::id()));
+        let _ = std::fs::remove_file(&history_file);
+
+        let mut editor = open_editor(Some(&history_file), &Context::default()).unwrap();
+        record_history_entry(&mut editor, "a = 1", Some(&history_file)).unwrap();
+        record_history_entry(&mut editor, "a + 1", Some(&history_file)).unwrap();
+
+        let mut reopened_editor = open_editor(Some(&history_file), &Context::default()).unwrap();
+        let history: Vec<&String> = reopened_editor.history().iter().collect();
+        assert_eq!(history, vec!["a = 1", "a + 1"]);
+
+        record_history_entry(&mut reopened_editor, "a * 2", Some(&history_file)).unwrap();
+        let history_file_content = std::fs::read_to_string(&history_file).unwrap();
+        assert!(history_file_content.ends_with("a = 1\na + 1\na * 2\n"));
+
+        std::fs::remove_file(&history_file).unwrap();
+    }
+
+    #[test]
+    fn test_complete_word() {
+        let builtins = builtin_names(&Context::default());
+        assert_eq!(complete_word("lo", &builtins, &[]), vec!["log"]);
+        assert_eq!(
+            complete_word("le", &builtins, &["left".into(), "log".into()]),
+            vec!["left", "length", "lerp"]
+        );
+        assert!(complete_word("xyz", &builtins, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_eval_line_keeps_variables() {
+        let mut vars = HashMap::new();
+        let mut ctx = Context::default();
+        assert_eq!(eval_line("a = 2", &mut vars, &mut ctx), Some("2".into()));
+        assert_eq!(eval_line("a * 3", &mut vars, &mut ctx), Some("6".into()));
+        assert_eq!(eval_line("print(a)", &mut vars, &mut ctx), None);
+    }
+
+    #[test]
+    fn test_eval_line_trailing_semicolon_suppresses_output() {
+        let mut vars = HashMap::new();
+        let mut ctx = Context::default();
+        assert_eq!(eval_line("a = 1;", &mut vars, &mut ctx), None);
+        assert_eq!(eval_line("a = 2; # silent", &mut vars, &mut ctx), None);
+        assert_eq!(eval_line("a;  ", &mut vars, &mut ctx), None);
+        assert_eq!(
+            eval_line("a = a + 1; a", &mut vars, &mut ctx),
+            Some("3".into())
+        );
+        assert_eq!(
+            eval_line("a # shown", &mut vars, &mut ctx),
+            Some("3".into())
+        );
+        assert!(eval_line("a / 0;", &mut vars, &mut ctx)
+            .unwrap()
+            .contains("division by zero"));
+    }
+}
@@ -1,12 +1,16 @@
-use std::collections::HashMap;
-use std::ops::Deref;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::collections::{HashMap, HashSet};
+use std::ptr;
 use std::rc::Rc;
 
+use crate::context::Context;
 use crate::errors::RuntimeError;
 use crate::parser::{BinaryOp, Expression, UnaryOp};
-use crate::values::builtins::builtin;
-use crate::values::function::Function;
-use crate::values::Value;
+use crate::span::Span;
+use crate::values::builtins::{builtin, constant, is_disabled_in_sandbox};
+use crate::values::function::{Function, UserDefinedFunction};
+use crate::values::{Int, Value};
 
 macro_rules! apply_bin {
     ( $func:expr, $left:expr, $right:expr, $op_name:expr ) => {{
@@ -36,290 +40,1679 @@ macro_rules! apply_un {
     }};
 }
 
+/// Adds a newly built value to the allocated size, checking it against the memory limit
+fn counted(value: Rc<Value>, ctx: &mut Context) -> Result<Rc<Value>, String> {
+    let Some(limit) = ctx.memory_limit else {
+        return Ok(value);
+    };
+    let size = match value.as_ref() {
+        Value::Tuple(elements) | Value::List(elements) => elements.len(),
+        Value::String(s) => s.chars().count(),
+        Value::Bytes(b) => b.len(),
+        _ => return Ok(value),
+    };
+    ctx.allocated = ctx.allocated.saturating_add(size);
+    if ctx.allocated > limit {
+        return Err(format!("memory limit of {} exceeded", limit));
+    }
+    Ok(value)
+}
+
+// most kinds of expressions are evaluated in separate functions: `eval` is on the native stack
+// for every level of recursion in the evaluated code, so its frame has to stay small
 pub fn eval(
     expression: &Expression,
     vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let extend_traceback = |e: RuntimeError| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: [e.traceback, vec![expression.clone()]].concat(),
+    };
+    ctx.record_eval(expression);
+    match expression {
+        Expression::Value(v, _) => Ok(Rc::clone(v)),
+        Expression::Variable(var_name, _) => eval_variable(expression, var_name, vars, ctx),
+        Expression::Scope {
+            body,
+            is_returnable,
+            span: _,
+        } => eval_scope(expression, body, *is_returnable, vars, ctx),
+        Expression::BinaryOperation {
+            op,
+            left,
+            right,
+            span: _,
+        } => match op {
+            BinaryOp::Assign => {
+                eval_assignment(left, right, false, vars, ctx).map_err(extend_traceback)
+            }
+            BinaryOp::FunctionCall => eval_function_call(expression, left, right, vars, ctx),
+            BinaryOp::And | BinaryOp::Or => {
+                eval_logical_operation(expression, *op, left, right, vars, ctx)
+            }
+            BinaryOp::Coalesce => {
+                // right-hand side is only evaluated when it's needed
+                let left_value = eval(left, vars, ctx).map_err(extend_traceback)?;
+                if let Value::Nothing = left_value.as_ref() {
+                    eval(right, vars, ctx).map_err(extend_traceback)
+                } else {
+                    Ok(left_value)
+                }
+            }
+            BinaryOp::Pipe | BinaryOp::OptionalChain => {
+                eval_pipe(expression, *op, left, right, vars, ctx)
+            }
+            ltr_op => eval_ltr_chain(expression, *ltr_op, left, right, vars, ctx),
+        },
+        Expression::UnaryOperation {
+            op: UnaryOp::Global,
+            operand,
+            span: _,
+        } => {
+            for name in pattern_variable_names(operand) {
+                ctx.declare_global(name);
+            }
+            Ok(Rc::new(Value::Nothing))
+        }
+        Expression::UnaryOperation {
+            op,
+            operand,
+            span: _,
+        } => eval_unary_operation(expression, *op, operand, vars, ctx),
+        Expression::List { elements, span: _ } => eval_list(expression, elements, vars, ctx),
+        Expression::If {
+            condition,
+            if_true,
+            if_false,
+            span: _,
+        } => match chosen_branch(
+            expression,
+            condition,
+            if_true,
+            if_false.as_deref(),
+            vars,
+            ctx,
+        )? {
+            Some(branch) => eval(branch, vars, ctx),
+            None => Ok(Rc::new(Value::Nothing)),
+        },
+        Expression::While {
+            label,
+            condition,
+            body,
+            if_completed: _, // TBD
+            span: _,
+        } => eval_while(expression, label, condition, body, vars, ctx),
+        Expression::Repeat {
+            label,
+            count,
+            body,
+            span: _,
+        } => eval_repeat(expression, label, count, body, vars, ctx),
+        Expression::TypeSwitch {
+            value,
+            arms,
+            span: _,
+        } => {
+            let value = eval(value, vars, ctx)?;
+            if value.is_control_flow() {
+                return Ok(value);
+            }
+            let type_name = value.type_name();
+            match arms
+                .iter()
+                .find(|(arm_type_name, _)| arm_type_name == type_name || arm_type_name == "_")
+            {
+                Some((_, body)) => eval(body, vars, ctx),
+                None => Ok(Rc::new(Value::Nothing)),
+            }
+        }
+        Expression::Slice {
+            collection,
+            start,
+            end,
+            step,
+            is_slice,
+            span: _,
+        } => eval_slice(
+            expression,
+            collection,
+            [start, end, step],
+            *is_slice,
+            vars,
+            ctx,
+        ),
+        Expression::Break { label, span: _ } => Ok(Rc::new(Value::Broken(label.clone()))),
+    }
+}
+
+/// Value of a variable, falling back to built-in functions and constants
+fn eval_variable(
+    expression: &Expression,
+    var_name: &str,
+    vars: &HashMap<String, Rc<Value>>,
+    ctx: &Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    if let Some(value) = vars.get(var_name).map(Rc::clone) {
+        Ok(value)
+    } else if is_disabled_in_sandbox(var_name, ctx) {
+        Err(new_error(format!(
+            "\"{}\" built-in function is disabled in sandbox mode",
+            var_name
+        )))
+    } else if let Some(builtin_func) = builtin(var_name) {
+        Ok(Rc::new(Value::Function(builtin_func)))
+    } else if let Some(constant_value) = constant(var_name) {
+        Ok(Rc::new(constant_value))
+    } else {
+        Err(new_error(format!(
+            "reference to non-existent variable \"{}\"",
+            var_name
+        )))
+    }
+}
+
+/// Statements of a block one by one, the value of a block is the value of its last statement
+fn eval_scope(
+    expression: &Expression,
+    body: &[Expression],
+    is_returnable: bool,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    if body.is_empty() {
+        return Ok(Rc::new(Value::Nothing));
+    }
+    let mut shadows = LetShadows::default();
+    let mut eval_body = |vars: &mut HashMap<String, Rc<Value>>| {
+        let mut results: Vec<Rc<Value>> = Vec::new();
+        for expr in body.iter() {
+            if !is_returnable {
+                shadows.record(expr, vars);
+            }
+            let expr_value = eval(expr, vars, ctx)?;
+            if let Some(exit) = block_exit(expression, &expr_value, is_returnable) {
+                return exit;
+            }
+            results.push(expr_value);
+        }
+        Ok(results[results.len() - 1].clone())
+    };
+    let result = eval_body(vars);
+    shadows.restore(vars);
+    result
+}
+
+/// Names declared with `let` in a block and their values from outside of it, put back when
+/// the block ends
+#[derive(Default)]
+struct LetShadows(Vec<(String, Option<Rc<Value>>)>);
+
+impl LetShadows {
+    /// Saves the outer values of the names the statement declares, before it's evaluated
+    fn record(&mut self, statement: &Expression, vars: &HashMap<String, Rc<Value>>) {
+        for name in let_declared_names(statement) {
+            if !self
+                .0
+                .iter()
+                .any(|(shadowed_name, _)| *shadowed_name == name)
+            {
+                let outer_value = vars.get(&name).cloned();
+                self.0.push((name, outer_value));
+            }
+        }
+    }
+
+    fn restore(self, vars: &mut HashMap<String, Rc<Value>>) {
+        for (name, outer_value) in self.0 {
+            match outer_value {
+                Some(value) => vars.insert(name, value),
+                None => vars.remove(&name),
+            };
+        }
+    }
+}
+
+/// Value of the block if a statement's value ends it early: a returnable block unwraps a
+/// returned value and rejects a break, other blocks pass both on
+fn block_exit(
+    expression: &Expression,
+    value: &Rc<Value>,
+    is_returnable: bool,
+) -> Option<Result<Rc<Value>, RuntimeError>> {
+    match value.as_ref() {
+        Value::Returned(v) if is_returnable => Some(Ok(v.clone())),
+        // returned value is passed wrapped up to the first returnable scope
+        Value::Returned(_) => Some(Ok(value.clone())),
+        Value::Broken(label) if is_returnable => Some(Err(RuntimeError {
+            errmsg: unmatched_break_errmsg(label),
+            traceback: vec![expression.clone()],
+        })),
+        Value::Broken(_) => Some(Ok(value.clone())),
+        _ => None,
+    }
+}
+
+/// "and" or "or" of two bools
+fn eval_logical_operation(
+    expression: &Expression,
+    op: BinaryOp,
+    left: &Expression,
+    right: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let extend_traceback = |e: RuntimeError| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: [e.traceback, vec![expression.clone()]].concat(),
+    };
+    // right-hand side is only evaluated when it decides the result
+    let op_name = if op == BinaryOp::And { "and" } else { "or" };
+    let left_value = eval(left, vars, ctx).map_err(extend_traceback)?;
+    match left_value.as_ref() {
+        Value::Returned(_) | Value::Broken(_) => return Ok(left_value),
+        Value::Bool(b) if *b == (op == BinaryOp::Or) => return Ok(left_value),
+        Value::Bool(_) => {}
+        v => {
+            return Err(new_error(format!(
+                "\"{}\" is only defined for bools, got {}",
+                op_name,
+                v.type_name()
+            )))
+        }
+    }
+    let right_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+    match right_value.as_ref() {
+        Value::Returned(_) | Value::Broken(_) | Value::Bool(_) => Ok(right_value),
+        v => Err(new_error(format!(
+            "\"{}\" is only defined for bools, got {}",
+            op_name,
+            v.type_name()
+        ))),
+    }
+}
+
+/// Call of the right operand's value with the left operand as the argument, for `|>` and `?.`
+fn eval_pipe(
+    expression: &Expression,
+    op: BinaryOp,
+    left: &Expression,
+    right: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let extend_traceback = |e: RuntimeError| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: [e.traceback, vec![expression.clone()]].concat(),
+    };
+    let (op_name, is_optional) = match op {
+        BinaryOp::OptionalChain => ("?.", true),
+        _ => ("|>", false),
+    };
+    let left_value = eval(left, vars, ctx).map_err(extend_traceback)?;
+    // with "?." the right-hand side is only evaluated when the left one is present
+    if (is_optional && matches!(left_value.as_ref(), Value::Nothing))
+        || left_value.is_control_flow()
+    {
+        return Ok(left_value);
+    }
+    let right_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+    match right_value.as_ref() {
+        Value::Returned(_) | Value::Broken(_) => Ok(right_value),
+        Value::Function(func) => {
+            call_function(func, &Expression::Value(left_value, left.span()), vars, ctx)
+                .map_err(extend_traceback)
+        }
+        v => Err(new_error(format!(
+            "\"{}\" expects a function on the right, got {}",
+            op_name,
+            v.type_name()
+        ))),
+    }
+}
+
+/// Unary operation applied to its evaluated operand
+fn eval_unary_operation(
+    expression: &Expression,
+    op: UnaryOp,
+    operand: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let extend_traceback = |e: RuntimeError| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: [e.traceback, vec![expression.clone()]].concat(),
+    };
+    let operand = eval(operand, vars, ctx).map_err(extend_traceback)?;
+    match op {
+        _ if matches!(operand.as_ref(), Value::Broken(_)) => Ok(operand),
+        UnaryOp::Neg if operand.is_control_flow() => Ok(operand),
+        // as `0 - v`, negating an `Int::MIN` numerator is too big for a rational
+        UnaryOp::Neg if matches!(operand.as_ref(), Value::Rational { .. }) => {
+            fraction_op(BinaryOp::Sub, &Value::Int(0), &operand)
+                .map(Rc::new)
+                .map_err(|errmsg| RuntimeError {
+                    errmsg,
+                    traceback: vec![expression.clone()],
+                })
+        }
+        UnaryOp::Neg => apply_un!(neg, operand, "negation", expression),
+        UnaryOp::Return => Ok(Rc::new(Value::Returned(operand))),
+        UnaryOp::Let => Ok(operand),
+        UnaryOp::Global => unreachable!("global declarations are evaluated above"),
+    }
+    .map_err(extend_traceback)
+}
+
+/// List of the evaluated elements
+fn eval_list(
+    expression: &Expression,
+    elements: &[Expression],
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let extend_traceback = |e: RuntimeError| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: [e.traceback, vec![expression.clone()]].concat(),
+    };
+    let mut values = Vec::with_capacity(elements.len());
+    for element in elements {
+        let value = eval(element, vars, ctx).map_err(extend_traceback)?;
+        if value.is_control_flow() {
+            return Ok(value);
+        }
+        values.push(value);
+    }
+    counted(Rc::new(Value::List(values)), ctx).map_err(new_error)
+}
+
+/// Loop body evaluated while the condition holds, the value of a loop is the value of its last
+/// iteration
+fn eval_while(
+    expression: &Expression,
+    label: &Option<String>,
+    condition: &Expression,
+    body: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let mut last_result = Rc::new(Value::Nothing);
+    loop {
+        let condition = eval(condition, vars, ctx)?;
+        if let Some(run_loop_iteration) = condition.truthiness() {
+            if run_loop_iteration {
+                last_result = eval(body, vars, ctx)?;
+                if let Some(loop_result) = exited_loop_result(&last_result, label) {
+                    return Ok(loop_result);
+                }
+            } else {
+                return Ok(last_result);
+            }
+        } else {
+            return Err(new_error(format!(
+                "while loop condition must evaluate to bool or a value with truthiness, got {}",
+                condition.type_name()
+            )));
+        }
+    }
+}
+
+/// Loop body evaluated the given number of times
+fn eval_repeat(
+    expression: &Expression,
+    label: &Option<String>,
+    count: &Expression,
+    body: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let count = eval(count, vars, ctx)?;
+    let times = match count.as_ref() {
+        Value::Returned(_) | Value::Broken(_) => return Ok(count),
+        Value::Int(n) if *n >= 0 => *n,
+        Value::Int(_) => return Err(new_error("repeat count can't be negative".into())),
+        v => {
+            return Err(new_error(format!(
+                "repeat count must be an integer, got {}",
+                v.type_name()
+            )))
+        }
+    };
+    let mut last_result = Rc::new(Value::Nothing);
+    for _ in 0..times {
+        last_result = eval(body, vars, ctx)?;
+        if let Some(loop_result) = exited_loop_result(&last_result, label) {
+            return Ok(loop_result);
+        }
+    }
+    Ok(last_result)
+}
+
+/// Element access `x[i]` or a slice `x[start:end:step]`
+fn eval_slice(
+    expression: &Expression,
+    collection: &Expression,
+    [start, end, step]: [&Option<Box<Expression>>; 3],
+    is_slice: bool,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let collection = eval(collection, vars, ctx)?;
+    if collection.is_control_flow() {
+        return Ok(collection);
+    }
+    let mut parts = Vec::with_capacity(3);
+    for part in [start, end, step] {
+        let part = match part {
+            Some(expr) => eval(expr, vars, ctx)?,
+            None => Rc::new(Value::Nothing),
+        };
+        if part.is_control_flow() {
+            return Ok(part);
+        }
+        parts.push(part);
+    }
+    let [start, end, step] = &parts[..] else {
+        unreachable!("slice has three parts")
+    };
+    if is_slice {
+        slice(&collection, start, end, step)
+            .and_then(|value| counted(value, ctx))
+            .map_err(new_error)
+    } else {
+        element_at(&collection, start).map_err(new_error)
+    }
+}
+
+/// Call of the left operand's value with the right operand as the argument
+fn eval_function_call(
+    expression: &Expression,
+    left: &Expression,
+    right: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    let new_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: vec![expression.clone()],
+    };
+    let extend_traceback = |e: RuntimeError| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: [e.traceback, vec![expression.clone()]].concat(),
+    };
+    let left_value = eval(left, vars, ctx)?;
+    if let Value::Function(func) = left_value.as_ref() {
+        match func {
+            Function::Builtin(builtin_func) => {
+                let arg_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+                if arg_value.is_control_flow() {
+                    return Ok(arg_value);
+                }
+                builtin_func(&arg_value)
+                    .map(Rc::new)
+                    .and_then(|value| counted(value, ctx))
+                    .map_err(new_error)
+            }
+            Function::HigherOrderBuiltin(builtin_func) => {
+                let arg_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+                if arg_value.is_control_flow() {
+                    return Ok(arg_value);
+                }
+                let arg_span = right.span();
+                let mut call = |func: &Function, arg: Rc<Value>| {
+                    call_function(func, &Expression::Value(arg, arg_span), vars, ctx)
+                        .map_err(|e| e.errmsg)
+                };
+                builtin_func(&arg_value, &mut call)
+                    .map(Rc::new)
+                    .and_then(|value| counted(value, ctx))
+                    .map_err(new_error)
+            }
+            Function::ContextBuiltin(builtin_func) => {
+                let arg_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+                if arg_value.is_control_flow() {
+                    return Ok(arg_value);
+                }
+                builtin_func(&arg_value, ctx)
+                    .map(Rc::new)
+                    .and_then(|value| counted(value, ctx))
+                    .map_err(new_error)
+            }
+            Function::UserDefined(_) | Function::Clauses(_) => {
+                call_function(func, right, vars, ctx).map_err(extend_traceback)
+            }
+            Function::Memoized(memoized) => {
+                let arg_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+                if arg_value.is_control_flow() {
+                    return Ok(arg_value);
+                }
+                // unhashable arguments bypass the cache
+                let arg_hash = arg_value.try_hash();
+                if let Some(result) = arg_hash.and_then(|hash| memoized.lookup(hash, &arg_value)) {
+                    return Ok(result);
+                }
+                let result = call_function(
+                    &memoized.func,
+                    &Expression::Value(arg_value.clone(), right.span()),
+                    vars,
+                    ctx,
+                )
+                .map_err(extend_traceback)?;
+                if let Some(hash) = arg_hash {
+                    memoized.store(hash, arg_value, result.clone());
+                }
+                Ok(result)
+            }
+            Function::Curried(curried) => {
+                let arg_value = eval(right, vars, ctx).map_err(extend_traceback)?;
+                if arg_value.is_control_flow() {
+                    return Ok(arg_value);
+                }
+                call_function(
+                    &curried.func,
+                    &Expression::Value(curried.full_arg(arg_value), right.span()),
+                    vars,
+                    ctx,
+                )
+                .map_err(extend_traceback)
+            }
+        }
+    } else {
+        Err(new_error(format!(
+            "\"{}\" is not callable",
+            left_value.type_name()
+        )))
+    }
+}
+
+/// Chain of operators with both operands evaluated left to right, like arithmetic and comparisons
+fn eval_ltr_chain<'a>(
+    expression: &'a Expression,
+    ltr_op: BinaryOp,
+    left: &'a Expression,
+    right: &'a Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    // operands are evaluated left to right, so an assignment on the left is
+    // visible on the right: `(x = next()) != x`. Returned or broken value from an
+    // operand unwinds the whole operation, so that they never reach the
+    // operator implementations. A chain like `1 + 2 + ... + n` is a left-leaning
+    // tree, it's evaluated in a loop from the innermost operation outwards, so that
+    // long chains don't overflow the native stack
+    let mut chain = vec![(expression, ltr_op, right)];
+    let mut innermost = left;
+    while let Expression::BinaryOperation {
+        op, left, right, ..
+    } = innermost
+    {
+        if !is_ltr_op(*op) {
+            break;
+        }
+        ctx.record_eval(innermost);
+        chain.push((innermost, *op, right.as_ref()));
+        innermost = left.as_ref();
+    }
+    // an error at some level of the chain gets all the enclosing levels in its
+    // traceback, just like with the recursive evaluation
+    let with_traceback = |e: RuntimeError, level: usize| RuntimeError {
+        errmsg: e.errmsg,
+        traceback: e
+            .traceback
+            .into_iter()
+            .chain(
+                chain[..=level]
+                    .iter()
+                    .rev()
+                    .map(|(expr, ..)| (*expr).clone()),
+            )
+            .collect(),
+    };
+    let mut left_value =
+        eval(innermost, vars, ctx).map_err(|e| with_traceback(e, chain.len() - 1))?;
+    for (level, (_, op, right)) in chain.iter().enumerate().rev() {
+        if left_value.is_control_flow() {
+            return Ok(left_value);
+        }
+        let right_value = eval(right, vars, ctx).map_err(|e| with_traceback(e, level))?;
+        if right_value.is_control_flow() {
+            return Ok(right_value);
+        }
+        left_value = apply_ltr_op(*op, left_value, right_value, ctx.strict_types)
+            .and_then(|value| counted(value, ctx))
+            .map_err(|errmsg| {
+                with_traceback(
+                    RuntimeError {
+                        errmsg,
+                        traceback: vec![],
+                    },
+                    level,
+                )
+            })?;
+    }
+    Ok(left_value)
+}
+
+/// Variables assigned by a `let` statement, none for other statements
+fn let_declared_names(statement: &Expression) -> Vec<String> {
+    if let Expression::UnaryOperation {
+        op: UnaryOp::Let,
+        operand,
+        ..
+    } = statement
+    {
+        if let Expression::BinaryOperation {
+            op: BinaryOp::Assign,
+            left,
+            ..
+        } = operand.as_ref()
+        {
+            return pattern_variable_names(left);
+        }
+    }
+    Vec::new()
+}
+
+fn pattern_variable_names(pattern: &Expression) -> Vec<String> {
+    match pattern {
+        Expression::Variable(name, _) => vec![name.clone()],
+        other => other
+            .subexpressions()
+            .into_iter()
+            .flat_map(pattern_variable_names)
+            .collect(),
+    }
+}
+
+/// Value of the whole loop if the iteration result stops it: a returned value and a break
+/// meant for an outer loop are passed on, a break for this loop makes it nothing
+fn exited_loop_result(iteration_result: &Rc<Value>, label: &Option<String>) -> Option<Rc<Value>> {
+    match iteration_result.as_ref() {
+        Value::Broken(None) => Some(Rc::new(Value::Nothing)),
+        Value::Broken(break_label) if break_label == label => Some(Rc::new(Value::Nothing)),
+        Value::Returned(_) | Value::Broken(_) => Some(iteration_result.clone()),
+        _ => None,
+    }
+}
+
+/// Elements of a sliceable value, strings are split into chars
+pub(crate) fn sliceable_elements(collection: &Value) -> Option<Vec<Rc<Value>>> {
+    match collection {
+        Value::Tuple(elements) | Value::List(elements) => Some(elements.clone()),
+        Value::Nothing => Some(Vec::new()), // empty round brackets
+        Value::String(s) => Some(
+            s.chars()
+                .map(|ch| Rc::new(Value::String(ch.to_string())))
+                .collect(),
+        ),
+        Value::Bytes(b) => Some(
+            b.iter()
+                .map(|byte| Rc::new(Value::Int(Int::from(*byte))))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Index or slice part as a number, nothing stands for an omitted part
+fn slice_part(part: &Value) -> Result<Option<i64>, String> {
+    match part {
+        Value::Nothing => Ok(None),
+        // `Int` is already `i64` with the int64 feature
+        #[allow(clippy::useless_conversion)]
+        Value::Int(i) => Ok(Some(i64::from(*i))),
+        v => Err(format!("indices must be integers, got {}", v.type_name())),
+    }
+}
+
+fn element_at(collection: &Value, index: &Value) -> Result<Rc<Value>, String> {
+    let Some(elements) = sliceable_elements(collection) else {
+        return Err(format!("{} can't be indexed", collection.type_name()));
+    };
+    let index = slice_part(index)?.expect("index is not omitted");
+    lookup_element(&elements, index).ok_or_else(|| {
+        format!(
+            "index {} is out of range for {} of length {}",
+            index,
+            collection.type_name(),
+            elements.len()
+        )
+    })
+}
+
+/// Element at the index, negative ones counting from the end, `None` if it's out of range
+pub(crate) fn lookup_element(elements: &[Rc<Value>], index: i64) -> Option<Rc<Value>> {
+    let len = elements.len() as i64;
+    let position = if index < 0 { index + len } else { index };
+    if (0..len).contains(&position) {
+        Some(elements[position as usize].clone())
+    } else {
+        None
+    }
+}
+
+/// Python-like slice: out of range bounds are clamped, and omitted ones cover the whole
+/// collection in the direction of the step
+fn slice(
+    collection: &Value,
+    start: &Value,
+    end: &Value,
+    step: &Value,
+) -> Result<Rc<Value>, String> {
+    let Some(elements) = sliceable_elements(collection) else {
+        return Err(format!("{} can't be sliced", collection.type_name()));
+    };
+    let step = slice_part(step)?.unwrap_or(1);
+    if step == 0 {
+        return Err("slice step can't be zero".into());
+    }
+    let len = elements.len() as i64;
+    // bounds are clamped to [-1, len - 1] going backwards, so that -1 is before the first element
+    let (lowest, highest) = if step > 0 { (0, len) } else { (-1, len - 1) };
+    let clamp = |bound: i64| {
+        if bound < 0 {
+            (bound + len).max(lowest)
+        } else {
+            bound.min(highest)
+        }
+    };
+    let start = slice_part(start)?.map_or(if step > 0 { lowest } else { highest }, clamp);
+    let end = slice_part(end)?.map_or(if step > 0 { highest } else { lowest }, clamp);
+    let mut sliced = Vec::new();
+    let mut position = start;
+    while (step > 0 && position < end) || (step < 0 && position > end) {
+        sliced.push(elements[position as usize].clone());
+        // a step past the end of the `i64` range is past the end of the collection too
+        let Some(next_position) = position.checked_add(step) else {
+            break;
+        };
+        position = next_position;
+    }
+    Ok(Rc::new(match collection {
+        Value::List(_) => Value::List(sliced),
+        Value::String(_) => Value::String(sliced.iter().map(|ch| ch.to_string()).collect()),
+        Value::Bytes(_) => Value::Bytes(
+            sliced
+                .iter()
+                .map(|byte| match byte.as_ref() {
+                    Value::Int(byte) => *byte as u8,
+                    _ => unreachable!("bytes are split into integers"),
+                })
+                .collect(),
+        ),
+        _ if sliced.is_empty() => Value::Nothing,
+        _ => Value::Tuple(sliced),
+    }))
+}
+
+fn unmatched_break_errmsg(label: &Option<String>) -> String {
+    match label {
+        None => "\"break\" outside of a loop".into(),
+        Some(label) => format!("no enclosing loop labeled \"{}\"", label),
+    }
+}
+
+/// Operators evaluated by `apply_ltr_op`, the rest have special evaluation rules
+fn is_ltr_op(op: BinaryOp) -> bool {
+    !matches!(
+        op,
+        BinaryOp::Assign
+            | BinaryOp::FunctionCall
+            | BinaryOp::And
+            | BinaryOp::Or
+            | BinaryOp::Coalesce
+            | BinaryOp::OptionalChain
+            | BinaryOp::Pipe
+    )
+}
+
+fn apply_ltr_op(
+    op: BinaryOp,
+    left_value: Rc<Value>,
+    right_value: Rc<Value>,
+    strict_types: bool,
+) -> Result<Rc<Value>, String> {
+    let is_numeric_op = !matches!(
+        op,
+        BinaryOp::FormTuple | BinaryOp::AppendToTuple | BinaryOp::In
+    );
+    if is_numeric_op && strict_types && is_int_float_mix(&left_value, &right_value) {
+        return Err(format!(
+            "{} and {} can't be mixed in strict types mode, use explicit conversion",
+            left_value.type_name(),
+            right_value.type_name()
+        ));
+    }
+    match op {
+        BinaryOp::Div if is_zero(&right_value) => Err("division by zero".into()),
+        _ if is_fraction_op(op, &left_value, &right_value) => {
+            fraction_op(op, &left_value, &right_value).map(Rc::new)
+        }
+        BinaryOp::Add => apply_bin!(add, left_value, right_value, "addition"),
+        BinaryOp::Sub => apply_bin!(sub, left_value, right_value, "subtraction"),
+        BinaryOp::Mul if repetition_len(&left_value, &right_value) > MAX_REPETITION_LEN => {
+            Err(format!(
+                "repetition result would be longer than {}",
+                MAX_REPETITION_LEN
+            ))
+        }
+        BinaryOp::Mul => apply_bin!(mul, left_value, right_value, "multiplication"),
+        BinaryOp::Div => apply_bin!(div, left_value, right_value, "division"),
+        // `%` is dispatched on the left operand's type: a string is a format
+        // template filled with the right operand, anything else is a remainder
+        BinaryOp::Mod => match left_value.as_ref() {
+            Value::String(template) => {
+                format_template(template, &right_value).map(|s| Rc::new(Value::String(s)))
+            }
+            _ if is_zero(&right_value) => Err("division by zero".into()),
+            _ => apply_bin!(rem, left_value, right_value, "modulo"),
+        },
+        BinaryOp::Pow => apply_bin!(pow, left_value, right_value, "power"),
+        BinaryOp::IsEq => apply_bin!(eq, left_value, right_value, "equality"),
+        BinaryOp::IsNotEq => {
+            apply_bin!(not_eq, left_value, right_value, "inequality")
+        }
+        BinaryOp::IsLt => apply_bin!(lt, left_value, right_value, "less-than"),
+        BinaryOp::IsGt => apply_bin!(gt, left_value, right_value, "greater-than"),
+        BinaryOp::In => {
+            collection_contains(&right_value, &left_value).map(|is_in| Rc::new(Value::Bool(is_in)))
+        }
+        BinaryOp::FormTuple => Ok(Rc::new(Value::Tuple(vec![left_value, right_value]))),
+        BinaryOp::AppendToTuple => {
+            if let Value::Tuple(left_tuple) = left_value.to_owned().as_ref() {
+                let mut left_tuple_copy = left_tuple.clone();
+                left_tuple_copy.push(right_value);
+                Ok(Rc::new(Value::Tuple(left_tuple_copy)))
+            } else {
+                Err("internal error: can't append to non-tuple value".into())
+            }
+        }
+        _ => panic!("RTL op "),
+    }
+}
+
+/// Calls a function on the argument expression, user-defined functions are evaluated
+/// with a copy of the caller's variables
+pub fn call_function(
+    func: &Function,
+    arg: &Expression,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    match func {
+        Function::UserDefined(user_defined) => {
+            let mut local_vars = vars.clone();
+            // a return or a break in the argument leaves the caller, the body isn't evaluated
+            let arg_value = eval_assignment(&user_defined.params, arg, true, &mut local_vars, ctx)?;
+            if arg_value.is_control_flow() {
+                return Ok(arg_value);
+            }
+            eval_function_body(user_defined, func, &mut local_vars, vars, ctx)
+        }
+        Function::Clauses(clauses) => {
+            // the argument is evaluated once and then matched against each clause
+            let arg_value = eval(arg, vars, ctx)?;
+            if arg_value.is_control_flow() {
+                return Ok(arg_value);
+            }
+            let (clause, mut local_vars) = matching_clause(clauses, arg_value, vars)?;
+            eval_function_body(clause, func, &mut local_vars, vars, ctx)
+        }
+        builtin_func => {
+            let call_expr = Expression::BinaryOperation {
+                op: BinaryOp::FunctionCall,
+                left: Box::new(Expression::Value(
+                    Rc::new(Value::Function(builtin_func.clone())),
+                    arg.span(),
+                )),
+                right: Box::new(arg.clone()),
+                span: arg.span(),
+            };
+            eval(&call_expr, vars, ctx)
+        }
+    }
+}
+
+/// The first clause whose parameters match the argument, with the variables of the call to it
+fn matching_clause<'a>(
+    clauses: &'a [UserDefinedFunction],
+    arg_value: Rc<Value>,
+    vars: &HashMap<String, Rc<Value>>,
+) -> Result<(&'a UserDefinedFunction, HashMap<String, Rc<Value>>), RuntimeError> {
+    for clause in clauses {
+        let mut local_vars = vars.clone();
+        if unpack_value(&clause.params, arg_value.clone(), true, &mut local_vars).is_ok() {
+            return Ok((clause, local_vars));
+        }
+    }
+    Err(RuntimeError {
+        errmsg: format!(
+            "no clause of \"{}\" matches the argument {}",
+            clauses[0].name, arg_value
+        ),
+        traceback: vec![],
+    })
+}
+
+/// Evaluates the body with the call's copy of variables, then writes the ones declared `global`
+/// back to the caller's. If the caller is a function too, they are global in it as well, so
+/// they reach the top level through any number of calls.
+///
+/// A call of the function itself the body ends with, like `func f(n) if (n > 0) f(n - 1)`,
+/// isn't nested: its argument is bound in the same variables and the body is evaluated again,
+/// so tail recursion is not limited by the stack depth.
+fn eval_function_body(
+    clause: &UserDefinedFunction,
+    func: &Function,
+    local_vars: &mut HashMap<String, Rc<Value>>,
+    caller_vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
 ) -> Result<Rc<Value>, RuntimeError> {
-    let new_error = |errmsg: String| RuntimeError {
-        errmsg,
-        traceback: vec![expression.clone()],
-    };
-    let extend_traceback = |e: RuntimeError| RuntimeError {
-        errmsg: e.errmsg,
-        traceback: [e.traceback, vec![expression.clone()]].concat(),
-    };
-    match expression {
-        Expression::Value(v) => Ok(Rc::clone(v)),
-        Expression::Variable(var_name) => {
-            if let Some(value) = vars.get(var_name).map(|v| Rc::clone(v)) {
-                return Ok(value);
-            } else if let Some(builtin_func) = builtin(&var_name) {
-                return Ok(Rc::new(Value::Function(builtin_func)));
-            } else {
-                return Err(new_error(format!(
-                    "reference to non-existent variable \"{}\"",
-                    var_name
-                )));
+    ctx.global_names.push(HashSet::new());
+    let mut clause = clause;
+    let result = loop {
+        let (arg_value, arg_span) = match eval_tail(&clause.body, func, local_vars, ctx) {
+            Ok(TailEval::SelfCall(arg_value, arg_span)) => (arg_value, arg_span),
+            Ok(TailEval::Done(value)) => break Ok(value),
+            Err(e) => break Err(e),
+        };
+        let rebound = match func {
+            Function::Clauses(clauses) => {
+                matching_clause(clauses, arg_value, local_vars).map(|(next_clause, next_vars)| {
+                    clause = next_clause;
+                    *local_vars = next_vars;
+                })
             }
+            _ => eval_assignment(
+                &clause.params,
+                &Expression::Value(arg_value, arg_span),
+                true,
+                local_vars,
+                ctx,
+            )
+            .map(|_| ()),
+        };
+        if let Err(e) = rebound {
+            break Err(e);
+        }
+    };
+    let global_names = ctx.global_names.pop().unwrap_or_default();
+    for name in global_names {
+        if let Some(value) = local_vars.get(&name) {
+            caller_vars.insert(name.clone(), value.clone());
         }
+        ctx.declare_global(name);
+    }
+    // a body that isn't a block, like `func f(x) 1 + { return x }`, is not a
+    // returnable scope, so the returned value is unwrapped here
+    let result = result?;
+    match result.as_ref() {
+        Value::Returned(v) => Ok(v.clone()),
+        Value::Broken(label) => Err(RuntimeError {
+            errmsg: unmatched_break_errmsg(label),
+            traceback: vec![],
+        }),
+        _ => Ok(result),
+    }
+}
+
+/// Outcome of a function body evaluated by `eval_tail`
+enum TailEval {
+    Done(Rc<Value>),
+    /// The body ends with a call of the function itself, with this argument
+    SelfCall(Rc<Value>, Span),
+}
+
+/// Evaluates a function body like `eval`, except for the call of the function itself in tail
+/// position, i.e. the last thing evaluated in a block, a branch of an `if` or a `return`: its
+/// argument is evaluated, but the call is left to the caller.
+fn eval_tail(
+    expression: &Expression,
+    func: &Function,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<TailEval, RuntimeError> {
+    match expression {
         Expression::Scope {
             body,
             is_returnable,
-        } => {
-            if body.is_empty() {
-                return Ok(Rc::new(Value::Nothing));
-            }
-            let mut results: Vec<Rc<Value>> = Vec::new();
-            for expr in body.iter() {
-                let expr_value = eval(expr, vars)?;
-                if let Value::Returned(v) = expr_value.clone().deref() {
-                    if *is_returnable {
-                        return Ok(v.clone());
-                    } else {
-                        // returned value is passed wrapped up to the first returnable scope
-                        return Ok(expr_value.clone());
-                    }
-                }
-                results.push(expr_value);
-            }
-            return Ok(results[results.len() - 1].clone());
-        }
-        Expression::BinaryOperation { op, left, right } => match op {
-            BinaryOp::Assign => eval_assignment(&left, &right, vars).map_err(new_error),
-            BinaryOp::FunctionCall => {
-                let left_value = eval(&left, vars)?;
-                if let Value::Function(func) = left_value.as_ref() {
-                    match func {
-                        Function::Builtin(builtin_func) => {
-                            let arg_value = eval(&right, vars).map_err(extend_traceback)?;
-                            builtin_func(&arg_value)
-                                .map(|v| Rc::new(v))
-                                .map_err(new_error)
-                        }
-                        Function::UserDefined(func) => {
-                            let mut local_vars = vars.clone();
-                            eval_assignment(&func.params, &right, &mut local_vars)
-                                .map_err(new_error)?;
-                            eval(&func.body, &mut local_vars).map_err(extend_traceback)
-                        }
-                    }
-                } else {
-                    Err(new_error(format!(
-                        "\"{}\" is not callable",
-                        left_value.type_name()
-                    )))
-                }
-            }
-            ltr_op => {
-                let right_value = eval(&right, vars).map_err(extend_traceback)?;
-                let left_value = eval(&left, vars).map_err(extend_traceback)?;
-                match ltr_op {
-                    BinaryOp::Add => apply_bin!(add, left_value, right_value, "addition"),
-                    BinaryOp::Sub => apply_bin!(sub, left_value, right_value, "subtraction"),
-                    BinaryOp::Mul => apply_bin!(mul, left_value, right_value, "multiplication"),
-                    BinaryOp::Div => apply_bin!(div, left_value, right_value, "division"),
-                    BinaryOp::Pow => apply_bin!(pow, left_value, right_value, "power"),
-                    BinaryOp::IsEq => apply_bin!(eq, left_value, right_value, "equality"),
-                    BinaryOp::IsLt => apply_bin!(lt, left_value, right_value, "less-than"),
-                    BinaryOp::IsGt => apply_bin!(gt, left_value, right_value, "greater-than"),
-                    BinaryOp::FormTuple => Ok(Rc::new(Value::Tuple(vec![left_value, right_value]))),
-                    BinaryOp::AppendToTuple => {
-                        if let Value::Tuple(left_tuple) = left_value.to_owned().as_ref() {
-                            let mut left_tuple_copy = left_tuple.clone();
-                            left_tuple_copy.push(right_value);
-                            Ok(Rc::new(Value::Tuple(left_tuple_copy)))
-                        } else {
-                            Err("internal error: can't append to non-tuple value".into())
-                        }
-                    }
-                    _ => panic!("RTL op "),
-                }
-                .map_err(new_error)
-            }
-        },
-        Expression::UnaryOperation { op, operand } => {
-            let operand = eval(&operand, vars).map_err(extend_traceback)?;
-            match op {
-                UnaryOp::Neg => apply_un!(neg, operand, "negation", expression),
-                UnaryOp::Return => Ok(Rc::new(Value::Returned(operand))),
-            }
-            .map_err(extend_traceback)
+            ..
+        } if !body.is_empty() => {
+            ctx.record_eval(expression);
+            let mut shadows = LetShadows::default();
+            let result = eval_tail_scope(
+                expression,
+                body,
+                *is_returnable,
+                func,
+                &mut shadows,
+                vars,
+                ctx,
+            );
+            shadows.restore(vars);
+            result
         }
         Expression::If {
             condition,
             if_true,
             if_false,
+            span: _,
         } => {
-            let condition = eval(&condition, vars)?;
-            if let Value::Bool(b) = condition.clone().as_ref() {
-                if *b {
-                    Ok(eval(&if_true, vars)?)
-                } else if let Some(if_false_expr) = if_false {
-                    Ok(eval(&if_false_expr, vars)?)
-                } else {
-                    Ok(Rc::new(Value::Nothing))
-                }
-            } else {
-                Err(new_error(format!(
-                    "if condition must evaluate to bool, got {}",
-                    condition.type_name()
-                )))
+            ctx.record_eval(expression);
+            match chosen_branch(
+                expression,
+                condition,
+                if_true,
+                if_false.as_deref(),
+                vars,
+                ctx,
+            )? {
+                Some(branch) => eval_tail(branch, func, vars, ctx),
+                None => Ok(TailEval::Done(Rc::new(Value::Nothing))),
             }
         }
-        Expression::While {
-            condition,
-            body,
-            if_completed: _, // TBD
-        } => {
-            let mut last_result = Rc::new(Value::Nothing);
-            loop {
-                let condition = eval(&condition, vars)?;
-                if let Value::Bool(run_loop_iteration) = condition.clone().as_ref() {
-                    if *run_loop_iteration {
-                        last_result = eval(&body, vars)?;
-                        if let Value::Returned(_) = last_result.clone().as_ref() {
-                            return Ok(last_result);
-                        }
-                    } else {
-                        return Ok(last_result);
-                    }
-                } else {
-                    return Err(new_error(format!(
-                        "while loop condition must evaluate to bool, got {}",
-                        condition.type_name()
-                    )));
-                }
+        Expression::UnaryOperation {
+            op: UnaryOp::Return,
+            operand,
+            span: _,
+        } if is_self_call(operand, func, vars) => {
+            // the body's value is returned anyway
+            ctx.record_eval(expression);
+            eval_tail(operand, func, vars, ctx)
+        }
+        Expression::BinaryOperation {
+            op: BinaryOp::FunctionCall,
+            left: _,
+            right,
+            span: _,
+        } if is_self_call(expression, func, vars) => {
+            ctx.record_eval(expression);
+            let arg_value = eval(right, vars, ctx)?;
+            if arg_value.is_control_flow() {
+                return Ok(TailEval::Done(arg_value));
             }
+            Ok(TailEval::SelfCall(arg_value, right.span()))
+        }
+        _ => eval(expression, vars, ctx).map(TailEval::Done),
+    }
+}
+
+/// Statements of a block like `eval_scope`, with the last one evaluated by `eval_tail`
+fn eval_tail_scope(
+    expression: &Expression,
+    body: &[Expression],
+    is_returnable: bool,
+    func: &Function,
+    shadows: &mut LetShadows,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<TailEval, RuntimeError> {
+    let (last, init) = body.split_last().expect("the body is not empty");
+    for expr in init {
+        if !is_returnable {
+            shadows.record(expr, vars);
         }
+        let value = eval(expr, vars, ctx)?;
+        if let Some(exit) = block_exit(expression, &value, is_returnable) {
+            return exit.map(TailEval::Done);
+        }
+    }
+    if !is_returnable {
+        shadows.record(last, vars);
+    }
+    match eval_tail(last, func, vars, ctx)? {
+        TailEval::Done(value) => match block_exit(expression, &value, is_returnable) {
+            Some(exit) => exit.map(TailEval::Done),
+            None => Ok(TailEval::Done(value)),
+        },
+        self_call => Ok(self_call),
+    }
+}
+
+/// Whether the expression calls the function by a variable holding it. The function is
+/// compared by identity, it's borrowed from the same value as the variable holds.
+fn is_self_call(
+    expression: &Expression,
+    func: &Function,
+    vars: &HashMap<String, Rc<Value>>,
+) -> bool {
+    let Expression::BinaryOperation {
+        op: BinaryOp::FunctionCall,
+        left,
+        right: _,
+        span: _,
+    } = expression
+    else {
+        return false;
+    };
+    let Expression::Variable(name, _) = left.as_ref() else {
+        return false;
+    };
+    vars.get(name)
+        .is_some_and(|value| matches!(value.as_ref(), Value::Function(f) if ptr::eq(f, func)))
+}
+
+/// The branch of an `if` chosen by its condition, `None` if there's no else branch to take
+fn chosen_branch<'a>(
+    expression: &Expression,
+    condition: &Expression,
+    if_true: &'a Expression,
+    if_false: Option<&'a Expression>,
+    vars: &mut HashMap<String, Rc<Value>>,
+    ctx: &mut Context,
+) -> Result<Option<&'a Expression>, RuntimeError> {
+    let condition = eval(condition, vars, ctx)?;
+    match condition.truthiness() {
+        Some(true) => Ok(Some(if_true)),
+        Some(false) => Ok(if_false),
+        None => Err(RuntimeError {
+            errmsg: format!(
+                "if condition must evaluate to bool or a value with truthiness, got {}",
+                condition.type_name()
+            ),
+            traceback: vec![expression.clone()],
+        }),
+    }
+}
+
+/// A `func` declaration following one with literals in the parameters of the last clause,
+/// like `func f(0) 1; func f(n) n * f(n - 1)`, adds a clause to it instead of replacing it
+fn with_added_clause(existing: Option<&Rc<Value>>, new_value: Rc<Value>) -> Rc<Value> {
+    let Value::Function(Function::UserDefined(new_clause)) = new_value.as_ref() else {
+        return new_value;
+    };
+    let mut clauses = match existing.map(|value| value.as_ref()) {
+        Some(Value::Function(Function::UserDefined(clause))) => vec![clause.clone()],
+        Some(Value::Function(Function::Clauses(clauses))) => clauses.clone(),
+        _ => return new_value,
+    };
+    let extends = clauses
+        .last()
+        .is_some_and(|last| last.name == new_clause.name && is_refutable(&last.params));
+    if !extends {
+        return new_value;
+    }
+    clauses.push(new_clause.clone());
+    Rc::new(Value::Function(Function::Clauses(clauses)))
+}
+
+/// Whether the pattern can fail to match a value of the right shape, by having a literal in it
+fn is_refutable(pattern: &Expression) -> bool {
+    match pattern {
+        Expression::Value(..) => true,
+        _ => tuple_pattern_elements(pattern)
+            .is_some_and(|elements| elements.into_iter().any(is_refutable)),
     }
 }
 
+/// Assigns to the pattern on the left, literals in it are only allowed in function parameters,
+/// where they match an equal argument
 pub fn eval_assignment(
     left: &Expression,
     right: &Expression,
+    allow_literals: bool,
     vars: &mut HashMap<String, Rc<Value>>,
-) -> Result<Rc<Value>, String> {
-    if let Expression::Variable(var_name) = left {
-        let right_value = eval(right, vars).map_err(|e| e.errmsg)?;
+    ctx: &mut Context,
+) -> Result<Rc<Value>, RuntimeError> {
+    // errors are returned with an empty traceback, to be filled by the caller
+    let pattern_error = |errmsg: String| RuntimeError {
+        errmsg,
+        traceback: Vec::new(),
+    };
+    let is_matching_operation = match (left, right) {
+        (
+            Expression::BinaryOperation { op: op_left, .. },
+            Expression::BinaryOperation { op: op_right, .. },
+        ) => op_left == op_right,
+        _ => false,
+    };
+    if let Expression::Variable(var_name, _) = left {
+        let mut right_value = eval(right, vars, ctx)?;
+        if let Expression::Value(..) = right {
+            // only `func` declarations have a function literal on the right
+            right_value = with_added_clause(vars.get(var_name), right_value);
+        }
         vars.insert(var_name.clone(), right_value.clone());
         Ok(right_value)
+    } else if matches!(left, Expression::Value(..)) && allow_literals {
+        let right_value = eval(right, vars, ctx)?;
+        unpack_value(left, right_value.clone(), true, vars).map_err(pattern_error)?;
+        Ok(right_value)
+    } else if tuple_pattern_elements(left).is_some() && !is_matching_operation {
+        // right-hand side is not a tuple expression but may evaluate to a tuple
+        let right_value = eval(right, vars, ctx)?;
+        unpack_value(left, right_value.clone(), allow_literals, vars).map_err(pattern_error)?;
+        Ok(right_value)
     } else if let Expression::BinaryOperation {
         op: op_left,
         left: ll,
         right: rl,
+        span,
     } = left
     {
         if let Expression::BinaryOperation {
             op: op_right,
             left: lr,
             right: rr,
+            span: _,
         } = right
         {
             if op_left != op_right {
-                return Err(format!("right-hand side of the assignment doesn't match the pattern, expected binary operation {:?}", op_left));
+                return Err(pattern_error(format!("right-hand side of the assignment doesn't match the pattern, expected binary operation {:?}", op_left)));
             }
-            let res_left = eval_assignment(ll, lr, vars)?;
-            let res_right = eval_assignment(rl, rr, vars)?;
-            return eval(
+            let res_left = eval_assignment(ll, lr, allow_literals, vars, ctx)?;
+            let res_right = eval_assignment(rl, rr, allow_literals, vars, ctx)?;
+            eval(
                 &Expression::BinaryOperation {
                     op: *op_left,
-                    left: Box::new(Expression::Value(res_left)),
-                    right: Box::new(Expression::Value(res_right)),
+                    left: Box::new(Expression::Value(res_left, ll.span())),
+                    right: Box::new(Expression::Value(res_right, rl.span())),
+                    span: *span,
                 },
                 vars,
+                ctx,
             )
-            .map_err(|e| e.errmsg);
         } else {
-            Err(
+            Err(pattern_error(
                 "right-hand side of the assignment doesn't match the pattern, expected binary operation".into(),
-            )
+            ))
         }
     } else if let Expression::UnaryOperation {
         op: op_left,
         operand: operand_left,
+        span,
     } = left
     {
         if let Expression::UnaryOperation {
             op: op_right,
             operand: operand_right,
+            span: _,
         } = right
         {
             if op_left != op_right {
-                return Err(format!("right-hand side of the assignment doesn't match the pattern, expected unary operation {:?}", op_left));
+                return Err(pattern_error(format!("right-hand side of the assignment doesn't match the pattern, expected unary operation {:?}", op_left)));
             }
-            let res_operand = eval_assignment(&operand_left, &operand_right, vars)?;
-            return eval(
+            let res_operand =
+                eval_assignment(operand_left, operand_right, allow_literals, vars, ctx)?;
+            eval(
                 &Expression::UnaryOperation {
                     op: *op_left,
-                    operand: Box::new(Expression::Value(res_operand)),
+                    operand: Box::new(Expression::Value(res_operand, operand_left.span())),
+                    span: *span,
                 },
                 vars,
+                ctx,
             )
-            .map_err(|e| e.errmsg);
         } else {
-            Err(
+            Err(pattern_error(
                 "right-hand side of the assignment doesn't match the pattern, expected unary operation".into(),
-            )
+            ))
         }
     } else {
-        Err("assignment is only possible to a variable or a simple expression".into())
+        Err(pattern_error(
+            "assignment is only possible to a variable or a simple expression".into(),
+        ))
+    }
+}
+
+/// Elements of a tuple pattern like `a, (b, c), d`, or None if the expression is not a tuple
+fn tuple_pattern_elements(pattern: &Expression) -> Option<Vec<&Expression>> {
+    match pattern {
+        Expression::BinaryOperation {
+            op: BinaryOp::FormTuple,
+            left,
+            right,
+            span: _,
+        } => Some(vec![left.as_ref(), right.as_ref()]),
+        Expression::BinaryOperation {
+            op: BinaryOp::AppendToTuple,
+            left,
+            right,
+            span: _,
+        } => {
+            let mut elements = tuple_pattern_elements(left)?;
+            elements.push(right.as_ref());
+            Some(elements)
+        }
+        _ => None,
+    }
+}
+
+/// Destructuring of an already evaluated value into variables
+fn unpack_value(
+    pattern: &Expression,
+    value: Rc<Value>,
+    allow_literals: bool,
+    vars: &mut HashMap<String, Rc<Value>>,
+) -> Result<(), String> {
+    if let Expression::Variable(var_name, _) = pattern {
+        vars.insert(var_name.clone(), value);
+        return Ok(());
+    }
+    if let (Expression::Value(literal, _), true) = (pattern, allow_literals) {
+        if values_equal(literal, &value) {
+            return Ok(());
+        }
+        return Err(format!("{} doesn't match the pattern {}", value, literal));
+    }
+    let Some(pattern_elements) = tuple_pattern_elements(pattern) else {
+        return Err("only variables and tuples of them can be assigned from a value".into());
+    };
+    match value.as_ref() {
+        Value::Tuple(elements) if elements.len() == pattern_elements.len() => {
+            for (pattern_element, element) in pattern_elements.into_iter().zip(elements) {
+                unpack_value(pattern_element, element.clone(), allow_literals, vars)?;
+            }
+            Ok(())
+        }
+        Value::Tuple(elements) => Err(format!(
+            "cannot unpack {} values into {} variables",
+            elements.len(),
+            pattern_elements.len()
+        )),
+        other => Err(format!(
+            "cannot unpack {} into {} variables",
+            other.type_name(),
+            pattern_elements.len()
+        )),
     }
 }
 
 fn add(a: &Value, b: &Value) -> Option<Value> {
     match (a, b) {
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+            let ((r1, i1), (r2, i2)) = (as_complex(a)?, as_complex(b)?);
+            Some(complex((r1 + r2, i1 + i2)))
+        }
         (Value::Float(f1), Value::Float(f2)) => Some(Value::Float(f1 + f2)),
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Float(*i1 as f32 + *f2)),
         (Value::Float(_), Value::Int(_)) => add(b, a),
-        (Value::Int(i1), Value::Int(i2)) => Some(Value::Int(i1 + i2)),
+        (Value::Int(i1), Value::Int(i2)) => Some(
+            i1.checked_add(*i2)
+                .map(Value::Int)
+                .unwrap_or_else(|| int_or_big(BigInt::from(*i1) + i2)),
+        ),
+        (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+        | (Value::Int(_), Value::BigInt(_)) => Some(int_or_big(as_big_int(a)? + as_big_int(b)?)),
+        (Value::BigInt(bi), f @ Value::Float(_)) | (f @ Value::Float(_), Value::BigInt(bi)) => {
+            add(&Value::Float(big_to_float(bi)), f)
+        }
+        (r @ Value::Rational { .. }, other) | (other, r @ Value::Rational { .. }) => {
+            add(&Value::Float(rational_to_float(r)?), other)
+        }
         (Value::String(s1), Value::String(s2)) => {
             let mut res = s1.clone();
             res.push_str(s2);
             Some(Value::String(res))
         }
+        (Value::Bytes(b1), Value::Bytes(b2)) => Some(Value::Bytes([&b1[..], &b2[..]].concat())),
         (Value::Bool(b1), Value::Bool(b2)) => Some(Value::Bool(*b1 || *b2)),
+        (Value::Tuple(t1), Value::Tuple(t2)) => Some(Value::Tuple([&t1[..], &t2[..]].concat())),
+        (Value::List(l1), Value::List(l2)) => Some(Value::List([&l1[..], &l2[..]].concat())),
         _ => None,
     }
 }
 fn sub(a: &Value, b: &Value) -> Option<Value> {
     match (a, b) {
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+            let ((r1, i1), (r2, i2)) = (as_complex(a)?, as_complex(b)?);
+            Some(complex((r1 - r2, i1 - i2)))
+        }
         (Value::Float(f1), Value::Float(f2)) => Some(Value::Float(f1 - f2)),
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Float(*i1 as f32 - *f2)),
         (Value::Float(f1), Value::Int(i2)) => Some(Value::Float(*f1 - *i2 as f32)),
-        (Value::Int(i1), Value::Int(i2)) => Some(Value::Int(i1 - i2)),
+        (Value::Int(i1), Value::Int(i2)) => Some(
+            i1.checked_sub(*i2)
+                .map(Value::Int)
+                .unwrap_or_else(|| int_or_big(BigInt::from(*i1) - i2)),
+        ),
+        (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+        | (Value::Int(_), Value::BigInt(_)) => Some(int_or_big(as_big_int(a)? - as_big_int(b)?)),
+        (Value::BigInt(bi), f @ Value::Float(_)) => sub(&Value::Float(big_to_float(bi)), f),
+        (f @ Value::Float(_), Value::BigInt(bi)) => sub(f, &Value::Float(big_to_float(bi))),
+        (r @ Value::Rational { .. }, other) => sub(&Value::Float(rational_to_float(r)?), other),
+        (other, r @ Value::Rational { .. }) => sub(other, &Value::Float(rational_to_float(r)?)),
         _ => None,
     }
 }
 fn mul(a: &Value, b: &Value) -> Option<Value> {
     match (a, b) {
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+            Some(complex(complex_mul(as_complex(a)?, as_complex(b)?)))
+        }
         (Value::Float(f1), Value::Float(f2)) => Some(Value::Float(f1 * f2)),
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Float(*i1 as f32 * *f2)),
         (Value::Float(_), Value::Int(_)) => mul(b, a),
-        (Value::Int(i1), Value::Int(i2)) => Some(Value::Int(i1 * i2)),
-        (Value::String(s), Value::Int(i)) => Some(Value::String(s.repeat(*i as usize))),
+        (Value::Int(i1), Value::Int(i2)) => Some(
+            i1.checked_mul(*i2)
+                .map(Value::Int)
+                .unwrap_or_else(|| int_or_big(BigInt::from(*i1) * i2)),
+        ),
+        (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+        | (Value::Int(_), Value::BigInt(_)) => Some(int_or_big(as_big_int(a)? * as_big_int(b)?)),
+        (Value::BigInt(bi), f @ Value::Float(_)) | (f @ Value::Float(_), Value::BigInt(bi)) => {
+            mul(&Value::Float(big_to_float(bi)), f)
+        }
+        (r @ Value::Rational { .. }, other) | (other, r @ Value::Rational { .. }) => {
+            mul(&Value::Float(rational_to_float(r)?), other)
+        }
+        (Value::String(s), Value::Int(i)) => Some(Value::String(s.repeat(repetition_count(*i)))),
+        (Value::Tuple(t), Value::Int(i)) => Some(Value::Tuple(repeat_elements(t, *i))),
+        (Value::List(l), Value::Int(i)) => Some(Value::List(repeat_elements(l, *i))),
         (Value::Bool(b1), Value::Bool(b2)) => Some(Value::Bool(*b1 && *b2)),
         _ => None,
     }
 }
+/// Max length of a string, tuple or list produced by repeating it with `*`
+const MAX_REPETITION_LEN: usize = 1 << 24;
+
+/// Negative repetition counts produce an empty result
+fn repetition_count(count: Int) -> usize {
+    usize::try_from(count).unwrap_or(0)
+}
+
+fn repeat_elements(elements: &[Rc<Value>], count: Int) -> Vec<Rc<Value>> {
+    let count = repetition_count(count);
+    elements
+        .iter()
+        .cycle()
+        .take(elements.len() * count)
+        .cloned()
+        .collect()
+}
+
+fn repetition_len(a: &Value, b: &Value) -> usize {
+    let len = match a {
+        Value::String(s) => s.len(),
+        Value::Tuple(elements) | Value::List(elements) => elements.len(),
+        _ => return 0,
+    };
+    match b {
+        Value::Int(count) => len.saturating_mul(repetition_count(*count)),
+        _ => 0,
+    }
+}
+
 fn div(a: &Value, b: &Value) -> Option<Value> {
     match (a, b) {
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+            Some(complex(complex_div(as_complex(a)?, as_complex(b)?)))
+        }
         (Value::Float(f1), Value::Float(f2)) => Some(Value::Float(f1 / f2)),
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Float(*i1 as f32 / *f2)),
         (Value::Float(f1), Value::Int(i2)) => Some(Value::Float(*f1 / *i2 as f32)),
-        (Value::Int(i1), Value::Int(i2)) => Some(Value::Float((*i1 as f32) / (*i2 as f32))),
+        (r @ Value::Rational { .. }, other) => div(&Value::Float(rational_to_float(r)?), other),
+        (other, r @ Value::Rational { .. }) => div(other, &Value::Float(rational_to_float(r)?)),
+        (Value::BigInt(bi), other) => div(&Value::Float(big_to_float(bi)), other),
+        (other, Value::BigInt(bi)) => div(other, &Value::Float(big_to_float(bi))),
+        _ => None,
+    }
+}
+/// Remainder with the sign of the dividend, same as the `mod` builtin
+pub(crate) fn rem(a: &Value, b: &Value) -> Option<Value> {
+    match (a, b) {
+        (Value::Float(f1), Value::Float(f2)) => Some(Value::Float(f1 % f2)),
+        (Value::Int(i1), Value::Float(f2)) => Some(Value::Float(*i1 as f32 % *f2)),
+        (Value::Float(f1), Value::Int(i2)) => Some(Value::Float(*f1 % *i2 as f32)),
+        (Value::Int(i1), Value::Int(i2)) => Some(
+            i1.checked_rem(*i2)
+                .map(Value::Int)
+                .unwrap_or_else(|| int_or_big(BigInt::from(*i1) % i2)),
+        ),
+        (Value::Int(_) | Value::BigInt(_), Value::Int(_) | Value::BigInt(_)) => {
+            Some(int_or_big(as_big_int(a)? % as_big_int(b)?))
+        }
         _ => None,
     }
 }
+
+/// Fills `%d` (integers), `%f` and `%.Nf` (numbers, 6 decimal places by default), `%s`
+/// (any value, as displayed) and `%%` (literal percent sign) in the template. A tuple on the
+/// right supplies one argument per specifier, any other value is the only argument.
+fn format_template(template: &str, arg: &Value) -> Result<String, String> {
+    let args = match arg {
+        Value::Tuple(elements) => elements.clone(),
+        other => vec![Rc::new(other.clone())],
+    };
+    let mut args = args.iter();
+    let mut next_arg = |spec: &str| {
+        args.next().ok_or(format!(
+            "not enough arguments for format specifier \"%{}\"",
+            spec
+        ))
+    };
+    let mut formatted = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            formatted.push(ch);
+            continue;
+        }
+        let mut precision: Option<usize> = None;
+        if chars.next_if_eq(&'.').is_some() {
+            let mut digits = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+            precision = Some(
+                digits
+                    .parse()
+                    .map_err(|_| "missing precision after \"%.\"")?,
+            );
+        }
+        let spec = chars.next().map(String::from).unwrap_or_default();
+        match (spec.as_str(), precision) {
+            ("%", None) => formatted.push('%'),
+            ("s", None) => formatted.push_str(&next_arg(&spec)?.to_string()),
+            ("d", None) => match next_arg(&spec)?.as_ref() {
+                v @ (Value::Int(_) | Value::BigInt(_) | Value::Rational { den: 1, .. }) => {
+                    formatted.push_str(&v.to_string())
+                }
+                v => {
+                    return Err(format!(
+                        "format specifier \"%d\" requires an integer, got {}",
+                        v.type_name()
+                    ))
+                }
+            },
+            ("f", precision) => {
+                let v = next_arg(&spec)?;
+                let f = match v.as_ref() {
+                    Value::Float(f) => *f,
+                    Value::Int(i) => *i as f32,
+                    Value::BigInt(bi) => big_to_float(bi),
+                    r @ Value::Rational { .. } => rational_to_float(r).unwrap_or(f32::NAN),
+                    v => {
+                        return Err(format!(
+                            "format specifier \"%f\" requires a number, got {}",
+                            v.type_name()
+                        ))
+                    }
+                };
+                formatted.push_str(&format!("{:.*}", precision.unwrap_or(6), f));
+            }
+            _ => {
+                let precision = precision.map(|p| format!(".{}", p)).unwrap_or_default();
+                return Err(format!(
+                    "unsupported format specifier \"%{}{}\"",
+                    precision, spec
+                ));
+            }
+        }
+    }
+    if args.next().is_some() {
+        return Err("not all arguments were used in string formatting".into());
+    }
+    Ok(formatted)
+}
+
 fn pow(a: &Value, b: &Value) -> Option<Value> {
     match (a, b) {
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+            Some(complex(complex_pow(as_complex(a)?, b)?))
+        }
         (Value::Float(f1), Value::Float(f2)) => Some(Value::Float(f1.powf(*f2))),
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Float((*i1 as f32).powf(*f2))),
-        (Value::Float(f1), Value::Int(i2)) => Some(Value::Float(f1.powi(*i2))),
-        (Value::Int(i1), Value::Int(i2)) => Some(if *i2 > 0 {
-            Value::Int(i1.pow(*i2 as u32))
-        } else {
-            Value::Float((*i1 as f32).powi(*i2))
+        (Value::Float(f1), Value::Int(i2)) => Some(Value::Float(float_powi(*f1, *i2))),
+        (Value::Int(i1), Value::Int(i2)) => Some(match u32::try_from(*i2) {
+            Ok(exp) if exp > 0 => i1
+                .checked_pow(exp)
+                .map(Value::Int)
+                .unwrap_or_else(|| int_or_big(BigInt::from(*i1).pow(exp))),
+            _ => Value::Float(float_powi(*i1 as f32, *i2)),
         }),
+        (Value::BigInt(bi), Value::Int(i2)) if *i2 > 0 && u32::try_from(*i2).is_ok() => {
+            Some(int_or_big(bi.pow(*i2 as u32)))
+        }
+        (Value::Rational { num, den }, Value::Int(i2)) => {
+            let (num, den) = if *i2 >= 0 { (num, den) } else { (den, num) };
+            let exp = u32::try_from(i128::from(*i2).unsigned_abs()).ok();
+            match (
+                exp.and_then(|exp| i128::from(*num).checked_pow(exp)),
+                exp.and_then(|exp| i128::from(*den).checked_pow(exp)),
+            ) {
+                // a power with parts too big for a rational is approximate, like a negative
+                // power of an integer
+                (Some(n), Some(d)) => match rational(BigInt::from(n), BigInt::from(d)) {
+                    Ok(value) => Some(value),
+                    Err(_) => pow(&Value::Float(rational_to_float(a)?), b),
+                },
+                _ => pow(&Value::Float(rational_to_float(a)?), b),
+            }
+        }
+        (r @ Value::Rational { .. }, other) => pow(&Value::Float(rational_to_float(r)?), other),
+        (other, r @ Value::Rational { .. }) => pow(other, &Value::Float(rational_to_float(r)?)),
+        (Value::BigInt(bi), other) => pow(&Value::Float(big_to_float(bi)), other),
+        (other, Value::BigInt(bi)) => pow(other, &Value::Float(big_to_float(bi))),
         (Value::Bool(b1), Value::Bool(b2)) => Some(Value::Bool(b1 ^ b2)),
         _ => None,
     }
@@ -330,6 +1723,17 @@ fn lt(a: &Value, b: &Value) -> Option<Value> {
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Bool((*i1 as f32) < *f2)),
         (Value::Float(f1), Value::Int(i2)) => Some(Value::Bool(*f1 < *i2 as f32)),
         (Value::Int(i1), Value::Int(i2)) => Some(Value::Bool(i1 < i2)),
+        (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+        | (Value::Int(_), Value::BigInt(_)) => Some(Value::Bool(as_big_int(a)? < as_big_int(b)?)),
+        (Value::BigInt(bi), f @ Value::Float(_)) => lt(&Value::Float(big_to_float(bi)), f),
+        (f @ Value::Float(_), Value::BigInt(bi)) => lt(f, &Value::Float(big_to_float(bi))),
+        (Value::Rational { .. }, Value::Int(_) | Value::Rational { .. })
+        | (Value::Int(_), Value::Rational { .. }) => {
+            let ((n1, d1), (n2, d2)) = (as_rational(a)?, as_rational(b)?);
+            Some(Value::Bool(n1 * d2 < n2 * d1))
+        }
+        (r @ Value::Rational { .. }, other) => lt(&Value::Float(rational_to_float(r)?), other),
+        (other, r @ Value::Rational { .. }) => lt(other, &Value::Float(rational_to_float(r)?)),
         _ => None,
     }
 }
@@ -339,32 +1743,294 @@ fn gt(a: &Value, b: &Value) -> Option<Value> {
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Bool((*i1 as f32) > *f2)),
         (Value::Float(f1), Value::Int(i2)) => Some(Value::Bool(*f1 > *i2 as f32)),
         (Value::Int(i1), Value::Int(i2)) => Some(Value::Bool(i1 > i2)),
+        (Value::BigInt(_), Value::Int(_) | Value::BigInt(_))
+        | (Value::Int(_), Value::BigInt(_)) => Some(Value::Bool(as_big_int(a)? > as_big_int(b)?)),
+        (Value::BigInt(bi), f @ Value::Float(_)) => gt(&Value::Float(big_to_float(bi)), f),
+        (f @ Value::Float(_), Value::BigInt(bi)) => gt(f, &Value::Float(big_to_float(bi))),
+        (Value::Rational { .. }, Value::Int(_) | Value::Rational { .. })
+        | (Value::Int(_), Value::Rational { .. }) => {
+            let ((n1, d1), (n2, d2)) = (as_rational(a)?, as_rational(b)?);
+            Some(Value::Bool(n1 * d2 > n2 * d1))
+        }
+        (r @ Value::Rational { .. }, other) => gt(&Value::Float(rational_to_float(r)?), other),
+        (other, r @ Value::Rational { .. }) => gt(other, &Value::Float(rational_to_float(r)?)),
         _ => None,
     }
 }
+/// Numbers are compared across types, in tuples and lists too: elements are compared
+/// pairwise with the same rules at any depth, so `(1, (2,)) == (1.0, (2.0,))`
 fn eq(a: &Value, b: &Value) -> Option<Value> {
     match (a, b) {
+        (Value::Tuple(elements_a), Value::Tuple(elements_b))
+        | (Value::List(elements_a), Value::List(elements_b)) => Some(Value::Bool(
+            elements_a.len() == elements_b.len()
+                && elements_a
+                    .iter()
+                    .zip(elements_b)
+                    .all(|(a, b)| values_equal(a, b)),
+        )),
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => Some(Value::Bool(
+            as_complex(a).is_some_and(|a| Some(a) == as_complex(b)),
+        )),
         (Value::Int(i1), Value::Float(f2)) => Some(Value::Bool((*i1 as f32) == *f2)),
         (Value::Float(f1), Value::Int(i2)) => Some(Value::Bool(*f1 == *i2 as f32)),
+        (Value::BigInt(bi), Value::Float(f)) | (Value::Float(f), Value::BigInt(bi)) => {
+            Some(Value::Bool(big_to_float(bi) == *f))
+        }
+        (Value::Rational { .. }, Value::Int(_)) | (Value::Int(_), Value::Rational { .. }) => {
+            let ((n1, d1), (n2, d2)) = (as_rational(a)?, as_rational(b)?);
+            Some(Value::Bool(n1 * d2 == n2 * d1))
+        }
+        (r @ Value::Rational { .. }, f @ Value::Float(_))
+        | (f @ Value::Float(_), r @ Value::Rational { .. }) => {
+            eq(&Value::Float(rational_to_float(r)?), f)
+        }
         (a, b) => Some(Value::Bool(a == b)),
     }
 }
 
+fn not_eq(a: &Value, b: &Value) -> Option<Value> {
+    match eq(a, b)? {
+        Value::Bool(is_eq) => Some(Value::Bool(!is_eq)),
+        _ => None,
+    }
+}
+
+/// Comparison as in the `<` operator, `None` for values it isn't defined for
+pub fn values_less(a: &Value, b: &Value) -> Option<bool> {
+    match lt(a, b)? {
+        Value::Bool(is_less) => Some(is_less),
+        _ => None,
+    }
+}
+
+/// Equality as in the `==` operator
+pub fn values_equal(a: &Value, b: &Value) -> bool {
+    eq(a, b) == Some(Value::Bool(true))
+}
+
+/// Membership as in the `in` operator: an element equal to the value in a tuple or list, or a
+/// substring in a string
+pub fn collection_contains(collection: &Value, value: &Value) -> Result<bool, String> {
+    match (collection, value) {
+        (Value::Tuple(elements) | Value::List(elements), value) => {
+            Ok(elements.iter().any(|element| values_equal(element, value)))
+        }
+        (Value::String(s), Value::String(substring)) => Ok(s.contains(substring.as_str())),
+        (collection, value) => Err(format!(
+            "membership is not defined for {} in {}",
+            value.type_name(),
+            collection.type_name()
+        )),
+    }
+}
+
 fn neg(v: &Value) -> Option<Value> {
     match v {
         Value::Float(v) => Some(Value::Float(-v)),
-        Value::Int(v) => Some(Value::Int(-v)),
+        Value::Int(v) => Some(
+            v.checked_neg()
+                .map(Value::Int)
+                .unwrap_or_else(|| int_or_big(-BigInt::from(*v))),
+        ),
+        Value::BigInt(v) => Some(int_or_big(-v)),
+        Value::Complex { re, im } => Some(Value::Complex { re: -re, im: -im }),
         Value::Bool(b) => Some(Value::Bool(!b)),
         _ => None,
     }
 }
 
+fn is_int_float_mix(a: &Value, b: &Value) -> bool {
+    let is_exact =
+        |v: &Value| matches!(v, Value::Int(_) | Value::BigInt(_) | Value::Rational { .. });
+    match (a, b) {
+        (Value::Float(_), other) | (other, Value::Float(_)) => is_exact(other),
+        _ => false,
+    }
+}
+
+/// Exact zero, dividing by it is an error, while a float zero gives inf or nan
+pub(crate) fn is_zero(v: &Value) -> bool {
+    match v {
+        Value::Int(i) => *i == 0,
+        Value::Rational { num, .. } => *num == 0,
+        _ => false,
+    }
+}
+
+/// Result of integer arithmetic, big integers are only used when the value doesn't fit `Int`
+pub(crate) fn int_or_big(n: BigInt) -> Value {
+    match Int::try_from(&n) {
+        Ok(i) => Value::Int(i),
+        Err(_) => Value::BigInt(n),
+    }
+}
+
+fn as_big_int(v: &Value) -> Option<BigInt> {
+    match v {
+        Value::Int(i) => Some(BigInt::from(*i)),
+        Value::BigInt(bi) => Some(bi.clone()),
+        _ => None,
+    }
+}
+
+/// Real and imaginary parts of a number, real numbers have a zero imaginary part
+fn as_complex(v: &Value) -> Option<(f32, f32)> {
+    match v {
+        Value::Complex { re, im } => Some((*re, *im)),
+        Value::Int(i) => Some((*i as f32, 0.0)),
+        Value::BigInt(bi) => Some((big_to_float(bi), 0.0)),
+        Value::Rational { .. } => Some((rational_to_float(v)?, 0.0)),
+        Value::Float(f) => Some((*f, 0.0)),
+        _ => None,
+    }
+}
+
+fn complex((re, im): (f32, f32)) -> Value {
+    Value::Complex { re, im }
+}
+
+fn complex_mul((r1, i1): (f32, f32), (r2, i2): (f32, f32)) -> (f32, f32) {
+    (r1 * r2 - i1 * i2, r1 * i2 + i1 * r2)
+}
+
+fn complex_div((r1, i1): (f32, f32), (r2, i2): (f32, f32)) -> (f32, f32) {
+    let denominator = r2 * r2 + i2 * i2;
+    (
+        (r1 * r2 + i1 * i2) / denominator,
+        (i1 * r2 - r1 * i2) / denominator,
+    )
+}
+
+/// Integer powers are computed by repeated squaring, so that results like `1i ^ 2 == -1` are
+/// exact, the rest go through the polar form
+fn complex_pow(base: (f32, f32), exponent: &Value) -> Option<(f32, f32)> {
+    if let Value::Int(n) = exponent {
+        let mut result = (1.0, 0.0);
+        let mut square = base;
+        let mut remaining = n.unsigned_abs();
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = complex_mul(result, square);
+            }
+            square = complex_mul(square, square);
+            remaining >>= 1;
+        }
+        return Some(if *n < 0 {
+            complex_div((1.0, 0.0), result)
+        } else {
+            result
+        });
+    }
+    let exponent = as_complex(exponent)?;
+    let (re, im) = base;
+    if re == 0.0 && im == 0.0 {
+        return Some((0.0, 0.0));
+    }
+    let (exp_re, exp_im) = complex_mul(exponent, (re.hypot(im).ln(), im.atan2(re)));
+    let magnitude = exp_re.exp();
+    Some((magnitude * exp_im.cos(), magnitude * exp_im.sin()))
+}
+
+/// `powi` for any `Int` exponent
+fn float_powi(base: f32, exp: Int) -> f32 {
+    match i32::try_from(i128::from(exp)) {
+        Ok(exp) => base.powi(exp),
+        Err(_) => base.powf(exp as f32),
+    }
+}
+
+fn big_to_float(bi: &BigInt) -> f32 {
+    bi.to_f32().unwrap_or(f32::NAN)
+}
+
+/// Exact quotient reduced to lowest terms, a whole one is an integer of any size. A fraction
+/// with parts that don't fit `Int` is an error rather than an approximate float.
+fn rational(num: BigInt, den: BigInt) -> Result<Value, String> {
+    let (mut a, mut b) = (num.clone(), den.clone());
+    while b != BigInt::ZERO {
+        (a, b) = (b.clone(), a % b);
+    }
+    let gcd = if (den < BigInt::ZERO) == (a < BigInt::ZERO) {
+        a
+    } else {
+        -a
+    };
+    let (num, den) = (num / &gcd, den / gcd);
+    if den == BigInt::from(1) {
+        return Ok(int_or_big(num));
+    }
+    match (Int::try_from(&num), Int::try_from(&den)) {
+        (Ok(num), Ok(den)) => Ok(Value::Rational { num, den }),
+        _ => Err(format!(
+            "{}/{} is too big for a rational number, convert an operand to float",
+            num, den
+        )),
+    }
+}
+
+/// Whether the operation is on exact numbers and gives a fraction: a division of integers or
+/// arithmetic with a rational number
+fn is_fraction_op(op: BinaryOp, a: &Value, b: &Value) -> bool {
+    let is_exact =
+        |v: &Value| matches!(v, Value::Int(_) | Value::BigInt(_) | Value::Rational { .. });
+    let has_rational = matches!(a, Value::Rational { .. }) || matches!(b, Value::Rational { .. });
+    match op {
+        BinaryOp::Div => is_exact(a) && is_exact(b),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => is_exact(a) && is_exact(b) && has_rational,
+        _ => false,
+    }
+}
+
+/// Addition, subtraction, multiplication or division of exact numbers, done with big integer
+/// numerators and denominators so the result is exact
+fn fraction_op(op: BinaryOp, a: &Value, b: &Value) -> Result<Value, String> {
+    let (Some((n1, d1)), Some((n2, d2))) = (as_big_rational(a), as_big_rational(b)) else {
+        unreachable!("operands are checked by is_fraction_op")
+    };
+    let (num, den) = match op {
+        BinaryOp::Add => (n1 * &d2 + n2 * &d1, d1 * d2),
+        BinaryOp::Sub => (n1 * &d2 - n2 * &d1, d1 * d2),
+        BinaryOp::Mul => (n1 * n2, d1 * d2),
+        BinaryOp::Div => (n1 * d2, d1 * n2),
+        _ => unreachable!("operation is checked by is_fraction_op"),
+    };
+    rational(num, den)
+}
+
+fn as_rational(v: &Value) -> Option<(i128, i128)> {
+    match v {
+        Value::Int(i) => Some((i128::from(*i), 1)),
+        Value::Rational { num, den } => Some((i128::from(*num), i128::from(*den))),
+        _ => None,
+    }
+}
+
+fn as_big_rational(v: &Value) -> Option<(BigInt, BigInt)> {
+    match v {
+        Value::Int(i) => Some((BigInt::from(*i), BigInt::from(1))),
+        Value::BigInt(bi) => Some((bi.clone(), BigInt::from(1))),
+        Value::Rational { num, den } => Some((BigInt::from(*num), BigInt::from(*den))),
+        _ => None,
+    }
+}
+
+fn rational_to_float(v: &Value) -> Option<f32> {
+    match v {
+        Value::Rational { num, den } => Some(*num as f32 / *den as f32),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::parse;
+    use crate::profile::Profile;
+    use crate::span::Span;
     use crate::tokenize;
     use rstest::rstest;
+    use std::cell::RefCell;
 
     #[rstest]
     #[case("()", Value::Nothing)]
@@ -372,24 +2038,462 @@ mod tests {
     #[case("{};", Value::Nothing)]
     #[case("a = {};a", Value::Nothing)]
     #[case("1", Value::Int(1))]
-    #[case("1;", Value::Int(1))]
-    #[case("(1);", Value::Int(1))]
+    #[case("1;", Value::Nothing)]
+    #[case("(1);", Value::Nothing)]
+    #[case("1; # comment", Value::Nothing)]
+    #[case("1;;", Value::Nothing)]
+    #[case("{1;}", Value::Nothing)]
+    #[case("{1}", Value::Int(1))]
+    #[case("x = {1;}; x", Value::Nothing)]
     #[case("(((1)))", Value::Int(1))]
-    #[case("1 + 1;", Value::Int(2))]
-    #[case("1 + 2 * 3 ^ 2 * 5 + 10;", Value::Int(101))]
-    #[case("1 + (2 * (3 ^ 2) * 5) + 10;", Value::Int(101))]
-    #[case("10 / 5 / 2", Value::Float(1.0))]
-    #[case("10 * 5 / 2", Value::Float(25.0))]
-    #[case("5 / 5 * 2", Value::Float(2.0))]
+    #[case("1 + 1", Value::Int(2))]
+    #[case("1 + 2 * 3 ^ 2 * 5 + 10", Value::Int(101))]
+    #[case("1 + (2 * (3 ^ 2) * 5) + 10", Value::Int(101))]
+    #[case("10 / 5 / 2", Value::Int(1))]
+    #[case("10 * 5 / 2", Value::Int(25))]
+    #[case("5 / 5 * 2", Value::Int(2))]
+    #[case("1 / 3 + 1 / 3 == 2 / 3", Value::Bool(true))]
+    #[case("1 / 3 + 1 / 3", Value::Rational { num: 2, den: 3 })]
+    #[case("6 / -4", Value::Rational { num: -3, den: 2 })]
+    #[case("1 / 2 - 1", Value::Rational { num: -1, den: 2 })]
+    #[case("(2 / 3) * (3 / 4)", Value::Rational { num: 1, den: 2 })]
+    #[case("(1 / 2) / (1 / 4)", Value::Int(2))]
+    #[case("(2 / 3) ^ 2", Value::Rational { num: 4, den: 9 })]
+    #[case("(2 / 3) ^ -1", Value::Rational { num: 3, den: 2 })]
+    #[case("-(1 / 2)", Value::Rational { num: -1, den: 2 })]
+    #[case("4 / 2 == 2", Value::Bool(true))]
+    #[case("1 / 3 < 1 / 2", Value::Bool(true))]
+    #[case("1 / 2 + 0.5", Value::Float(1.0))]
+    #[case("1 / 2 == 0.5", Value::Bool(true))]
+    #[case("float(1 / 4)", Value::Float(0.25))]
+    #[case("float(2)", Value::Float(2.0))]
     #[case("a = 5; b = 6; a + b", Value::Int(11))]
     #[case("a = 5; b = 6; d = c = a + b; d", Value::Int(11))]
     #[case("2 + -3", Value::Int(-1))]
     #[case("-3 ^ 4", Value::Int(-81))]
+    #[case("-3^4", Value::Int(-81))]
+    #[case("-2^2", Value::Int(-4))]
+    #[case("2^-1", Value::Float(0.5))]
+    #[case("(-2)^2", Value::Int(4))]
+    #[case("-2 ^ -2", Value::Float(-0.25))]
+    #[case("1 - -2 ^ 2", Value::Int(5))]
+    #[case("2 ^ 3 ^ 2", Value::Int(512))]
+    #[case("(2 ^ 3) ^ 2", Value::Int(64))]
+    #[case(
+        "func factorial(n) { if n < 2 { 1 } else { n * factorial(n - 1) } }; factorial(20)",
+        int_or_big("2432902008176640000".parse().unwrap())
+    )]
+    #[case(
+        "2 ^ 100",
+        Value::BigInt("1267650600228229401496703205376".parse().unwrap())
+    )]
+    #[case("2 ^ 31", int_or_big("2147483648".parse().unwrap()))]
+    #[case("2 ^ 31 - 1", Value::Int(2147483647))]
+    #[case("-2147483648", Value::Int(-2147483648))]
+    #[case("-(-2147483648)", int_or_big("2147483648".parse().unwrap()))]
+    #[case("2147483647 + 1 - 1", Value::Int(2147483647))]
+    #[case("2 ^ 100 > 2 ^ 99", Value::Bool(true))]
+    #[case("2 ^ 100 / 2 ^ 99", Value::Int(2))]
+    #[case("(3 / 2) * 2 ^ 70 == 3 * 2 ^ 69", Value::Bool(true))]
+    #[case("type(2 ^ 70 / 4) == \"big integer\"", Value::Bool(true))]
+    #[case("-(2 / 6)", Value::Rational { num: -1, den: 3 })]
+    #[case("2 ^ 32 == 4294967296.0", Value::Bool(true))]
+    #[case("(1, 2) + (3, 4) == (1, 2, 3, 4)", Value::Bool(true))]
+    #[case("((1, 2), 3) + (4, 5) == ((1, 2), 3, 4, 5)", Value::Bool(true))]
+    #[case("[1, 2] + [3, 4] == [1, 2, 3, 4]", Value::Bool(true))]
+    #[case("[] + [1] + []", Value::List(vec![Rc::new(Value::Int(1))]))]
+    #[case("[(1, 2)] == [(1, 2)]", Value::Bool(true))]
+    #[case("[(1, 2), 3,]", Value::List(vec![
+        Rc::new(Value::Tuple(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(2))])),
+        Rc::new(Value::Int(3)),
+    ]))]
+    #[case("[1, 2] == (1, 2)", Value::Bool(false))]
+    #[case("(1, 2) == (2, 1)", Value::Bool(false))]
+    #[case("[(1, 2), 3] == [(1, 2), 3]", Value::Bool(true))]
+    #[case("(1, 2) * 3 == (1, 2, 1, 2, 1, 2)", Value::Bool(true))]
+    #[case("[1] * 2 == [1, 1]", Value::Bool(true))]
+    #[case("(1, 2) * 0", Value::Tuple(vec![]))]
+    #[case("(1, 2) * -1", Value::Tuple(vec![]))]
+    #[case("[1, 2] * -5", Value::List(vec![]))]
+    #[case("\"ab\" * -1", Value::String("".into()))]
+    #[case("a = [(1, 2), [3]]; copy(a) == a", Value::Bool(true))]
+    #[case("copy(5)", Value::Int(5))]
+    #[case("true and false", Value::Bool(false))]
+    #[case("false or 1 < 2", Value::Bool(true))]
+    #[case("false and undefined", Value::Bool(false))]
+    #[case("true or 1 / 0", Value::Bool(true))]
+    #[case("false or true and false", Value::Bool(false))]
+    #[case("flag = true; flag and= 1 < 2; flag", Value::Bool(true))]
+    #[case("flag = true; flag and= 2 < 1; flag", Value::Bool(false))]
+    #[case("flag = false; flag and= 1 / 0 == 1; flag", Value::Bool(false))]
+    #[case("flag = false; flag or= 2 > 1; flag", Value::Bool(true))]
+    #[case("flag = true; flag or= undefined; flag", Value::Bool(true))]
+    #[case("flag = true; flag and= true", Value::Bool(true))]
+    #[case("x = 1 < 2; x", Value::Bool(true))]
+    #[case("if (x = 5) { x } else { 0 }", Value::Int(5))]
+    #[case("if x = \"\" { 1 } else { x + \"!\" }", Value::String("!".into()))]
+    #[case(
+        "n = 3; total = 0; while (n = n - 1) { total = total + n }; total",
+        Value::Int(3)
+    )]
+    #[case("s = \"abc\"; out = \"\"; while s = drop(s, 1) { out = out + s }; out", Value::String("bcc".into()))]
+    #[case("if () { 1 } else { 2 }", Value::Int(2))]
+    #[case("if [0] { 1 } else { 2 }", Value::Int(1))]
+    #[case("if 1 / 2 { 1 } else { 2 }", Value::Int(1))]
+    #[case("if 0.0 { 1 } else { 2 }", Value::Int(2))]
+    #[case("x = 0; repeat(3) { x = x + 2 }; x", Value::Int(6))]
+    #[case("x = 1; repeat 4 { x = x * 2 }", Value::Int(16))]
+    #[case("repeat(0) { 1 }", Value::Nothing)]
+    #[case("func f(n) { repeat(n) { return 7 }; 0 }; f(5)", Value::Int(7))]
+    #[case("func f(n) { repeat(n) { return 7 }; 0 }; f(0)", Value::Int(0))]
+    #[case(
+        "func by_second((a, x), (b, y)) { x < y }; sort_by([(1, 3), (2, 1), (3, 2)], by_second)",
+        Value::List(vec![
+            Rc::new(Value::Tuple(vec![Rc::new(Value::Int(2)), Rc::new(Value::Int(1))])),
+            Rc::new(Value::Tuple(vec![Rc::new(Value::Int(3)), Rc::new(Value::Int(2))])),
+            Rc::new(Value::Tuple(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(3))])),
+        ])
+    )]
+    #[case(
+        "func desc(a, b) { sign(b - a) }; sort_by((3, 1, 4, 1, 5), desc) == (5, 4, 3, 1, 1)",
+        Value::Bool(true)
+    )]
+    #[case(
+        "func by_len(a, b) { length(a) < length(b) }; sort_by((\"bb\", \"a\", \"cc\", \"d\"), by_len) == (\"a\", \"d\", \"bb\", \"cc\")",
+        Value::Bool(true)
+    )]
+    #[case("func any(a, b) { true }; sort_by([], any)", Value::List(vec![]))]
+    #[case("all((true, true, false)) == false", Value::Bool(true))]
+    #[case("any((false, false, true)) == true", Value::Bool(true))]
+    #[case("all([true, 1 < 2])", Value::Bool(true))]
+    #[case("any([false])", Value::Bool(false))]
+    #[case("all(()) == true", Value::Bool(true))]
+    #[case("any(()) == false", Value::Bool(true))]
+    #[case("all([])", Value::Bool(true))]
+    #[case("any((true, 5))", Value::Bool(true))]
+    #[case("count(((1,2,1,3,1), 1)) == 3", Value::Bool(true))]
+    #[case("count((\"banana\", \"na\")) == 2", Value::Bool(true))]
+    #[case("count([1, 1.0, 2], 1)", Value::Int(2))]
+    #[case("count((1, 2), 3)", Value::Int(0))]
+    #[case("count(\"aaaa\", \"aa\")", Value::Int(2))]
+    #[case(r#""\x41" == "A""#, Value::Bool(true))]
+    #[case(r#"length("\u{1F600}")"#, Value::Int(1))]
+    #[case(r#""a\"b\\c\td""#, Value::String("a\"b\\c\td".into()))]
+    #[case(
+        "func fib(n) if (n < 3) 1 else fib(n - 1) + fib(n - 2); fib = memoize(fib); fib(40)",
+        Value::Int(102334155)
+    )]
+    #[case(
+        "func double(x) x * 2; f = memoize(double); f(3) + f(3)",
+        Value::Int(12)
+    )]
+    #[case("memoize(print) == memoize(print)", Value::Bool(false))]
+    #[case("func add(a, b) a + b; curry((add, 10))(5) == 15", Value::Bool(true))]
+    #[case(
+        "func sum3(a, b, c) a + b + c; add1 = curry(sum3, 1); add1(2, 3)",
+        Value::Int(6)
+    )]
+    #[case("inc = curry(log, 1); inc()", Value::Float(0.0))]
+    #[case("12345 |> str |> length", Value::Int(5))]
+    #[case(
+        "typeswitch 1 { integer: \"int\", string: \"str\", _: \"other\" }",
+        Value::String("int".into())
+    )]
+    #[case(
+        "typeswitch (1, 2) { integer: \"int\", string: \"str\", _: \"other\" }",
+        Value::String("other".into())
+    )]
+    #[case(
+        "x = 1.5; typeswitch x { _: 0, \"floating point number\": x * 2 }",
+        Value::Int(0)
+    )]
+    #[case(
+        "x = 1.5; typeswitch x { \"floating point number\": x * 2, _: 0 }",
+        Value::Float(3.0)
+    )]
+    #[case("typeswitch \"a\" { integer: 1 }", Value::Nothing)]
+    #[case("typeswitch print { \"built-in function\": 1, }", Value::Int(1))]
+    #[case("x = 0 |> exp |> log; x", Value::Float(0.0))]
+    #[case("(3, 4) |> mod", Value::Int(3))]
+    #[case("2 |> curry(mod, 7) |> str", Value::String("1".into()))]
+    #[case("curry(mod, 7)(4)", Value::Int(3))]
+    #[case(
+        "func apply(f) f(2); g = memoize(apply); g(sign) + g(sign)",
+        Value::Int(2)
+    )]
+    #[case("7 % 3", Value::Int(1))]
+    #[case("-7 % 3", Value::Int(-1))]
+    #[case("7.5 % 2", Value::Float(1.5))]
+    #[case("2 * 7 % 4", Value::Int(6))]
+    #[case("10000000000 % 7", Value::Int(4))]
+    #[case(r#""%d items" % 3 == "3 items""#, Value::Bool(true))]
+    #[case(r#""%s has %d%%" % ("x", 50)"#, Value::String("x has 50%".into()))]
+    #[case(r#""%.2f, %f" % (1 / 3, 2)"#, Value::String("0.33, 2.000000".into()))]
+    #[case(r#"b"hi\x00\xff""#, Value::Bytes(vec![b'h', b'i', 0, 255]))]
+    #[case(r#"byte_at(b"AB", 1)"#, Value::Int(66))]
+    #[case(r#"byte_at(b"\xff", 0)"#, Value::Int(255))]
+    #[case(r#"b"ab" + b"\x63" == b"abc""#, Value::Bool(true))]
+    #[case(r#"length(b"\x00\x01\x02")"#, Value::Int(3))]
+    #[case(r#"b"a" == "a""#, Value::Bool(false))]
+    #[case("hash(1) == hash(1)", Value::Bool(true))]
+    #[case(r#"hash("a") == hash("b")"#, Value::Bool(false))]
+    #[case("hash((1, [2, 3])) == hash((1, [2, 3]))", Value::Bool(true))]
+    #[case("hash(1) == hash(1.0)", Value::Bool(false))]
+    #[case("hash(2 ^ 40) > 0", Value::Bool(true))]
+    #[case(r#"type(10.0 / 5) == "floating point number""#, Value::Bool(true))]
+    #[case(r#"type(10 / 4) == "rational number""#, Value::Bool(true))]
+    #[case(r#"type(10 / 5) == "integer""#, Value::Bool(true))]
+    #[case(r#"type(1) == "integer""#, Value::Bool(true))]
+    #[case(r#"type(1.0) == "floating point number""#, Value::Bool(true))]
+    #[case(
+        r#"max_by(["a", "abc", "ab"], length)"#,
+        Value::String("abc".into())
+    )]
+    #[case(r#"min_by(("ab", "c", "d"), length)"#, Value::String("c".into()))]
+    #[case("func square(x) x * x; max_by((3, -5, 4), square)", Value::Int(-5))]
+    #[case("max_by([1, 2, 2.0], sign)", Value::Int(1))]
+    #[case("1 != 2", Value::Bool(true))]
+    #[case("1 != 1.0", Value::Bool(false))]
+    #[case("(1, 2) != (1, 2)", Value::Bool(false))]
+    #[case("() != ()", Value::Bool(false))]
+    #[case("x = 1; (x = 2) != x", Value::Bool(false))]
+    #[case(
+        "func item(i) if (i < 3) { (i + 1) * 10 } else (); i = 0; total = 0;
+        while (x = item(i)) != () { total = total + x; i = i + 1 }; (total, x)",
+        Value::Tuple(vec![Rc::new(Value::Int(60)), Rc::new(Value::Nothing)])
+    )]
     #[case("log(1)", Value::Float(0.0))]
     #[case("exp(0)", Value::Float(1.0))]
     #[case("a = exp; a(0)", Value::Float(1.0))]
+    #[case("inf > 1000000000000.0", Value::Bool(true))]
+    #[case("-inf < -1000000000000.0", Value::Bool(true))]
+    #[case("1 / inf", Value::Float(0.0))]
+    #[case("is_nan(nan)", Value::Bool(true))]
+    #[case("is_nan(inf - inf)", Value::Bool(true))]
+    #[case("is_nan(1.5)", Value::Bool(false))]
+    #[case("1 / 0.0 == inf", Value::Bool(true))]
+    #[case("1.5 / -0.0 == -inf", Value::Bool(true))]
+    #[case("is_nan(5 % 0.0)", Value::Bool(true))]
+    #[case("inf = 1; inf + 1", Value::Int(2))]
+    #[case("sign(-5) == -1", Value::Bool(true))]
+    #[case("sign(0) == 0", Value::Bool(true))]
+    #[case("sign(2.3) == 1", Value::Bool(true))]
+    #[case("sign(-0.0)", Value::Int(0))]
+    #[case("sign(-inf)", Value::Int(-1))]
+    #[case("take(((1, 2, 3), 2)) == (1, 2)", Value::Bool(true))]
+    #[case("take((1, 2, 3), 5) == (1, 2, 3)", Value::Bool(true))]
+    #[case("take((1, 2, 3), 0)", Value::Tuple(vec![]))]
+    #[case("drop((1, 2, 3), 1) == (2, 3)", Value::Bool(true))]
+    #[case("drop((1, 2, 3), 3)", Value::Tuple(vec![]))]
+    #[case("take(([1, 2, 3], 2)) == [1, 2]", Value::Bool(true))]
+    #[case("drop([1, 2, 3], 1) == [2, 3]", Value::Bool(true))]
+    #[case("drop([1, 2, 3], 5)", Value::List(vec![]))]
+    #[case("drop((\"hello\", 2)) == \"llo\"", Value::Bool(true))]
+    #[case("take(\"hello\", 2)", Value::String("he".into()))]
+    #[case("drop(\"hello\", 10)", Value::String("".into()))]
+    #[case(
+        "func isEven(x) { mod(x, 2) == 0 }; filter((isEven, (1, 2, 3, 4))) == (2, 4)",
+        Value::Bool(true)
+    )]
+    #[case("filter(is_nan, (1.0, 2.0))", Value::Tuple(vec![]))]
+    #[case(
+        "func isVowel(c) { index_of(\"aeiou\", c) > -1 }; filter(isVowel, \"calculator\")",
+        Value::String("auao".into())
+    )]
+    #[case("index_of(((10, 20, 30), 20)) == 1", Value::Bool(true))]
+    #[case("index_of((10, 20, 30), 20.0)", Value::Int(1))]
+    #[case("index_of((10, 20, 30), 40)", Value::Int(-1))]
+    #[case("index_of(((1, 2), (3, 4)), (3, 4))", Value::Int(1))]
+    #[case("index_of(([10, 20, 30], 20))", Value::Int(1))]
+    #[case(
+        "func even(n) n % 2 == 0; filter((even, [1, 2, 3, 4])) == [2, 4]",
+        Value::Bool(true)
+    )]
+    #[case("length([1])", Value::Int(1))]
+    #[case("length([])", Value::Int(0))]
+    #[case(
+        "enumerate((\"a\", 2.5, ())) == ((0, \"a\"), (1, 2.5), (2, ()))",
+        Value::Bool(true)
+    )]
+    #[case("enumerate(\"hi\") == ((0, \"h\"), (1, \"i\"))", Value::Bool(true))]
+    #[case("enumerate([5]) == [(0, 5)]", Value::Bool(true))]
+    #[case("enumerate(\"\")", Value::Tuple(vec![]))]
+    #[case("deep_eq(((1,), 2), ((1.0,), 2.0)) == True", Value::Bool(true))]
+    #[case("deep_eq([1, (2, 3)], [1, (2.0, 3.5)])", Value::Bool(false))]
+    #[case("deep_eq((1, 2), [1, 2])", Value::Bool(false))]
+    #[case("deep_eq((1, 2), (1, 2, 3))", Value::Bool(false))]
+    #[case("deep_eq(1/2, 0.5)", Value::Bool(true))]
+    #[case("2 in (1, 2, 3)", Value::Bool(true))]
+    #[case("2.0 in [1, 2]", Value::Bool(true))]
+    #[case("(1, 2) in ((1, 2), 3)", Value::Bool(true))]
+    #[case("4 in (1, 2, 3)", Value::Bool(false))]
+    #[case("\"ell\" in \"hello\"", Value::Bool(true))]
+    #[case("\"\" in \"\"", Value::Bool(true))]
+    #[case("\"elo\" in \"hello\" == false", Value::Bool(true))]
+    #[case("1 + 1 in (2, 3) and 3 in (2, 3)", Value::Bool(true))]
+    #[case("contains((1, 2, 3), 3)", Value::Bool(true))]
+    #[case("4 not in (1,2,3)", Value::Bool(true))]
+    #[case("2 not in (1,2,3)", Value::Bool(false))]
+    #[case("not true == false", Value::Bool(true))]
+    #[case("not 1 == 2", Value::Bool(false))]
+    #[case("not 1 == -1", Value::Bool(true))]
+    #[case("not not true and not false", Value::Bool(true))]
+    #[case("not 2 in (1, 2)", Value::Bool(false))]
+    #[case("while true break", Value::Nothing)]
+    #[case(
+        "n = 0; repeat(3) { repeat(5) { n = n + 1; break } }; n",
+        Value::Int(3)
+    )]
+    #[case(
+        "i = 0; outer: while true { while true { i = i + 1; break outer }; i = 100 }; i",
+        Value::Int(1)
+    )]
+    #[case(
+        "n = 0; outer: repeat(3) { inner: repeat(3) { n = n + 1; if (n == 2) break outer } }; n",
+        Value::Int(2)
+    )]
+    #[case(
+        "func f(n) { repeat(n) { while true return 7 }; 0 }; f(5)",
+        Value::Int(7)
+    )]
+    #[case("\"z\" not in \"abc\" and 1 not in []", Value::Bool(true))]
+    #[case("pad_left((\"7\", 3, \"0\")) == \"007\"", Value::Bool(true))]
+    #[case(
+        "func fact(0) 1; func fact(n) n * fact(n - 1); fact(5)",
+        Value::Int(120)
+    )]
+    #[case(
+        "func f(0) \"zero\"; func f(n) n; f(0) == \"zero\" and f(7) == 7",
+        Value::Bool(true)
+    )]
+    #[case(
+        "func f(0, x) x; func f(n, x) f(n - 1, x + 1); f(3, 10)",
+        Value::Int(13)
+    )]
+    #[case("func f(n) 1; func f(n) 2; f(0)", Value::Int(2))]
+    #[case("h = help(); \"log\" in h and \"print\" in h", Value::Bool(true))]
+    #[case("help(sqrt)", Value::String("sqrt: square root, complex for negative numbers".into()))]
+    #[case("unique((1, 1, 2, 3, 3))", Value::Tuple(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(2)), Rc::new(Value::Int(3))]))]
+    #[case("str(unique((2, 1.0, 1, 2.0, \"a\", 1, \"a\")))", Value::String("(2, 1.0, a)".into()))]
+    #[case(
+        "unique([(1, 2), (1.0, 2.0), (2, 1)]) == [(1, 2), (2, 1)]",
+        Value::Bool(true)
+    )]
+    #[case("unique(())", Value::Nothing)]
+    #[case("flatten(((1,2),(3,(4,5)))) == (1,2,3,4,5)", Value::Bool(true))]
+    #[case("flatten([(1, [2]), 3, []]) == [1, 2, 3]", Value::Bool(true))]
+    #[case(
+        "flatten(((1, (2, 3)), (4, 5)), 1) == (1, (2, 3), 4, 5)",
+        Value::Bool(true)
+    )]
+    #[case("flatten(((1, (2, 3)), 4), 0) == ((1, (2, 3)), 4)", Value::Bool(true))]
+    #[case("flatten(((1, (2, 3)), 4), 2) == (1, 2, 3, 4)", Value::Bool(true))]
+    #[case("(1,2,3,4,5)[::2] == (1,3,5)", Value::Bool(true))]
+    #[case("(1,2,3,4,5)[::-1] == (5,4,3,2,1)", Value::Bool(true))]
+    #[case(
+        "x = (1, 2, 3, 4, 5); x[1:3] == (2, 3) and x[-2:] == (4, 5)",
+        Value::Bool(true)
+    )]
+    #[case("(1, 2, 3, 4, 5)[3:1:-1] == (4, 3)", Value::Bool(true))]
+    #[case("(1, 2, 3, 4, 5)[-1::-2] == (5, 3, 1)", Value::Bool(true))]
+    #[case("(1, 2, 3)[10:]", Value::Nothing)]
+    #[case("[1, 2, 3][::-1] == [3, 2, 1]", Value::Bool(true))]
+    #[case("\"hello\"[::-1]", Value::String("olleh".into()))]
+    #[case("b\"abc\"[1:] == b\"bc\"", Value::Bool(true))]
+    #[case("x = (1, 2, 3); x[0] + x[-1]", Value::Int(4))]
+    #[case("\"hello\"[1]", Value::String("e".into()))]
+    #[case("func f(n) { (n, n + 1) }; f(1)[1]", Value::Int(2))]
+    #[case("assert_eq((1 + 1, 2.0))", Value::Nothing)]
+    #[case("assert_eq([1, (2, 3)], [1, (2, 3)])", Value::Nothing)]
+    #[case(
+        "x = 1; y = { let x = 2; x * 10 }; (x, y) == (1, 20)",
+        Value::Bool(true)
+    )]
+    #[case("x = 1; { let x = 2; x = 3 }; x", Value::Int(1))]
+    #[case("x = 1; { let a, x = 5, 6; { let x = 7 }; x }", Value::Int(6))]
+    #[case("x = 1; { y = 2; let x = 3 }; y + x", Value::Int(3))]
+    #[case("let x = 5; x", Value::Int(5))]
+    #[case(
+        "counter = 0; func inc() { global counter; counter = counter + 1 }; inc(); inc(); inc(); counter",
+        Value::Int(3)
+    )]
+    #[case("n = 0; func f() { n = 5 }; f(); n", Value::Int(0))]
+    #[case(
+        "func f() { global a, b; a, b = 1, 2 }; func g() { f(); a * 10 }; x = g(); (x, a + b) == (10, 3)",
+        Value::Bool(true)
+    )]
+    #[case(
+        "total = 0; func add(n) { global total; if n > 0 { total = total + n; add(n - 1) } }; add(4); total",
+        Value::Int(10)
+    )]
+    #[case(
+        "x = 1; func g() { global x; {let x = 5; x} }; (g(), x) == (5, 1)",
+        Value::Bool(true)
+    )]
+    #[case("parse_int((\"ff\", 16)) == 255", Value::Bool(true))]
+    #[case("parse_int(\"-42\")", Value::Int(-42))]
+    #[case("parse_int((\"-101\", 2))", Value::Int(-5))]
+    #[case("parse_int((\"Zz\", 36))", Value::Int(1295))]
+    #[case(
+        "parse_int(\"99999999999999999999\") == 10 ^ 20 - 1",
+        Value::Bool(true)
+    )]
+    #[case("parse_float(\"1.5\")", Value::Float(1.5))]
+    #[case("parse_float(\"-2e3\")", Value::Float(-2000.0))]
+    #[case("get((1, 2, 3), 1, 0)", Value::Int(2))]
+    #[case("get([1, 2], 5, \"none\") == \"none\"", Value::Bool(true))]
+    #[case("get([1, 2], -1, 0)", Value::Int(2))]
+    #[case("get([1, 2], -3, 0)", Value::Int(0))]
+    #[case("get(\"abc\", 0, \"?\") == \"a\"", Value::Bool(true))]
+    #[case("get(\"abc\", 3, \"?\") == \"?\"", Value::Bool(true))]
+    #[case("get((), 0, 7)", Value::Int(7))]
+    #[case("get([1], 10 ^ 30, 0)", Value::Int(0))]
+    #[case("get(\"abc\", -(10 ^ 30), 0)", Value::Int(0))]
+    #[case("func g(x) x; func f(y) { g(return 5); 0 }; f(1)", Value::Int(5))]
+    #[case("func g(a, b) a; func f(y) { g(1, return 5); 0 }; f(1)", Value::Int(5))]
+    #[case(
+        "func g(x) x; n = 0; while true { n = n + 1; g(break) }; n",
+        Value::Int(1)
+    )]
+    #[case(
+        "func g(x) x; n = 0; outer: while true { while true { n = n + 1; g(break outer) } }; n",
+        Value::Int(1)
+    )]
+    #[case(r#""C:\dir""#, Value::String(r"C:\dir".into()))]
+    #[case(r#""\q\\q""#, Value::String(r"\q\q".into()))]
+    #[case(r#"b"\d""#, Value::Bytes(b"\\d".to_vec()))]
+    #[case("lerp((0, 10, 0.5)) == 5.0", Value::Bool(true))]
+    #[case("lerp((0, 10, 0)) == 0.0", Value::Bool(true))]
+    #[case("lerp(2, 4, 0.5)", Value::Float(3.0))]
+    #[case("lerp(0, 10, -1)", Value::Float(-10.0))]
+    #[case("pad_right(\"ab\", 4)", Value::String("ab  ".into()))]
+    #[case("pad_left(\"long\", 2, \"-\")", Value::String("long".into()))]
+    #[case("pad_right(\"\", 3, \"*\")", Value::String("***".into()))]
+    #[case("index_of((\"hello\", \"ll\")) == 2", Value::Bool(true))]
+    #[case("index_of(\"hello\", \"x\")", Value::Int(-1))]
+    #[case("ord(\"A\") == 65", Value::Bool(true))]
+    #[case("chr(65) == \"A\"", Value::Bool(true))]
+    #[case("chr(ord(\"z\"))", Value::String("z".into()))]
+    #[case("char_at(\"hello\", 1)", Value::String("e".into()))]
     #[case("{1} + {2}", Value::Int(3))]
     #[case("{1} + {2}", Value::Int(3))]
+    #[case("{a = 1; a} * 2", Value::Int(2))]
+    #[case("{ {1} + {2} } * { {3} }", Value::Int(9))]
+    #[case("{}", Value::Nothing)]
+    #[case("{ 1; 2 } + {3}", Value::Int(5))]
+    #[case("-{2} ^ {3}", Value::Int(-8))]
+    #[case("a = 1; {a = 5}; a", Value::Int(5))]
+    #[case("[{1}, {2; 3}]", Value::List(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(3))]))]
+    #[case(
+        "func f(x) { y = { if (x > 0) return 1; 2 } * 10; y }; (f(1), f(-1))",
+        Value::Tuple(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(20))])
+    )]
+    #[case("func f(x) { { { return x } }; 5 }; f(3)", Value::Int(3))]
+    #[case(
+        "func f(x) 1 + { if (x) { return 2 }; 3 }; (f(true), f(false))",
+        Value::Tuple(vec![Rc::new(Value::Int(2)), Rc::new(Value::Int(4))])
+    )]
+    #[case("func f(x) x * [1, { return x }]; f(7) + 1", Value::Int(8))]
     #[case("True", Value::Bool(true))]
     #[case("tRuE", Value::Bool(true))]
     #[case("true + false", Value::Bool(true))]
@@ -422,7 +2526,57 @@ mod tests {
     #[case("return return 1", Value::Returned(Rc::new(Value::Int(1))))]
     #[case("if !(1 == 2) {return 1}; return 2", Value::Int(1))]
     #[case("if (1 == 2) {return 1}; return 2", Value::Int(2))]
-    #[case("if (1 == 2) {return 1}; 2;", Value::Int(2))]
+    #[case("if (1 == 2) {return 1}; 2", Value::Int(2))]
+    #[case("if true { 1 + {return 2} }; 3", Value::Int(2))]
+    #[case("func f(x) { 1 + {return x} }; f(5)", Value::Int(5))]
+    #[case("func f(x) { {return x} * 2 }; f(5) + 1", Value::Int(6))]
+    #[case("func f(x) { -{return x} }; f(5)", Value::Int(5))]
+    #[case("func f(x) { print({return x}) }; f(5)", Value::Int(5))]
+    #[case("() ?? 5", Value::Int(5))]
+    #[case("3 ?? 5", Value::Int(3))]
+    #[case("(() ?? 5) == 5", Value::Bool(true))]
+    #[case("a = () ?? () ?? 1; a", Value::Int(1))]
+    #[case("a = if false 1; a ?? 2", Value::Int(2))]
+    #[case("3 ?? undefined_variable", Value::Int(3))]
+    #[case("x = 1; 3 ?? {x = 2}; x", Value::Int(1))]
+    #[case("x = 1; () ?? {x = 2}; x", Value::Int(2))]
+    #[case("7 mod 3 == 1", Value::Bool(true))]
+    #[case("10 mod 4 mod 3", Value::Int(2))]
+    #[case("1 + 7 mod 3 * 2", Value::Int(3))]
+    #[case("mod(7, 3)", Value::Int(1))]
+    #[case("mod(7, 3) mod 2", Value::Int(1))]
+    #[case("mod(-7, 3)", Value::Int(-1))]
+    #[case("mod(-2147483648, -1)", Value::Int(0))]
+    #[case("(1i) * (1i) == -1", Value::Bool(true))]
+    #[case("1i ^ 2 == -1", Value::Bool(true))]
+    #[case("(3 + 4i) * (1 - 2i)", Value::Complex { re: 11.0, im: -2.0 })]
+    #[case("(1 + 2i) / (3 - 4i)", Value::Complex { re: -0.2, im: 0.4 })]
+    #[case("(3 + 4i) - 3 == 4i", Value::Bool(true))]
+    #[case("-(1 + 1i) == -1 - 1i", Value::Bool(true))]
+    #[case("2i ^ -1", Value::Complex { re: 0.0, im: -0.5 })]
+    #[case("1 / 2 + 0.5i", Value::Complex { re: 0.5, im: 0.5 })]
+    #[case("abs(3 + 4i)", Value::Float(5.0))]
+    #[case("abs(3 + 4i) == 5.0", Value::Bool(true))]
+    #[case("abs((3, 4)) == 5.0", Value::Bool(true))]
+    #[case("abs((1, -2, 2))", Value::Float(3.0))]
+    #[case("abs((3i, 4.0))", Value::Float(5.0))]
+    #[case("abs(-3)", Value::Int(3))]
+    #[case("to_binary(10) == \"1010\"", Value::Bool(true))]
+    #[case("to_hex(255) == \"ff\"", Value::Bool(true))]
+    #[case("to_binary(0)", Value::String("0".into()))]
+    #[case("to_hex(-255)", Value::String("-ff".into()))]
+    #[case("to_hex(2 ^ 64)", Value::String("10000000000000000".into()))]
+    #[case("sqrt(-4) == 2i", Value::Bool(true))]
+    #[case("sqrt(2i) == 1 + 1i", Value::Bool(true))]
+    #[case("sqrt(9)", Value::Float(3.0))]
+    #[case("1i == \"1i\"", Value::Bool(false))]
+    #[case("() ?. something == ()", Value::Bool(true))]
+    #[case("\"abc\" ?. length", Value::Int(3))]
+    #[case("\"ab\" ?. length ?. float", Value::Float(2.0))]
+    #[case("x = (); x ?. length ?. float", Value::Nothing)]
+    #[case("func inc(x) x + 1; 1 ?. inc ?. inc", Value::Int(3))]
+    #[case("x = 1; () ?. {x = 2}; x", Value::Int(1))]
+    #[case("x = (); x ?. length ?? 0", Value::Int(0))]
     #[case("while (1 == 2) {};", Value::Nothing)]
     #[case(
         "a = 1; while true { str = \"b\" * a; a = a + 1; print(str); if length(str) > 5 { return str; } };",
@@ -465,11 +2619,676 @@ mod tests {
     #[case("sum = a + b = 3 + 7; a", Value::Int(3))]
     #[case("func add(a, b) a + b; add(1, 2)", Value::Int(3))]
     #[case("func add(a, (b + c)) a + b + c; add(1, (2 + 3))", Value::Int(6))]
+    #[case(
+        "func minmax(pair) { x, y = pair; if x < y { return x, y }; return y, x }; lo, hi = minmax((3, 1)); lo * 10 + hi",
+        Value::Int(13)
+    )]
+    #[case(
+        "func minmax(x, y) if x < y { (x, y) } else { (y, x) }; lo, hi = minmax(1, 3); lo * 10 + hi",
+        Value::Int(13)
+    )]
+    #[case(
+        "func triple(n) n, (n + 1, n + 2); a, (b, c) = triple(1); a + b + c",
+        Value::Int(6)
+    )]
+    #[case("t = 1, 2; a, b = t", Value::Tuple(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(2))]))]
     fn test_runtime_basic(#[case] code: &str, #[case] expected_result: Value) {
         let code_ = String::from(code);
         let tokens = tokenize(&code_).unwrap();
         let ast = parse(&tokens).unwrap();
-        let result = eval(&ast, &mut HashMap::new());
+        let result = eval(&ast, &mut HashMap::new(), &mut Context::default());
         assert_eq!(result.unwrap().as_ref().to_owned(), expected_result);
     }
+
+    #[cfg(feature = "int64")]
+    #[rstest]
+    #[case("2 ^ 40", Value::Int(1 << 40))]
+    #[case("3000000000 * 3", Value::Int(9000000000))]
+    #[case("-9223372036854775808", Value::Int(i64::MIN))]
+    #[case("2 ^ 63 - 1", Value::Int(i64::MAX))]
+    #[case("2 ^ 63", Value::BigInt("9223372036854775808".parse().unwrap()))]
+    #[case("length(\"abc\") + 5000000000", Value::Int(5000000003))]
+    #[case("5000000000 / 2 == 2500000000", Value::Bool(true))]
+    #[case("2.0 ^ 40", Value::Float(1099511627776.0))]
+    fn test_runtime_int64(#[case] code: &str, #[case] expected_result: Value) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let result = eval(&ast, &mut HashMap::new(), &mut Context::default());
+        assert_eq!(*result.unwrap(), expected_result);
+    }
+
+    #[rstest]
+    #[case(
+        "ord(\"AB\")",
+        "\"ord\" expects a single-character string, got string of length 2"
+    )]
+    #[case(
+        "ord(\"\")",
+        "\"ord\" expects a single-character string, got string of length 0"
+    )]
+    #[case("chr(-1)", "-1 is not a valid code point")]
+    #[case("chr(1114112)", "1114112 is not a valid code point")]
+    #[case(
+        "char_at(\"abc\", 3)",
+        "index 3 is out of range for string of length 3"
+    )]
+    #[case("t = 1, 2, 3; a, b = t", "cannot unpack 3 values into 2 variables")]
+    #[case("a, b = 1", "cannot unpack integer into 2 variables")]
+    #[case("a, b = 1, 2, 3", "cannot unpack 3 values into 2 variables")]
+    #[case("a, b, c = 1, 2", "cannot unpack 2 values into 3 variables")]
+    #[case("a, b, c = (1, 2)", "cannot unpack 2 values into 3 variables")]
+    #[case("a, (b, c) = 1, (2, 3, 4)", "cannot unpack 3 values into 2 variables")]
+    #[case("sign(nan)", "\"sign\" is not defined for nan")]
+    #[case(
+        "sign(\"x\")",
+        "\"sign\" built-in function is not defined for arg of type \"string\""
+    )]
+    #[case("take((1, 2), -1)", "\"take\" count can't be negative")]
+    #[case(
+        "drop(1, 2)",
+        "\"drop\" built-in function is not defined for arg of type \"integer\""
+    )]
+    #[case(
+        "func double(x) { x * 2 }; filter(double, (1, 2))",
+        "\"filter\" predicate must return bool, got integer"
+    )]
+    #[case(
+        "filter((1, 2), is_nan)",
+        "\"filter\" accepts a predicate function and a collection"
+    )]
+    #[case("(1, 2) + [3, 4]", "addition is not defined for tuple and list")]
+    #[case("(1, 2) + 3", "addition is not defined for tuple and integer")]
+    #[case("(1, 2) * 10000000", "repetition result would be longer than 16777216")]
+    #[case(
+        "\"abc\" * 2000000000",
+        "repetition result would be longer than 16777216"
+    )]
+    #[case("x = 1; x and= true", "\"and\" is only defined for bools, got integer")]
+    #[case(
+        "x = false; x or= \"yes\"",
+        "\"or\" is only defined for bools, got string"
+    )]
+    #[case(
+        "if log { 1 }",
+        "if condition must evaluate to bool or a value with truthiness, got built-in function"
+    )]
+    #[case("repeat(-1) { 1 }", "repeat count can't be negative")]
+    #[case(
+        "repeat(1.5) { 1 }",
+        "repeat count must be an integer, got floating point number"
+    )]
+    #[case(
+        "func bad(a, b) { \"no\" }; sort_by((2, 1), bad)",
+        "\"sort_by\" comparator must return bool or integer, got string"
+    )]
+    #[case("func bad(a, b) { a / 0 }; sort_by((2, 1), bad)", "division by zero")]
+    #[case(
+        "all((true, 5))",
+        "\"all\" expects a collection of bools, got integer element"
+    )]
+    #[case(
+        "any(true)",
+        "\"any\" built-in function is not defined for arg of type \"bool\""
+    )]
+    #[case(
+        "memoize(1)",
+        "\"memoize\" built-in function is not defined for arg of type \"integer\""
+    )]
+    #[case("break", "\"break\" outside of a loop")]
+    #[case("while true { break nope }", "no enclosing loop labeled \"nope\"")]
+    #[case("func f(x) { break }; repeat(2) f(1)", "\"break\" outside of a loop")]
+    #[case(
+        "lerp(0, 10)",
+        "\"lerp\" accepts three numbers: the start, the end and the position"
+    )]
+    #[case(
+        "lerp(0, \"10\", 1)",
+        "\"lerp\" accepts three numbers: the start, the end and the position"
+    )]
+    #[case("flatten((1, 2), -1)", "\"flatten\" depth can't be negative")]
+    #[case(
+        "flatten(\"abc\")",
+        "\"flatten\" built-in function is not defined for arg of type \"string\""
+    )]
+    #[case("(1, 2)[::0]", "slice step can't be zero")]
+    #[case("(1, 2)[2]", "index 2 is out of range for tuple of length 2")]
+    #[case("(1, 2)[0.5]", "indices must be integers, got floating point number")]
+    #[case("5[0]", "integer can't be indexed")]
+    #[case(
+        "assert_eq((1, \"b\"), (1, \"c\"))",
+        "assertion failed: expected (1, c), got (1, b)"
+    )]
+    #[case(
+        "assert_eq(1)",
+        "\"assert_eq\" accepts the actual and the expected values"
+    )]
+    #[case("{ let z = 1 }; z", "reference to non-existent variable \"z\"")]
+    #[case(
+        "int((2.5, \"nearest\"))",
+        "unknown rounding mode \"nearest\", expected truncate, floor, ceil, up or even"
+    )]
+    #[case("int(nan)", "\"int\" can't convert nan to an integer")]
+    #[case(
+        "int(\"2\")",
+        "\"int\" built-in function is not defined for arg of type \"string\""
+    )]
+    #[case(
+        "parse_int(\"12x\")",
+        "\"parse_int\" can't parse \"12x\" as an integer in base 10"
+    )]
+    #[case(
+        "parse_int(\" 12\")",
+        "\"parse_int\" can't parse \" 12\" as an integer in base 10"
+    )]
+    #[case(
+        "parse_int((\"12\", 2))",
+        "\"parse_int\" can't parse \"12\" as an integer in base 2"
+    )]
+    #[case(
+        "parse_int((\"1\", 37))",
+        "\"parse_int\" radix must be from 2 to 36, got 37"
+    )]
+    #[case(
+        "parse_int(\"99999999999999999999_1\")",
+        "\"parse_int\" can't parse \"99999999999999999999_1\" as an integer in base 10"
+    )]
+    #[case(
+        "parse_int(\"99999999999999999999x\")",
+        "\"parse_int\" can't parse \"99999999999999999999x\" as an integer in base 10"
+    )]
+    #[case(
+        "parse_int(\"+-1\")",
+        "\"parse_int\" can't parse \"+-1\" as an integer in base 10"
+    )]
+    #[case(
+        "parse_int(\"-\")",
+        "\"parse_int\" can't parse \"-\" as an integer in base 10"
+    )]
+    #[case(
+        "parse_int(12)",
+        "\"parse_int\" accepts a string and an optional radix"
+    )]
+    #[case(
+        "parse_float(\"1.5.2\")",
+        "\"parse_float\" can't parse \"1.5.2\" as a float"
+    )]
+    #[case("parse_float(\"\")", "\"parse_float\" can't parse \"\" as a float")]
+    #[case("get(1, 0, 0)", "integer can't be indexed")]
+    #[case("get([1], \"a\", 0)", "indices must be integers, got string")]
+    #[case(
+        "get([1], 0)",
+        "\"get\" accepts a collection, an index and the default value"
+    )]
+    #[case("sleep(-0.5)", "\"sleep\" duration can't be negative")]
+    #[case(
+        "sleep(\"1s\")",
+        "\"sleep\" built-in function is not defined for arg of type \"string\""
+    )]
+    #[case("func f(1) 2; f(3)", "3 doesn't match the pattern 1")]
+    #[case(
+        "1 = 1",
+        "assignment is only possible to a variable or a simple expression"
+    )]
+    #[case(
+        "a, 1 = 2, 1",
+        "assignment is only possible to a variable or a simple expression"
+    )]
+    #[case(
+        "t = (2, 1); a, 1 = t",
+        "only variables and tuples of them can be assigned from a value"
+    )]
+    #[case(
+        "func f(1) 1; func f(2) 2; f(3)",
+        "no clause of \"f\" matches the argument 3"
+    )]
+    #[case(
+        "func f(x) x; help(f)",
+        "\"help\" describes built-in functions, got <function f(x)>"
+    )]
+    #[case("curry(1, 2)", "\"curry\" accepts a function and its first argument")]
+    #[case("1 in \"123\"", "membership is not defined for integer in string")]
+    #[case("1 in 1", "membership is not defined for integer in integer")]
+    #[case(
+        "pad_left(\"7\", 3, \"00\")",
+        "\"pad_left\" fill must be a single character"
+    )]
+    #[case(
+        "pad_right(7, 3)",
+        "\"pad_right\" accepts a string, a width and optionally a fill character"
+    )]
+    #[case("1 |> 2", "\"|>\" expects a function on the right, got integer")]
+    #[case("1 % 0", "division by zero")]
+    #[case("(1, 2) % 2", "modulo is not defined for tuple and integer")]
+    #[case(
+        r#""%d" % 1.5"#,
+        "format specifier \"%d\" requires an integer, got floating point number"
+    )]
+    #[case(
+        r#""%d and %d" % 1"#,
+        "not enough arguments for format specifier \"%d\""
+    )]
+    #[case(
+        r#""%d" % (1, 2, 3)"#,
+        "not all arguments were used in string formatting"
+    )]
+    #[case(r#""%x" % 1"#, "unsupported format specifier \"%x\"")]
+    #[case(
+        r#"byte_at(b"AB", 2)"#,
+        "index 2 is out of range for bytes of length 2"
+    )]
+    #[case(r#"b"a" + "b""#, "addition is not defined for bytes and string")]
+    #[case("hash(print)", "functions can't be hashed")]
+    #[case("hash((1, print))", "tuple containing functions can't be hashed")]
+    #[case(
+        "max_by(length, (1, 2))",
+        "\"max_by\" accepts a collection and a key function"
+    )]
+    #[case(
+        "min_by([], sign)",
+        "\"min_by\" is not defined for an empty collection"
+    )]
+    #[case(
+        "func id(x) x; max_by([1, (1, 2)], id)",
+        "\"max_by\" keys can't be compared: tuple and integer"
+    )]
+    #[case("func inv(x) x / 0; min_by([1, 2], inv)", "division by zero")]
+    #[case(
+        "to_hex(1.5)",
+        "\"to_hex\" built-in function is not defined for arg of type \"floating point number\""
+    )]
+    #[case(
+        "abs((1, \"a\"))",
+        "\"abs\" of a tuple is only defined when all elements are numbers"
+    )]
+    #[case(
+        "1i < 2i",
+        "less-than is not defined for complex number and complex number"
+    )]
+    #[case("1i + \"a\"", "addition is not defined for complex number and string")]
+    #[case("1 + {}", "addition is not defined for integer and nothing")]
+    #[case("1 ?. 2", "\"?.\" expects a function on the right, got integer")]
+    #[case("1 / 0", "division by zero")]
+    #[case(
+        "(1 / 3) * 2 ^ 70",
+        "1180591620717411303424/3 is too big for a rational number, convert an operand to float"
+    )]
+    #[case("2 ^ 70 / 0", "division by zero")]
+    #[case("mod(7, 0)", "division by zero")]
+    #[case("x = 1 / 2; 1 / (x - x)", "division by zero")]
+    #[case("5.5 % 0", "division by zero")]
+    #[case("x = 1; 2.5 / (x - 1)", "division by zero")]
+    #[case("1 / (1 / 2 - 1 / 2)", "division by zero")]
+    fn test_runtime_error(#[case] code: &str, #[case] expected_errmsg: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let result = eval(&ast, &mut HashMap::new(), &mut Context::default());
+        assert_eq!(result.unwrap_err().errmsg, expected_errmsg);
+    }
+
+    #[rstest]
+    #[case("((1,2),3)", "((1, 2), 3)")]
+    #[case("(1, (2, (3, 4)))", "(1, (2, (3, 4)))")]
+    #[case("(3, 1) + (2, 0)", "(3, 1, 2, 0)")]
+    #[case("(3, 1) * 2", "(3, 1, 3, 1)")]
+    #[case("a, b = (1, 2); (b, a)", "(2, 1)")]
+    #[case("filter(is_nan, (nan, 1.0, nan))", "(nan, nan)")]
+    #[case("[[2, 1], (0,  -1)]", "[[2, 1], (0, -1)]")]
+    #[case("10.0 / 5", "2.0")]
+    #[case("10 / 5", "2")]
+    #[case("(1.0, 2, 2.5)", "(1.0, 2, 2.5)")]
+    fn test_runtime_display(#[case] code: &str, #[case] expected_display: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let result = eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap();
+        assert_eq!(format!("{}", result), expected_display);
+    }
+
+    #[test]
+    fn test_memoized_fib_calls() {
+        let count_calls = |code: &str| {
+            let tokens = tokenize(code).unwrap();
+            let ast = parse(&tokens).unwrap();
+            let mut ctx = Context {
+                profile: Some(Profile::default()),
+                ..Default::default()
+            };
+            let result = eval(&ast, &mut HashMap::new(), &mut ctx).unwrap();
+            let profile = ctx.profile.unwrap();
+            (result, profile.counts["FunctionCall"])
+        };
+        let fib = "func fib(n) if (n < 3) 1 else fib(n - 1) + fib(n - 2);";
+        let (naive_result, naive_calls) = count_calls(&format!("{} fib(20)", fib));
+        let (memo_result, memo_calls) =
+            count_calls(&format!("{} fib = memoize(fib); fib(20)", fib));
+        assert_eq!(naive_result, memo_result);
+        assert_eq!(naive_calls, 13529);
+        assert!(memo_calls < 50, "{} calls", memo_calls);
+
+        // the cache outlives a single top-level call
+        let (_, first_calls) = count_calls(&format!("{} fib = memoize(fib); fib(20)", fib));
+        let (_, repeated_calls) =
+            count_calls(&format!("{} fib = memoize(fib); fib(20); fib(20)", fib));
+        assert_eq!(repeated_calls, first_calls + 1);
+    }
+
+    #[test]
+    fn test_long_operator_chain() {
+        // way deeper than the test thread's stack allows for recursive evaluation
+        let code = vec!["1"; 10000].join(" + ") + " - 2 * 3";
+        let tokens = tokenize(&code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut ctx = Context {
+            profile: Some(Profile::default()),
+            ..Default::default()
+        };
+        let result = eval(&ast, &mut HashMap::new(), &mut ctx).unwrap();
+        let profile = ctx.profile.unwrap();
+        assert_eq!(*result, Value::Int(9994));
+        assert_eq!(profile.counts["Add"], 9999);
+    }
+
+    #[test]
+    fn test_copy_shares_no_elements() {
+        let tokens = tokenize("original = [[1, 2], (3, 4)]; copied = copy(original)").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut vars = HashMap::new();
+        eval(&ast, &mut vars, &mut Context::default()).unwrap();
+        let original = vars.remove("original").unwrap();
+        let mut copied = vars.remove("copied").unwrap();
+
+        // the copy exclusively owns all its elements, so they can be modified in place
+        let Value::List(elements) = Rc::get_mut(&mut copied).unwrap() else {
+            panic!("list expected");
+        };
+        let Value::List(inner) = Rc::get_mut(&mut elements[0]).unwrap() else {
+            panic!("list expected");
+        };
+        inner[0] = Rc::new(Value::Int(100));
+
+        assert_eq!(format!("{}", copied), "[[100, 2], (3, 4)]");
+        assert_eq!(format!("{}", original), "[[1, 2], (3, 4)]");
+    }
+
+    #[rstest]
+    #[case("1 == 1.0")]
+    #[case("1.0 < 2")]
+    #[case("1 + 0.5")]
+    #[case("2 ^ 100 * 1.0")]
+    #[case("1 / 2 - 0.5")]
+    fn test_strict_types_error(#[case] code: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut ctx = Context {
+            strict_types: true,
+            ..Default::default()
+        };
+        let result = eval(&ast, &mut HashMap::new(), &mut ctx);
+        assert!(result
+            .unwrap_err()
+            .errmsg
+            .ends_with("can't be mixed in strict types mode, use explicit conversion"));
+        assert!(eval(&ast, &mut HashMap::new(), &mut Context::default()).is_ok());
+    }
+
+    #[rstest]
+    #[case("1 == 1", Value::Bool(true))]
+    #[case("float(1) == 1.0", Value::Bool(true))]
+    #[case("1 / 2 + 1 / 2", Value::Int(1))]
+    #[case("(1, 2.0) == (1, 2.0)", Value::Bool(true))]
+    fn test_strict_types_ok(#[case] code: &str, #[case] expected_result: Value) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut ctx = Context {
+            strict_types: true,
+            ..Default::default()
+        };
+        let result = eval(&ast, &mut HashMap::new(), &mut ctx);
+        assert_eq!(*result.unwrap(), expected_result);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let tokens = tokenize("t = enumerate(\"ab\" * 300); t == t").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut ctx = Context {
+            memory_limit: Some(1000),
+            ..Default::default()
+        };
+        let result = eval(&ast, &mut HashMap::new(), &mut ctx);
+        assert_eq!(result.unwrap_err().errmsg, "memory limit of 1000 exceeded");
+        assert_eq!(
+            *eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_version() {
+        let tokens = tokenize("version()").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let Value::String(version) = eval(&ast, &mut HashMap::new(), &mut Context::default())
+            .unwrap()
+            .as_ref()
+            .clone()
+        else {
+            panic!("version is a string");
+        };
+        let parts: Vec<&str> = version.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.parse::<u32>().is_ok()));
+    }
+
+    #[rstest]
+    #[case("(1, 2) == (1.0, 2.0)", true)]
+    #[case("(1, (2, 3)) == (1.0, (2, 3.0))", true)]
+    #[case("((1, (2,)), 3) == ((1.0, (2.0,)), 3)", true)]
+    #[case("[1, [2.0, (3,)]] == [1.0, [2, (3.0,)]]", true)]
+    #[case("(1 / 2, 2) == (0.5, 2.0)", true)]
+    #[case("(1, 2) != (1.0, 2.0)", false)]
+    #[case("(1.0, 2) in ((1, 2), (3, 4))", true)]
+    #[case("(1, 2) == (1.0, 2.5)", false)]
+    #[case("(1, (2, 3)) == (1.0, (2.0, 3.0, 4.0))", false)]
+    #[case("((1,),) == ((1, 1),)", false)]
+    #[case("(1, 2) == [1, 2]", false)]
+    // construction keeps the element types, only the comparison coerces
+    #[case("str((1, 2.0)) == \"(1, 2.0)\"", true)]
+    fn test_mixed_type_tuple_equality(#[case] code: &str, #[case] expected: bool) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            *eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap(),
+            Value::Bool(expected)
+        );
+    }
+
+    #[rstest]
+    #[case(r#"read_file("missing.txt")"#, "read_file")]
+    #[case(r#"write_file(("out.txt", "text"))"#, "write_file")]
+    fn test_io_disabled_in_context(#[case] code: &str, #[case] func_name: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut ctx = Context {
+            io_enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            eval(&ast, &mut HashMap::new(), &mut ctx)
+                .unwrap_err()
+                .errmsg,
+            format!(
+                "\"{}\" is not available, file access is disabled",
+                func_name
+            )
+        );
+    }
+
+    #[test]
+    fn test_sandbox_in_context() {
+        let mut ctx = Context {
+            sandbox: true,
+            ..Default::default()
+        };
+        let tokens = tokenize("print(\"x\")").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            eval(&ast, &mut HashMap::new(), &mut ctx)
+                .unwrap_err()
+                .errmsg,
+            "\"print\" built-in function is disabled in sandbox mode"
+        );
+        let tokens = tokenize("help()").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let Value::String(help) = eval(&ast, &mut HashMap::new(), &mut ctx)
+            .unwrap()
+            .as_ref()
+            .clone()
+        else {
+            panic!("help() returns a string");
+        };
+        assert!(help.contains("log") && !help.contains("print"));
+    }
+
+    /// Output of `print` and `debug` kept for inspection after the evaluation
+    #[derive(Clone, Default)]
+    struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for CapturedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_print_and_debug_write_to_context_output() {
+        let output = CapturedOutput::default();
+        let mut ctx = Context {
+            output: Box::new(output.clone()),
+            ..Default::default()
+        };
+        let tokens = tokenize("x = debug(1.0); print(x); debug((1, \"a\"))").unwrap();
+        let ast = parse(&tokens).unwrap();
+        eval(&ast, &mut HashMap::new(), &mut ctx).unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.take()).unwrap(),
+            "Float(1.0)\n1.0\nTuple([Int(1), String(\"a\")])\n"
+        );
+    }
+
+    #[test]
+    fn test_now_with_fake_clock() {
+        let mut ctx = Context {
+            clock: Box::new(|| std::time::Duration::from_millis(1500)),
+            ..Default::default()
+        };
+        let tokens = tokenize("t0 = now(); (t0, now() - t0)").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            *eval(&ast, &mut HashMap::new(), &mut ctx).unwrap(),
+            Value::Tuple(vec![Rc::new(Value::Float(1.5)), Rc::new(Value::Float(0.0))])
+        );
+    }
+
+    #[rstest]
+    #[case("int(2.5)", 2)]
+    #[case("int(-2.5)", -2)]
+    #[case("int((2.5, \"truncate\"))", 2)]
+    #[case("int((-2.5, \"truncate\"))", -2)]
+    #[case("int((2.5, \"floor\"))", 2)]
+    #[case("int((-2.5, \"floor\"))", -3)]
+    #[case("int((2.5, \"ceil\"))", 3)]
+    #[case("int((-2.5, \"ceil\"))", -2)]
+    #[case("int((2.5, \"up\"))", 3)]
+    #[case("int((-2.5, \"up\"))", -3)]
+    #[case("int((-2.4, \"up\"))", -2)]
+    #[case("int((2.5, \"even\"))", 2)]
+    #[case("int((3.5, \"even\"))", 4)]
+    #[case("int((-2.5, \"even\"))", -2)]
+    #[case("int((2.4, \"up\"))", 2)]
+    #[case("int((7, \"ceil\"))", 7)]
+    #[case("x = 5 / 2; int(x)", 2)]
+    #[case("x = -5 / 2; int((x, \"floor\"))", -3)]
+    #[case("x = -5 / 2; int((x, \"ceil\"))", -2)]
+    #[case("x = -5 / 2; int((x, \"up\"))", -3)]
+    #[case("x = -7 / 3; int((x, \"up\"))", -2)]
+    #[case("x = 5 / 2; int((x, \"even\"))", 2)]
+    #[case("x = 7 / 2; int((x, \"even\"))", 4)]
+    fn test_int_rounding(#[case] code: &str, #[case] expected: Int) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            *eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap(),
+            Value::Int(expected)
+        );
+    }
+
+    #[rstest]
+    #[case("sleep(0)")]
+    #[case("sleep(2.5)")]
+    #[case("sleep(1 / 2)")]
+    fn test_sleep_returns_nothing(#[case] code: &str) {
+        let mut ctx = Context {
+            sleep: Box::new(|_duration| {}),
+            ..Default::default()
+        };
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            *eval(&ast, &mut HashMap::new(), &mut ctx).unwrap(),
+            Value::Nothing
+        );
+    }
+
+    #[rstest]
+    #[case("func count(n) if (n == 0) \"done\" else count(n - 1); count(100000)")]
+    #[case("func count(n) { if (n == 0) return \"done\"; return count(n - 1) }; count(100000)")]
+    #[case("func count(0) \"done\"; func count(n) { m = n - 1; count(m) }; count(100000)")]
+    fn test_tail_recursion(#[case] code: &str) {
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            *eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap(),
+            Value::String("done".into())
+        );
+    }
+
+    #[test]
+    fn test_slice_with_extreme_step() {
+        for (code, expected) in [
+            (format!("(1, 2, 3, 4, 5)[3::{}]", Int::MAX), vec![4]),
+            (format!("(1, 2, 3, 4, 5)[::{}]", Int::MAX), vec![1]),
+            (format!("(1, 2, 3, 4, 5)[1::-{}]", Int::MAX), vec![2]),
+        ] {
+            let tokens = tokenize(&code).unwrap();
+            let ast = parse(&tokens).unwrap();
+            let expected: Vec<Rc<Value>> = expected
+                .into_iter()
+                .map(|i| Rc::new(Value::Int(i)))
+                .collect();
+            assert_eq!(
+                *eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap(),
+                Value::Tuple(expected),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn test_runtime_error_span() {
+        let code = "x = 1;\ny = 10 / (x - 1); # oops";
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let error = eval(&ast, &mut HashMap::new(), &mut Context::default()).unwrap_err();
+
+        let division_start = code.find("10").unwrap();
+        let division_end = code.find(')').unwrap() + 1;
+        assert_eq!(error.span(), Some(Span::new(division_start, division_end)));
+        assert!(error.format_with_source(code).starts_with(
+            "Runtime error\n> y = 10 / (x - 1); # oops\n      ^^^^^^^^^^^^ division by zero\n"
+        ));
+    }
 }
@@ -1,10 +1,11 @@
 use crate::{
     bracket::{Bracket, BracketSide, BracketType},
     errors::TokenizerError,
+    span::Span,
 };
 
 use super::errors;
-use std::fmt;
+use std::{fmt, iter::Peekable, str::CharIndices};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
@@ -13,30 +14,62 @@ pub enum TokenType {
     Minus,
     Star,
     Slash,
+    Percent,
     Bracket(Bracket),
     ExprEnd,
     Caret,
     Equals,
     Identifier,
     StringLiteral,
+    BytesLiteral,
     BoolLiteral,
     If,
     Else,
     LeftAngle,
     RightAngle,
+    LeftAngleEquals,
+    RightAngleEquals,
+    DoubleLeftAngle,
+    DoubleRightAngle,
+    DoubleStar,
     DoubleEquals,
+    BangEquals,
     Return,
     Bang,
     While,
     Func,
     Comma,
     Comment,
+    DoubleQuestion,
+    QuestionDot,
+    Pipe,
+    And,
+    Or,
+    Repeat,
+    TypeSwitch,
+    Colon,
+    In,
+    Not,
+    Break,
+    Let,
+    Global,
 }
 
 #[derive(PartialEq, Eq, Clone)]
 pub struct Token<'a> {
     pub t: TokenType,
     pub lexeme: &'a str,
+    pub span: Span,
+}
+
+impl<'a> Token<'a> {
+    fn new(t: TokenType, code: &'a str, start: usize, end: usize) -> Token<'a> {
+        Token {
+            t,
+            lexeme: &code[start..end],
+            span: Span::new(start, end),
+        }
+    }
 }
 
 impl fmt::Debug for Token<'_> {
@@ -45,164 +78,210 @@ impl fmt::Debug for Token<'_> {
     }
 }
 
-pub fn tokenize<'a>(code: &'a str) -> Result<Vec<Token<'a>>, errors::TokenizerError> {
+pub fn tokenize<'a>(code: &'a str) -> Result<Vec<Token<'a>>, errors::TokenizerError<'a>> {
+    tokenize_with_limit(code, usize::MAX)
+}
+
+/// Same as `tokenize`, but fails with "input too large" once there are more than `max_tokens`
+/// tokens, to bound memory use on untrusted input
+pub fn tokenize_with_limit<'a>(
+    code: &'a str,
+    max_tokens: usize,
+) -> Result<Vec<Token<'a>>, errors::TokenizerError<'a>> {
     let mut tokens = Vec::new();
+    let check_limit = |tokens: &[Token]| {
+        if tokens.len() > max_tokens {
+            Err(errors::TokenizerError {
+                code,
+                errmsg: "input too large".into(),
+                error_char_idx: tokens[max_tokens].span.start,
+            })
+        } else {
+            Ok(())
+        }
+    };
 
-    if code.len() == 0 {
+    if code.is_empty() {
         return Ok(tokens);
     }
 
-    let mut code_chars = code.char_indices();
-    let mut current_char: Option<char> = None;
+    let mut code_chars = code.char_indices().peekable();
 
-    while let Some((lookahead_idx, lookahead_char)) = code_chars.next() {
-        if !lookahead_char.is_ascii() {
+    while let Some((start_idx, start_char)) = code_chars.next() {
+        check_limit(&tokens)?;
+        if !start_char.is_ascii() {
             return Err(errors::TokenizerError {
-                code: code,
+                code,
                 errmsg: "non-ASCII character".into(),
-                error_char_idx: lookahead_idx,
+                error_char_idx: start_idx,
             });
         }
 
-        // matching singe-char tokens, possibly left over from prev iteration / long token matching
-        if let Some(current_char) = current_char {
-            match match_char(current_char) {
-                CharMatch::Token(token_type) => tokens.push(Token {
-                    t: token_type,
-                    lexeme: &code[lookahead_idx - 1..lookahead_idx],
-                }),
-                CharMatch::Whitespace => {}
-                CharMatch::Unexpected => {
-                    return Err(errors::TokenizerError {
-                        code: &code,
-                        errmsg: String::from("unexpected character"),
-                        error_char_idx: lookahead_idx - 1,
-                    })
-                }
-            };
-        }
-
-        // lookahead matching of "long" tokens with subiteration
-        let maybe_long_token = match lookahead_char {
+        // long tokens are consumed up to, but not including, the char after them
+        let token = match start_char {
             numeric if is_numeric_char(numeric) => {
-                let end_idx: usize;
-                (end_idx, current_char) = iter_while_predicate(&mut code_chars, is_numeric_char)
-                    .unwrap_or((code.len(), None));
-                Some(Token {
-                    t: TokenType::Number,
-                    lexeme: &code[lookahead_idx..end_idx],
-                })
+                let mut end_idx = consume_while(code, &mut code_chars, is_numeric_char);
+                // imaginary number suffix
+                if code[end_idx..].starts_with('i') {
+                    let suffix_end_idx = consume_while(code, &mut code_chars, |ch| {
+                        ch.is_ascii_alphanumeric() || ch == '_'
+                    });
+                    if suffix_end_idx != end_idx + 1 {
+                        return Err(TokenizerError {
+                            code,
+                            errmsg: "invalid number suffix".into(),
+                            error_char_idx: end_idx,
+                        });
+                    }
+                    end_idx = suffix_end_idx;
+                }
+                Token::new(TokenType::Number, code, start_idx, end_idx)
             }
-            letter if letter.is_ascii_alphabetic() => {
-                let end_idx: usize;
-                (end_idx, current_char) = iter_while_predicate(&mut code_chars, |ch| {
+            letter if letter.is_ascii_alphabetic() || letter == '_' => {
+                let end_idx = consume_while(code, &mut code_chars, |ch| {
                     ch.is_ascii_alphanumeric() || ch == '_'
-                })
-                .unwrap_or((code.len(), None));
-                let lexeme = &code[lookahead_idx..end_idx];
-                if let Some(keyword) = match_keyword(lexeme) {
-                    Some(Token { t: keyword, lexeme })
+                });
+                if &code[start_idx..end_idx] == "b" && code[end_idx..].starts_with('"') {
+                    // bytes literal
+                    code_chars.next();
+                    let end_idx = consume_string_literal(code, &mut code_chars)?;
+                    Token::new(TokenType::BytesLiteral, code, start_idx, end_idx)
                 } else {
-                    Some(Token {
-                        t: TokenType::Identifier,
-                        lexeme,
-                    })
+                    let token_type =
+                        match_keyword(&code[start_idx..end_idx]).unwrap_or(TokenType::Identifier);
+                    Token::new(token_type, code, start_idx, end_idx)
                 }
             }
             '#' => {
-                let end_idx: usize;
-                (end_idx, current_char) = iter_while_predicate(&mut code_chars, |ch| ch != '\n')
-                    .unwrap_or((code.len(), None));
-                Some(Token {
-                    t: TokenType::Comment,
-                    lexeme: &code[lookahead_idx..end_idx],
-                })
+                let end_idx = consume_while(code, &mut code_chars, |ch| ch != '\n');
+                Token::new(TokenType::Comment, code, start_idx, end_idx)
             }
-            '=' => {
-                let end_idx: usize;
-                (end_idx, current_char) = iter_while_predicate(&mut code_chars, |ch| ch == '=')
-                    .unwrap_or((code.len(), None));
-                let lexeme = &code[lookahead_idx..end_idx];
-                let token_type = match lexeme.len() {
-                    1 => TokenType::Equals,
-                    2 => TokenType::DoubleEquals,
-                    _ => {
+            '"' => {
+                let end_idx = consume_string_literal(code, &mut code_chars)?;
+                Token::new(TokenType::StringLiteral, code, start_idx, end_idx)
+            }
+            ch => match longest_multi_char_operator(&code[start_idx..]) {
+                Some((lexeme, token_type)) => {
+                    let end_idx = start_idx + lexeme.len();
+                    if token_type == TokenType::DoubleEquals && code[end_idx..].starts_with('=') {
                         return Err(TokenizerError {
-                            code: code,
+                            code,
                             errmsg: "too much equal signs".into(),
-                            error_char_idx: end_idx - 1,
+                            error_char_idx: end_idx,
+                        });
+                    }
+                    // the first char is already consumed
+                    code_chars.nth(lexeme.len() - 2);
+                    Token::new(token_type, code, start_idx, end_idx)
+                }
+                None => match match_char(ch) {
+                    CharMatch::Token(token_type) => {
+                        Token::new(token_type, code, start_idx, start_idx + 1)
+                    }
+                    CharMatch::Whitespace => continue,
+                    CharMatch::Unexpected if starts_multi_char_operator(ch) => {
+                        let expected: Vec<String> = MULTI_CHAR_OPERATORS
+                            .iter()
+                            .filter(|(lexeme, _)| lexeme.starts_with(ch))
+                            .map(|(lexeme, _)| format!("\"{}\"", lexeme))
+                            .collect();
+                        return Err(TokenizerError {
+                            code,
+                            errmsg: format!("{} operator expected", expected.join(" or ")),
+                            error_char_idx: start_idx,
+                        });
+                    }
+                    CharMatch::Unexpected => {
+                        return Err(errors::TokenizerError {
+                            code,
+                            errmsg: String::from("unexpected character"),
+                            error_char_idx: start_idx,
                         })
                     }
-                };
-                Some(Token {
-                    t: token_type,
-                    lexeme,
-                })
-            }
-            '"' => {
-                let (end_idx, _) = iter_while_predicate(&mut code_chars, |ch| ch != '"').ok_or(
-                    TokenizerError {
-                        code: &code,
-                        errmsg: "unterminated string literal".into(),
-                        error_char_idx: code.len() - 1,
-                    },
-                )?;
-                current_char = None;
-                Some(Token {
-                    t: TokenType::StringLiteral,
-                    lexeme: &code[lookahead_idx..=end_idx],
-                })
-            }
-            _ => {
-                current_char = Some(lookahead_char);
-                None
-            }
+                },
+            },
         };
-
-        if let Some(token) = maybe_long_token {
-            tokens.push(token);
-        }
+        tokens.push(token);
     }
+    check_limit(&tokens)?;
+    Ok(tokens)
+}
 
-    // matching the last leftover character, if exists
-    if let Some(last_char) = current_char {
-        match match_char(last_char) {
-            CharMatch::Token(tt) => tokens.push(Token {
-                t: tt,
-                lexeme: &code[code.len() - 1..code.len()],
-            }),
-            CharMatch::Whitespace => {}
-            CharMatch::Unexpected => {
-                return Err(errors::TokenizerError {
-                    code: &code,
-                    errmsg: String::from("unexpected character"),
-                    error_char_idx: code.len() - 1,
-                })
-            }
-        };
+/// Consumes string literal contents after the opening quote, up to and including the closing
+/// quote, and returns the index right after it
+fn consume_string_literal<'a>(
+    code: &'a str,
+    code_chars: &mut Peekable<CharIndices>,
+) -> Result<usize, TokenizerError<'a>> {
+    let mut escaped = false;
+    let end_idx = consume_while(code, code_chars, |ch| {
+        let inside = escaped || ch != '"';
+        escaped = !escaped && ch == '\\';
+        inside
+    });
+    if code_chars.next().is_none() {
+        return Err(TokenizerError {
+            code,
+            errmsg: "unterminated string literal".into(),
+            error_char_idx: code.len() - 1,
+        });
     }
-    return Ok(tokens);
+    Ok(end_idx + 1)
 }
 
-fn iter_while_predicate<Predicate>(
-    it: &mut impl Iterator<Item = (usize, char)>,
-    predicate: Predicate,
-) -> Option<(usize, Option<char>)>
+/// Consumes chars while they match the predicate and returns the index of the first one that
+/// doesn't, which is left in the iterator
+fn consume_while<Predicate>(
+    code: &str,
+    code_chars: &mut Peekable<CharIndices>,
+    mut predicate: Predicate,
+) -> usize
 where
-    Predicate: Fn(char) -> bool,
+    Predicate: FnMut(char) -> bool,
 {
-    while let Some((idx, ch)) = it.next() {
+    while let Some(&(idx, ch)) = code_chars.peek() {
         if !predicate(ch) {
-            return Some((idx, Some(ch)));
+            return idx;
         }
+        code_chars.next();
     }
-    return None;
+    code.len()
 }
 
 fn is_numeric_char(ch: char) -> bool {
     ch.is_ascii_digit() || ch == '.'
 }
 
+/// Operators longer than one char. The longest one the code continues with is matched, so `<=`
+/// is a single token, while `<` followed by anything else is left to `match_char`. New
+/// operators only need an entry here.
+const MULTI_CHAR_OPERATORS: &[(&str, TokenType)] = &[
+    ("==", TokenType::DoubleEquals),
+    ("!=", TokenType::BangEquals),
+    ("<=", TokenType::LeftAngleEquals),
+    (">=", TokenType::RightAngleEquals),
+    ("<<", TokenType::DoubleLeftAngle),
+    (">>", TokenType::DoubleRightAngle),
+    ("**", TokenType::DoubleStar),
+    ("??", TokenType::DoubleQuestion),
+    ("?.", TokenType::QuestionDot),
+    ("|>", TokenType::Pipe),
+];
+
+fn starts_multi_char_operator(ch: char) -> bool {
+    MULTI_CHAR_OPERATORS
+        .iter()
+        .any(|(lexeme, _)| lexeme.starts_with(ch))
+}
+
+fn longest_multi_char_operator(code: &str) -> Option<(&'static str, TokenType)> {
+    MULTI_CHAR_OPERATORS
+        .iter()
+        .filter(|(lexeme, _)| code.starts_with(lexeme))
+        .max_by_key(|(lexeme, _)| lexeme.len())
+        .copied()
+}
+
 enum CharMatch {
     Token(TokenType),
     Whitespace,
@@ -216,6 +295,7 @@ fn match_char(ch: char) -> CharMatch {
         '!' => CharMatch::Token(TokenType::Bang),
         '*' => CharMatch::Token(TokenType::Star),
         '/' => CharMatch::Token(TokenType::Slash),
+        '%' => CharMatch::Token(TokenType::Percent),
         '(' => CharMatch::Token(TokenType::Bracket(Bracket {
             type_: BracketType::Round,
             side: BracketSide::Opening,
@@ -237,7 +317,16 @@ fn match_char(ch: char) -> CharMatch {
             type_: BracketType::Curly,
             side: BracketSide::Closing,
         })),
+        '[' => CharMatch::Token(TokenType::Bracket(Bracket {
+            type_: BracketType::Square,
+            side: BracketSide::Opening,
+        })),
+        ']' => CharMatch::Token(TokenType::Bracket(Bracket {
+            type_: BracketType::Square,
+            side: BracketSide::Closing,
+        })),
         ',' => CharMatch::Token(TokenType::Comma),
+        ':' => CharMatch::Token(TokenType::Colon),
         ws if ws.is_whitespace() => CharMatch::Whitespace,
         _ => CharMatch::Unexpected,
     }
@@ -252,12 +341,151 @@ fn match_keyword(lexeme: &str) -> Option<TokenType> {
         "return" => Some(TokenType::Return),
         "while" => Some(TokenType::While),
         "func" => Some(TokenType::Func),
+        "and" => Some(TokenType::And),
+        "or" => Some(TokenType::Or),
+        "repeat" => Some(TokenType::Repeat),
+        "typeswitch" => Some(TokenType::TypeSwitch),
+        "in" => Some(TokenType::In),
+        "not" => Some(TokenType::Not),
+        "break" => Some(TokenType::Break),
+        "let" => Some(TokenType::Let),
+        "global" => Some(TokenType::Global),
         _ => None,
     }
 }
 
+/// Adds an implicit `;` at every line break that ends a statement: the line ends with an operand
+/// or a closing bracket, and the next line doesn't continue it with a binary operator, `else`, a
+/// closing bracket or an opening round or curly bracket (a call or an `if`/`while` body). Line
+/// breaks inside round and square brackets are never separators.
+pub fn insert_newline_separators<'a>(code: &'a str, tokens: &[Token<'a>]) -> Vec<Token<'a>> {
+    let mut open_brackets = Vec::new();
+    let mut separated_after = Vec::new();
+    let mut prev: Option<(usize, &Token)> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.t == TokenType::Comment {
+            continue;
+        }
+        if let Some((prev_idx, prev_token)) = prev {
+            let is_line_break = code[prev_token.span.end..token.span.start].contains('\n');
+            let is_in_block = open_brackets
+                .last()
+                .is_none_or(|type_| *type_ == BracketType::Curly);
+            if is_line_break
+                && is_in_block
+                && ends_statement(prev_token.t)
+                && !continues_statement(token.t)
+            {
+                separated_after.push(prev_idx);
+            }
+        }
+        if let TokenType::Bracket(bracket) = token.t {
+            match bracket.side {
+                BracketSide::Opening => open_brackets.push(bracket.type_),
+                BracketSide::Closing => {
+                    open_brackets.pop();
+                }
+            }
+        }
+        prev = Some((i, token));
+    }
+
+    let mut separated_after = separated_after.into_iter().peekable();
+    let mut result = Vec::with_capacity(tokens.len());
+    for (i, token) in tokens.iter().enumerate() {
+        result.push(token.clone());
+        if separated_after.next_if_eq(&i).is_some() {
+            let end = token.span.end;
+            result.push(Token::new(TokenType::ExprEnd, code, end, end));
+        }
+    }
+    result
+}
+
+fn ends_statement(t: TokenType) -> bool {
+    matches!(
+        t,
+        TokenType::Number
+            | TokenType::Identifier
+            | TokenType::StringLiteral
+            | TokenType::BytesLiteral
+            | TokenType::BoolLiteral
+            | TokenType::Break
+            | TokenType::Bracket(Bracket {
+                side: BracketSide::Closing,
+                ..
+            })
+    )
+}
+
+fn continues_statement(t: TokenType) -> bool {
+    matches!(
+        t,
+        TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Percent
+            | TokenType::Caret
+            | TokenType::Equals
+            | TokenType::DoubleEquals
+            | TokenType::BangEquals
+            | TokenType::LeftAngle
+            | TokenType::RightAngle
+            | TokenType::LeftAngleEquals
+            | TokenType::RightAngleEquals
+            | TokenType::DoubleLeftAngle
+            | TokenType::DoubleRightAngle
+            | TokenType::DoubleStar
+            | TokenType::DoubleQuestion
+            | TokenType::QuestionDot
+            | TokenType::Pipe
+            | TokenType::And
+            | TokenType::Or
+            | TokenType::In
+            | TokenType::Not
+            | TokenType::Comma
+            | TokenType::Else
+            | TokenType::ExprEnd
+            | TokenType::Bracket(Bracket {
+                side: BracketSide::Closing,
+                ..
+            })
+            | TokenType::Bracket(Bracket {
+                type_: BracketType::Round | BracketType::Curly,
+                side: BracketSide::Opening,
+            })
+    )
+}
+
+/// Where each token ended up in the formatted code, indexed by the original token index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    pub formatted_spans: Vec<Span>,
+}
+
+impl SourceMap {
+    /// Index of the original token covering the byte offset in the formatted code, if any
+    pub fn original_token_at(&self, formatted_idx: usize) -> Option<usize> {
+        self.formatted_spans
+            .iter()
+            .position(|span| span.start <= formatted_idx && formatted_idx < span.end)
+    }
+}
+
 pub fn untokenize(tokens: &[Token], minified: bool) -> String {
+    untokenize_with_source_map(tokens, minified).0
+}
+
+/// Same as `untokenize`, additionally mapping formatted positions back to the tokens
+pub fn untokenize_with_source_map(tokens: &[Token], minified: bool) -> (String, SourceMap) {
     let mut res = String::new();
+    let mut formatted_spans = Vec::with_capacity(tokens.len());
+    let mut push_token = |res: &mut String, token: &Token| {
+        let start = res.len();
+        res.push_str(&format_token(token));
+        formatted_spans.push(Span::new(start, res.len()));
+    };
 
     let token_iter_1 = tokens.iter();
     let mut token_iter_2 = tokens.iter();
@@ -269,7 +497,7 @@ pub fn untokenize(tokens: &[Token], minified: bool) -> String {
     let newline = if minified { " " } else { "\n" };
 
     for (token_l, token_r) in token_iter_1.zip(token_iter_2) {
-        res.push_str(&format_token(token_l));
+        push_token(&mut res, token_l);
         let delimiter = match (token_l.t, token_r.t) {
             (
                 // opening block
@@ -293,9 +521,10 @@ pub fn untokenize(tokens: &[Token], minified: bool) -> String {
                 current_indent = current_indent.saturating_sub(1);
                 newline
             }
+            (TokenType::And | TokenType::Or, TokenType::Equals) => "",
             (TokenType::Caret, _) => "",
             (_, TokenType::Caret) => "",
-            (_, TokenType::Comma) => "",
+            (_, TokenType::Comma | TokenType::Colon) => "",
             (
                 // function calls like log(10)
                 TokenType::Identifier,
@@ -329,13 +558,15 @@ pub fn untokenize(tokens: &[Token], minified: bool) -> String {
             res.push_str(&" ".repeat(current_indent * indent_spaces))
         }
     }
-    res.push_str(&format_token(&tokens[tokens.len() - 1]));
-    return res;
+    push_token(&mut res, &tokens[tokens.len() - 1]);
+    (res, SourceMap { formatted_spans })
 }
 
 fn format_token(token: &Token) -> String {
     match token.t {
         TokenType::BoolLiteral => token.lexeme.to_lowercase(),
+        // implicit separators have an empty lexeme
+        TokenType::ExprEnd => ";".into(),
         _ => token.lexeme.into(),
     }
 }
@@ -346,53 +577,183 @@ mod tests {
     use rstest::rstest;
 
     #[rstest]
-    #[case("1", vec![Token{t: TokenType::Number, lexeme: "1"}])]
-    #[case("  1     ", vec![Token{t: TokenType::Number, lexeme: "1"}])]
-    #[case("1 1", vec![Token{t: TokenType::Number, lexeme: "1"}, Token{t: TokenType::Number, lexeme: "1"}])]
+    #[case("1", vec![Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)}])]
+    #[case("  1     ", vec![Token{t: TokenType::Number, lexeme: "1", span: Span::new(2, 3)}])]
+    #[case("1 1", vec![Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)}, Token{t: TokenType::Number, lexeme: "1", span: Span::new(2, 3)}])]
     #[case("1 + 1", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Plus, lexeme: "+"},
-        Token{t: TokenType::Number, lexeme: "1"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(2, 3)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(4, 5)},
     ])]
     #[case("1+1", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Plus, lexeme: "+"},
-        Token{t: TokenType::Number, lexeme: "1"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(1, 2)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(2, 3)},
     ])]
     #[case("1  + 1", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Plus, lexeme: "+"},
-        Token{t: TokenType::Number, lexeme: "1"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(3, 4)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(5, 6)},
     ])]
     #[case("1 +1", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Plus, lexeme: "+"},
-        Token{t: TokenType::Number, lexeme: "1"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(2, 3)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(3, 4)},
     ])]
     #[case("1+ 1", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Plus, lexeme: "+"},
-        Token{t: TokenType::Number, lexeme: "1"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(1, 2)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(3, 4)},
     ])]
     #[case("   1      + \n  1  ", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Plus, lexeme: "+"},
-        Token{t: TokenType::Number, lexeme: "1"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(3, 4)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(10, 11)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(15, 16)},
+    ])]
+    #[case("a ?? b", vec![
+        Token{t: TokenType::Identifier, lexeme: "a", span: Span::new(0, 1)},
+        Token{t: TokenType::DoubleQuestion, lexeme: "??", span: Span::new(2, 4)},
+        Token{t: TokenType::Identifier, lexeme: "b", span: Span::new(5, 6)},
+    ])]
+    #[case("2.5i+1", vec![
+        Token{t: TokenType::Number, lexeme: "2.5i", span: Span::new(0, 4)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(4, 5)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(5, 6)},
+    ])]
+    #[case("a ?. f", vec![
+        Token{t: TokenType::Identifier, lexeme: "a", span: Span::new(0, 1)},
+        Token{t: TokenType::QuestionDot, lexeme: "?.", span: Span::new(2, 4)},
+        Token{t: TokenType::Identifier, lexeme: "f", span: Span::new(5, 6)},
+    ])]
+    #[case("x |>f", vec![
+        Token{t: TokenType::Identifier, lexeme: "x", span: Span::new(0, 1)},
+        Token{t: TokenType::Pipe, lexeme: "|>", span: Span::new(2, 4)},
+        Token{t: TokenType::Identifier, lexeme: "f", span: Span::new(4, 5)},
+    ])]
+    #[case("() ?? 1", vec![
+        Token{t: TokenType::Bracket(Bracket{type_: BracketType::Round, side: BracketSide::Opening}), lexeme: "(", span: Span::new(0, 1)},
+        Token{t: TokenType::Bracket(Bracket{type_: BracketType::Round, side: BracketSide::Closing}), lexeme: ")", span: Span::new(1, 2)},
+        Token{t: TokenType::DoubleQuestion, lexeme: "??", span: Span::new(3, 5)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(6, 7)},
+    ])]
+    #[case("[1]", vec![
+        Token{t: TokenType::Bracket(Bracket{type_: BracketType::Square, side: BracketSide::Opening}), lexeme: "[", span: Span::new(0, 1)},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(1, 2)},
+        Token{t: TokenType::Bracket(Bracket{type_: BracketType::Square, side: BracketSide::Closing}), lexeme: "]", span: Span::new(2, 3)},
+    ])]
+    #[case("a", vec![Token{t: TokenType::Identifier, lexeme: "a", span: Span::new(0, 1)}])]
+    #[case("a != b", vec![
+        Token{t: TokenType::Identifier, lexeme: "a", span: Span::new(0, 1)},
+        Token{t: TokenType::BangEquals, lexeme: "!=", span: Span::new(2, 4)},
+        Token{t: TokenType::Identifier, lexeme: "b", span: Span::new(5, 6)},
+    ])]
+    #[case("! = b", vec![
+        Token{t: TokenType::Bang, lexeme: "!", span: Span::new(0, 1)},
+        Token{t: TokenType::Equals, lexeme: "=", span: Span::new(2, 3)},
+        Token{t: TokenType::Identifier, lexeme: "b", span: Span::new(4, 5)},
+    ])]
+    #[case(r#"b"\"a" + b"#, vec![
+        Token{t: TokenType::BytesLiteral, lexeme: r#"b"\"a""#, span: Span::new(0, 6)},
+        Token{t: TokenType::Plus, lexeme: "+", span: Span::new(7, 8)},
+        Token{t: TokenType::Identifier, lexeme: "b", span: Span::new(9, 10)},
     ])]
-    #[case("a", vec![Token{t: TokenType::Identifier, lexeme: "a"}])]
     #[case("a^b", vec![
-        Token{t: TokenType::Identifier, lexeme: "a"},
-        Token{t: TokenType::Caret, lexeme: "^"},
-        Token{t: TokenType::Identifier, lexeme: "b"},
+        Token{t: TokenType::Identifier, lexeme: "a", span: Span::new(0, 1)},
+        Token{t: TokenType::Caret, lexeme: "^", span: Span::new(1, 2)},
+        Token{t: TokenType::Identifier, lexeme: "b", span: Span::new(2, 3)},
     ])]
     #[case("1  /  abc123def            ", vec![
-        Token{t: TokenType::Number, lexeme: "1"},
-        Token{t: TokenType::Slash, lexeme: "/"},
-        Token{t: TokenType::Identifier, lexeme: "abc123def"},
+        Token{t: TokenType::Number, lexeme: "1", span: Span::new(0, 1)},
+        Token{t: TokenType::Slash, lexeme: "/", span: Span::new(3, 4)},
+        Token{t: TokenType::Identifier, lexeme: "abc123def", span: Span::new(6, 15)},
     ])]
     fn test_tokenizer(#[case] code: &str, #[case] expected_result: Vec<Token>) {
         let code_ = String::from(code);
         let tokens = tokenize(&code_).unwrap();
         assert_eq!(tokens, expected_result);
     }
+
+    #[rstest]
+    #[case("1<=2", &[(TokenType::Number, "1"), (TokenType::LeftAngleEquals, "<="), (TokenType::Number, "2")])]
+    #[case("1>=2", &[(TokenType::Number, "1"), (TokenType::RightAngleEquals, ">="), (TokenType::Number, "2")])]
+    #[case("1<<2", &[(TokenType::Number, "1"), (TokenType::DoubleLeftAngle, "<<"), (TokenType::Number, "2")])]
+    #[case("1>>2", &[(TokenType::Number, "1"), (TokenType::DoubleRightAngle, ">>"), (TokenType::Number, "2")])]
+    #[case("1**2", &[(TokenType::Number, "1"), (TokenType::DoubleStar, "**"), (TokenType::Number, "2")])]
+    #[case("1==2", &[(TokenType::Number, "1"), (TokenType::DoubleEquals, "=="), (TokenType::Number, "2")])]
+    #[case("1!=2", &[(TokenType::Number, "1"), (TokenType::BangEquals, "!="), (TokenType::Number, "2")])]
+    #[case("a??b", &[(TokenType::Identifier, "a"), (TokenType::DoubleQuestion, "??"), (TokenType::Identifier, "b")])]
+    #[case("a?.b", &[(TokenType::Identifier, "a"), (TokenType::QuestionDot, "?."), (TokenType::Identifier, "b")])]
+    #[case("a|>b", &[(TokenType::Identifier, "a"), (TokenType::Pipe, "|>"), (TokenType::Identifier, "b")])]
+    // the longest match is taken first, the rest is tokenized anew
+    #[case("1<==2", &[(TokenType::Number, "1"), (TokenType::LeftAngleEquals, "<="), (TokenType::Equals, "="), (TokenType::Number, "2")])]
+    #[case("1<<=2", &[(TokenType::Number, "1"), (TokenType::DoubleLeftAngle, "<<"), (TokenType::Equals, "="), (TokenType::Number, "2")])]
+    #[case("1***2", &[(TokenType::Number, "1"), (TokenType::DoubleStar, "**"), (TokenType::Star, "*"), (TokenType::Number, "2")])]
+    #[case("a>>>b", &[(TokenType::Identifier, "a"), (TokenType::DoubleRightAngle, ">>"), (TokenType::RightAngle, ">"), (TokenType::Identifier, "b")])]
+    #[case("!!=", &[(TokenType::Bang, "!"), (TokenType::BangEquals, "!=")])]
+    #[case("a<-1", &[(TokenType::Identifier, "a"), (TokenType::LeftAngle, "<"), (TokenType::Minus, "-"), (TokenType::Number, "1")])]
+    #[case("a=!b", &[(TokenType::Identifier, "a"), (TokenType::Equals, "="), (TokenType::Bang, "!"), (TokenType::Identifier, "b")])]
+    #[case("<", &[(TokenType::LeftAngle, "<")])]
+    #[case("a>=", &[(TokenType::Identifier, "a"), (TokenType::RightAngleEquals, ">=")])]
+    fn test_multi_char_operators(#[case] code: &str, #[case] expected: &[(TokenType, &str)]) {
+        let tokens: Vec<(TokenType, &str)> = tokenize(code)
+            .unwrap()
+            .iter()
+            .map(|token| (token.t, token.lexeme))
+            .collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[rstest]
+    #[case("a === b", "too much equal signs", 4)]
+    #[case("a ? b", "\"??\" or \"?.\" operator expected", 2)]
+    #[case("a | b", "\"|>\" operator expected", 2)]
+    #[case("a |", "\"|>\" operator expected", 2)]
+    fn test_tokenizer_error(
+        #[case] code: &str,
+        #[case] expected_errmsg: &str,
+        #[case] expected_idx: usize,
+    ) {
+        let e = tokenize(code).unwrap_err();
+        assert_eq!(e.errmsg, expected_errmsg);
+        assert_eq!(e.error_char_idx, expected_idx);
+    }
+
+    #[rstest]
+    #[case("a = 1\nb = 2", "a = 1; b = 2")]
+    #[case("a = 1 +\n2\nb = a\n* 2;\nc", "a = 1 + 2; b = a * 2; c")]
+    #[case("f(1,\n2)\n[3,\n4]", "f(1, 2); [3, 4]")]
+    #[case("if (a)\n{\nb\nc\n}\nelse\nd\n\n\ne", "if (a) { b; c } else d; e")]
+    #[case("func f(x)\n{\nx\n}\nf(1)", "func f(x) { x }; f(1)")]
+    fn test_insert_newline_separators(#[case] code: &str, #[case] expected_minified: &str) {
+        let tokens = insert_newline_separators(code, &tokenize(code).unwrap());
+        assert_eq!(untokenize(&tokens, true), expected_minified);
+    }
+
+    #[test]
+    fn test_tokenize_with_limit() {
+        assert_eq!(tokenize_with_limit("1 + 2", 3).unwrap().len(), 3);
+        let e = tokenize_with_limit("1 + 2 + 3", 3).unwrap_err();
+        assert_eq!(e.errmsg, "input too large");
+        assert_eq!(e.error_char_idx, 6);
+        assert!(tokenize_with_limit("print(\"a\")", 3).is_err());
+    }
+
+    #[test]
+    fn test_untokenize_source_map() {
+        let code = "x   = 1;if (x>0)   {TRUE}";
+        let tokens = tokenize(code).unwrap();
+        let (formatted, source_map) = untokenize_with_source_map(&tokens, false);
+        assert_eq!(formatted, "x = 1;\nif (x > 0) {\n    true\n}");
+        assert_eq!(formatted, untokenize(&tokens, false));
+        assert_eq!(source_map.formatted_spans.len(), tokens.len());
+
+        let formatted_true = formatted.find("true").unwrap();
+        let original_idx = source_map.original_token_at(formatted_true).unwrap();
+        assert_eq!(tokens[original_idx].lexeme, "TRUE");
+        assert_eq!(tokens[original_idx].span, Span::new(20, 24));
+        assert_eq!(
+            source_map.original_token_at(formatted.find(' ').unwrap()),
+            None
+        );
+    }
 }
@@ -1,35 +1,139 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use num_bigint::BigInt;
 
 use crate::values::function::Function;
 pub mod builtins;
 pub mod function;
 
+/// Machine integer type of `Value::Int`, 64-bit with the `int64` feature
+#[cfg(not(feature = "int64"))]
+pub type Int = i32;
+#[cfg(feature = "int64")]
+pub type Int = i64;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Nothing,
-    Int(i32),
+    Int(Int),
+    /// Integer that doesn't fit `Int`, produced by overflowing arithmetic
+    BigInt(BigInt),
+    /// Exact result of integer division, always in lowest terms with a positive denominator
+    Rational {
+        num: Int,
+        den: Int,
+    },
     Float(f32),
+    /// Written with an imaginary literal, like `3 + 4i`
+    Complex {
+        re: f32,
+        im: f32,
+    },
     String(String),
+    /// Raw binary data, displayed as a `b"..."` literal
+    Bytes(Vec<u8>),
     Bool(bool),
     Function(Function),
+    /// Elements keep their construction order through all operations, equality compares them
+    /// pairwise in that order and display lists them in it, nested collections included
     Tuple(Vec<Rc<Value>>),
+    /// Same ordering guarantees as `Tuple`
+    List(Vec<Rc<Value>>),
     // service values for control flow
+    /// Produced by `return` and unwrapped by the nearest returnable scope. Operators and
+    /// function calls pass it through unchanged, so it never reaches value-level operations.
     Returned(Rc<Value>),
+    /// Produced by `break` and consumed by the innermost loop, or by the loop with the given
+    /// label. Passed through like `Returned` on the way there.
+    Broken(Option<String>),
 }
 
 impl Value {
+    /// Whether the value counts as true in `if` and `while` conditions: bools are taken as is,
+    /// `nothing`, zero numbers and empty strings and collections are false, the rest are true.
+    /// Functions don't have truthiness.
+    pub fn truthiness(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            Value::Nothing => Some(false),
+            Value::Int(i) => Some(*i != 0),
+            Value::BigInt(_) => Some(true), // big integers are never zero
+            Value::Rational { num, .. } => Some(*num != 0),
+            Value::Float(f) => Some(*f != 0.0),
+            Value::Complex { re, im } => Some(*re != 0.0 || *im != 0.0),
+            Value::String(s) => Some(!s.is_empty()),
+            Value::Bytes(b) => Some(!b.is_empty()),
+            Value::Tuple(elements) | Value::List(elements) => Some(!elements.is_empty()),
+            Value::Function(_) | Value::Returned(_) | Value::Broken(_) => None,
+        }
+    }
+
+    /// Whether the value is a control flow service value, which unwinds the surrounding
+    /// expression instead of taking part in it
+    pub fn is_control_flow(&self) -> bool {
+        matches!(self, Value::Returned(_) | Value::Broken(_))
+    }
+
+    /// Hash for keying caches on values, stable within a run. Structurally equal values hash
+    /// equally, but values of different types that compare equal with `==`, like 1 and 1.0,
+    /// usually don't. Functions aren't hashable, and neither are collections containing them.
+    pub fn try_hash(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        self.hash_into(&mut hasher)?;
+        Some(hasher.finish())
+    }
+
+    fn hash_into(&self, state: &mut impl Hasher) -> Option<()> {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Nothing => {}
+            Value::Int(i) => i.hash(state),
+            Value::BigInt(i) => i.hash(state),
+            Value::Rational { num, den } => (num, den).hash(state),
+            Value::Float(f) => f.to_bits().hash(state),
+            Value::Complex { re, im } => (re.to_bits(), im.to_bits()).hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Tuple(elements) | Value::List(elements) => {
+                elements.len().hash(state);
+                for element in elements {
+                    element.hash_into(state)?;
+                }
+            }
+            Value::Function(_) | Value::Returned(_) | Value::Broken(_) => return None,
+        }
+        Some(())
+    }
+
     pub fn type_name(&self) -> &str {
         match self {
             Value::Nothing => "nothing",
             Value::Returned(_) => "returned value",
+            Value::Broken(_) => "break",
             Value::Int(_) => "integer",
+            Value::BigInt(_) => "big integer",
+            Value::Rational { .. } => "rational number",
             Value::Float(_) => "floating point number",
+            Value::Complex { .. } => "complex number",
             Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
             Value::Bool(_) => "bool",
             Value::Tuple(_) => "tuple",
+            Value::List(_) => "list",
             Value::Function(f) => match f {
-                Function::Builtin(_) => "built-in function",
-                Function::UserDefined(_) => "function",
+                Function::Builtin(_)
+                | Function::HigherOrderBuiltin(_)
+                | Function::ContextBuiltin(_) => "built-in function",
+                Function::UserDefined(_)
+                | Function::Clauses(_)
+                | Function::Memoized(_)
+                | Function::Curried(_) => "function",
             },
         }
     }
@@ -39,10 +143,33 @@ impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Returned(v) => write!(f, "returned {}", v),
+            Value::Broken(None) => write!(f, "break"),
+            Value::Broken(Some(label)) => write!(f, "break {}", label),
             Value::Nothing => write!(f, "nothing"),
             Value::Int(v) => write!(f, "{}", v),
+            Value::BigInt(v) => write!(f, "{}", v),
+            Value::Rational { num, den: 1 } => write!(f, "{}", num),
+            Value::Rational { num, den } => write!(f, "{}/{}", num, den),
+            Value::Float(v) if v.is_nan() => write!(f, "nan"),
+            // whole floats keep the decimal point to tell them apart from integers
+            Value::Float(v) if v.is_finite() && v.fract() == 0.0 => write!(f, "{:.1}", v),
             Value::Float(v) => write!(f, "{}", v),
+            Value::Complex { re, im } => write!(f, "{}{:+}i", re, im),
             Value::String(s) => write!(f, "{}", s),
+            Value::Bytes(bytes) => {
+                write!(f, "b\"")?;
+                for &byte in bytes {
+                    match byte {
+                        b'"' => write!(f, "\\\"")?,
+                        b'\\' => write!(f, "\\\\")?,
+                        b'\n' => write!(f, "\\n")?,
+                        b'\t' => write!(f, "\\t")?,
+                        0x20..=0x7e => write!(f, "{}", byte as char)?,
+                        _ => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
             Value::Bool(v) => write!(f, "{}", if *v { "True" } else { "False" }),
             Value::Tuple(vec) => {
                 write!(f, "(")?;
@@ -55,7 +182,105 @@ impl Display for Value {
                 write!(f, ")")?;
                 Ok(())
             }
-            _ => write!(f, "{:?}", self),
+            Value::List(vec) => {
+                write!(f, "[")?;
+                for (idx, elem) in vec.iter().enumerate() {
+                    write!(f, "{}", elem)?;
+                    if idx < vec.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Function(func) => write!(f, "{}", func),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Value::Float(f32::INFINITY), "inf")]
+    #[case(Value::Float(f32::NEG_INFINITY), "-inf")]
+    #[case(Value::Float(f32::NAN), "nan")]
+    #[case(Value::Float(1.5), "1.5")]
+    #[case(Value::Float(2.0), "2.0")]
+    #[case(Value::Float(-0.0), "-0.0")]
+    #[case(Value::Float(1e10), "10000000000.0")]
+    #[case(Value::Int(2), "2")]
+    #[case(Value::Complex { re: 3.0, im: 4.0 }, "3+4i")]
+    #[case(Value::Complex { re: 0.5, im: -1.0 }, "0.5-1i")]
+    #[case(Value::Rational { num: -1, den: 3 }, "-1/3")]
+    #[case(Value::Rational { num: 2, den: 1 }, "2")]
+    #[case(Value::BigInt("-12345678901234567890".parse().unwrap()), "-12345678901234567890")]
+    #[case(Value::Tuple(vec![]), "()")]
+    #[case(
+        Value::Tuple(vec![
+            Rc::new(Value::Tuple(vec![Rc::new(Value::Int(1)), Rc::new(Value::Int(2))])),
+            Rc::new(Value::Int(3)),
+        ]),
+        "((1, 2), 3)"
+    )]
+    #[case(
+        Value::List(vec![
+            Rc::new(Value::String("b".into())),
+            Rc::new(Value::List(vec![Rc::new(Value::Bool(true))])),
+            Rc::new(Value::String("a".into())),
+        ]),
+        "[b, [True], a]"
+    )]
+    #[case(Value::Bytes(b"a\"\\\n\x00\xff".to_vec()), r#"b"a\"\\\n\x00\xff""#)]
+    fn test_value_display(#[case] value: Value, #[case] expected_display: &str) {
+        assert_eq!(format!("{}", value), expected_display);
+    }
+
+    #[test]
+    fn test_function_display() {
+        let display_builtin =
+            |name| format!("{}", Value::Function(builtins::builtin(name).unwrap()));
+        assert_eq!(display_builtin("log"), "<builtin log>");
+        assert_eq!(display_builtin("filter"), "<builtin filter>");
+
+        let code = "func foo(a, (b, c)) a; foo";
+        let tokens = crate::tokenize(code).unwrap();
+        let ast = crate::parse(&tokens).unwrap();
+        let func = crate::runtime::eval(
+            &ast,
+            &mut std::collections::HashMap::new(),
+            &mut crate::context::Context::default(),
+        )
+        .unwrap();
+        assert_eq!(format!("{}", func), "<function foo(a, (b, c))>");
+
+        let code = "func fact(0) 1; func fact(n) n * fact(n - 1); fact";
+        let tokens = crate::tokenize(code).unwrap();
+        let ast = crate::parse(&tokens).unwrap();
+        let func = crate::runtime::eval(
+            &ast,
+            &mut std::collections::HashMap::new(),
+            &mut crate::context::Context::default(),
+        )
+        .unwrap();
+        assert_eq!(format!("{}", func), "<function fact(0) | fact(n)>");
+    }
+
+    #[test]
+    fn test_try_hash() {
+        let pair = |a: Value, b: Value| Value::Tuple(vec![Rc::new(a), Rc::new(b)]);
+        assert_eq!(
+            pair(Value::Int(1), Value::String("a".into())).try_hash(),
+            pair(Value::Int(1), Value::String("a".into())).try_hash()
+        );
+        assert_ne!(Value::Int(1).try_hash(), Value::Float(1.0).try_hash());
+        assert_ne!(
+            Value::Tuple(vec![Rc::new(Value::Int(1))]).try_hash(),
+            Value::List(vec![Rc::new(Value::Int(1))]).try_hash()
+        );
+        let func = Value::Function(builtins::builtin("print").unwrap());
+        assert_eq!(func.try_hash(), None);
+        assert_eq!(pair(Value::Int(1), func).try_hash(), None);
+    }
+}
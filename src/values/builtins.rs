@@ -1,14 +1,86 @@
-use super::Value;
+use super::{Int, Value};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Num, Signed, ToPrimitive};
 use rand::Rng;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io::Write,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use crate::values::function::Function;
+use crate::context::Context;
+use crate::runtime::{
+    collection_contains, int_or_big, is_zero, lookup_element, rem, sliceable_elements,
+    values_equal, values_less,
+};
+use crate::values::function::{CurriedFunction, Function, MemoizedFunction};
 
 pub type BuiltinFunction = fn(&Value) -> Result<Value, String>;
 
+/// Calls a function value with the given argument, provided by the runtime to higher-order built-ins
+pub type Call<'a> = dyn FnMut(&Function, Rc<Value>) -> Result<Rc<Value>, String> + 'a;
+pub type HigherOrderBuiltinFunction = fn(&Value, &mut Call) -> Result<Value, String>;
+
+/// Built-in depending on the settings of the evaluation, like file access being enabled
+pub type ContextBuiltinFunction = fn(&Value, &mut Context) -> Result<Value, String>;
+
+/// Time elapsed since an arbitrary moment, read by `now`
+pub type Clock = Box<dyn Fn() -> Duration>;
+
+/// Blocks for the duration, called by `sleep`
+pub type Sleep = Box<dyn Fn(Duration)>;
+
+thread_local! {
+    static REGISTERED_BUILTINS: RefCell<HashMap<String, BuiltinFunction>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Built-ins that interact with the outside world, unavailable in sandbox mode
+const SIDE_EFFECTING_BUILTINS: &[&str] = &[
+    "print",
+    "debug",
+    "random",
+    "now",
+    "sleep",
+    "read_file",
+    "write_file",
+];
+
+pub fn is_disabled_in_sandbox(name: &str, ctx: &Context) -> bool {
+    ctx.sandbox && SIDE_EFFECTING_BUILTINS.contains(&name)
+}
+
+fn check_io_enabled(ctx: &Context, func_name: &str) -> Result<(), String> {
+    if ctx.io_enabled {
+        Ok(())
+    } else {
+        Err(format!(
+            "\"{}\" is not available, file access is disabled",
+            func_name
+        ))
+    }
+}
+
+/// Monotonic clock counting from the moment it's created
+pub fn monotonic_clock() -> Clock {
+    let start = Instant::now();
+    Box::new(move || start.elapsed())
+}
+
+/// Makes a host-defined function available to scripts under the given name, taking
+/// precedence over the standard built-in with the same name
+pub fn register_builtin(name: &str, func: BuiltinFunction) {
+    REGISTERED_BUILTINS.with(|registry| registry.borrow_mut().insert(name.into(), func));
+}
+
 fn log(arg: &Value) -> Result<Value, String> {
     match arg {
         Value::Float(v) => Ok(Value::Float(v.ln())),
         Value::Int(v) => log(&Value::Float(*v as f32)),
+        Value::BigInt(_) | Value::Rational { .. } => log(&float(arg)?),
         a => not_defined_for_arg("log", a),
     }
 }
@@ -16,16 +88,48 @@ fn exp(arg: &Value) -> Result<Value, String> {
     match arg {
         Value::Float(v) => Ok(Value::Float(v.exp())),
         Value::Int(v) => exp(&Value::Float(*v as f32)),
+        Value::BigInt(_) | Value::Rational { .. } => exp(&float(arg)?),
         a => not_defined_for_arg("exp", a),
     }
 }
-fn print(arg: &Value) -> Result<Value, String> {
-    println!("{}", arg);
+fn print(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    writeln!(ctx.output, "{}", arg).map_err(|e| format!("can't print: {}", e))?;
     Ok(Value::Nothing)
 }
+/// Prints the internal representation, e.g. `Float(1.0)`, and passes the value through
+fn debug(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    writeln!(ctx.output, "{:?}", arg).map_err(|e| format!("can't print: {}", e))?;
+    Ok(arg.clone())
+}
+fn read_file(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    check_io_enabled(ctx, "read_file")?;
+    match arg {
+        Value::String(path) => fs::read_to_string(path)
+            .map(Value::String)
+            .map_err(|e| format!("can't read file \"{}\": {}", path, e)),
+        a => not_defined_for_arg("read_file", a),
+    }
+}
+/// Creates or overwrites the file with a string or bytes
+fn write_file(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    check_io_enabled(ctx, "write_file")?;
+    let (path, contents) = match args_pair(arg) {
+        Some((Value::String(path), Value::String(s))) => (path, s.as_bytes()),
+        Some((Value::String(path), Value::Bytes(b))) => (path, &b[..]),
+        _ => return Err("\"write_file\" accepts a path and a string or bytes".into()),
+    };
+    fs::write(path, contents)
+        .map(|_| Value::Nothing)
+        .map_err(|e| format!("can't write file \"{}\": {}", path, e))
+}
+fn str(arg: &Value) -> Result<Value, String> {
+    Ok(Value::String(arg.to_string()))
+}
 fn length(arg: &Value) -> Result<Value, String> {
     match arg {
-        Value::String(s) => Ok(Value::Int(s.len() as i32)),
+        Value::String(s) => Ok(Value::Int(s.chars().count() as Int)),
+        Value::Bytes(b) => Ok(Value::Int(b.len() as Int)),
+        Value::List(elements) => Ok(Value::Int(elements.len() as Int)),
         a => not_defined_for_arg("length", a),
     }
 }
@@ -37,25 +141,1114 @@ fn random(arg: &Value) -> Result<Value, String> {
         Err("\"random\" built-in function accepts no arguments".into())
     }
 }
+/// Same as the `mod` operator on integers
 fn mod_(arg: &Value) -> Result<Value, String> {
+    match args_pair(arg) {
+        Some((_, divisor @ Value::Int(_))) if is_zero(divisor) => Err("division by zero".into()),
+        Some((a @ Value::Int(_), b @ Value::Int(_))) => {
+            Ok(rem(a, b).expect("remainder of integers is defined"))
+        }
+        _ => Err("\"mod\" accepts two integer arguments".into()),
+    }
+}
+
+fn pad_left(arg: &Value) -> Result<Value, String> {
+    pad("pad_left", arg, true)
+}
+fn pad_right(arg: &Value) -> Result<Value, String> {
+    pad("pad_right", arg, false)
+}
+/// Pads a string to the width in chars with a fill char, a space if omitted
+fn pad(func_name: &str, arg: &Value, left: bool) -> Result<Value, String> {
+    let usage_error = || {
+        format!(
+            "\"{}\" accepts a string, a width and optionally a fill character",
+            func_name
+        )
+    };
+    let Value::Tuple(elements) = arg else {
+        return Err(usage_error());
+    };
+    let (s, width, fill) = match &elements[..] {
+        [s, width] => (s, width, ' '),
+        [s, width, fill] => match fill.as_ref() {
+            Value::String(fill) if fill.chars().count() == 1 => {
+                (s, width, fill.chars().next().unwrap())
+            }
+            _ => return Err(format!("\"{}\" fill must be a single character", func_name)),
+        },
+        _ => return Err(usage_error()),
+    };
+    let (Value::String(s), Value::Int(width)) = (s.as_ref(), width.as_ref()) else {
+        return Err(usage_error());
+    };
+    let padding: String = std::iter::repeat_n(
+        fill,
+        usize::try_from(*width)
+            .unwrap_or(0)
+            .saturating_sub(s.chars().count()),
+    )
+    .collect();
+    Ok(Value::String(if left {
+        padding + s
+    } else {
+        s.clone() + &padding
+    }))
+}
+fn char_at(arg: &Value) -> Result<Value, String> {
     if let Value::Tuple(elements) = arg {
-        if let [a, b] = &elements[..] {
-            if let (Value::Int(i1), Value::Int(i2)) = (a.clone().as_ref(), b.clone().as_ref()) {
-                return Ok(Value::Int(i1 % i2));
+        if let [s, idx] = &elements[..] {
+            if let (Value::String(s), Value::Int(idx)) = (s.as_ref(), idx.as_ref()) {
+                return usize::try_from(*idx)
+                    .ok()
+                    .and_then(|idx| s.chars().nth(idx))
+                    .map(|ch| Value::String(ch.to_string()))
+                    .ok_or(format!(
+                        "index {} is out of range for string of length {}",
+                        idx,
+                        s.chars().count()
+                    ));
+            }
+        }
+    }
+    Err("\"char_at\" accepts a string and an integer index".into())
+}
+fn byte_at(arg: &Value) -> Result<Value, String> {
+    let Some((Value::Bytes(bytes), Value::Int(idx))) = args_pair(arg) else {
+        return Err("\"byte_at\" accepts bytes and an integer index".into());
+    };
+    usize::try_from(*idx)
+        .ok()
+        .and_then(|idx| bytes.get(idx))
+        .map(|&byte| Value::Int(byte.into()))
+        .ok_or(format!(
+            "index {} is out of range for bytes of length {}",
+            idx,
+            bytes.len()
+        ))
+}
+
+fn ord(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::String(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Ok(Value::Int(ch as Int)),
+                _ => Err(format!(
+                    "\"ord\" expects a single-character string, got string of length {}",
+                    s.chars().count()
+                )),
+            }
+        }
+        a => not_defined_for_arg("ord", a),
+    }
+}
+fn chr(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Int(code) => u32::try_from(*code)
+            .ok()
+            .and_then(char::from_u32)
+            .map(|ch| Value::String(ch.to_string()))
+            .ok_or(format!("{} is not a valid code point", code)),
+        a => not_defined_for_arg("chr", a),
+    }
+}
+
+/// Magnitude for complex numbers, Euclidean norm for tuples of numbers
+fn abs(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Int(v) => Ok(v
+            .checked_abs()
+            .map(Value::Int)
+            .unwrap_or_else(|| Value::BigInt(BigInt::from(*v).abs()))),
+        Value::BigInt(v) => Ok(Value::BigInt(v.abs())),
+        Value::Rational { num, den } if *num != Int::MIN => Ok(Value::Rational {
+            num: num.abs(),
+            den: *den,
+        }),
+        Value::Rational { .. } => abs(&float(arg)?),
+        Value::Float(v) => Ok(Value::Float(v.abs())),
+        Value::Complex { re, im } => Ok(Value::Float(re.hypot(*im))),
+        Value::Tuple(elements) if !elements.is_empty() => elements
+            .iter()
+            .map(|element| match element.as_ref() {
+                Value::Tuple(_) => None,
+                scalar => match float(&abs(scalar).ok()?).ok()? {
+                    Value::Float(magnitude) => Some(magnitude * magnitude),
+                    _ => None,
+                },
+            })
+            .sum::<Option<f32>>()
+            .map(|sum_of_squares| Value::Float(sum_of_squares.sqrt()))
+            .ok_or("\"abs\" of a tuple is only defined when all elements are numbers".into()),
+        a => not_defined_for_arg("abs", a),
+    }
+}
+/// Principal square root, negative numbers have complex roots
+fn sqrt(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Float(v) if *v < 0.0 => Ok(Value::Complex {
+            re: 0.0,
+            im: (-v).sqrt(),
+        }),
+        Value::Float(v) => Ok(Value::Float(v.sqrt())),
+        Value::Int(_) | Value::BigInt(_) | Value::Rational { .. } => sqrt(&float(arg)?),
+        Value::Complex { re, im } => {
+            let magnitude = re.hypot(*im);
+            Ok(Value::Complex {
+                re: ((magnitude + re) / 2.0).sqrt(),
+                im: im.signum() * ((magnitude - re) / 2.0).sqrt(),
+            })
+        }
+        a => not_defined_for_arg("sqrt", a),
+    }
+}
+
+/// Integer digits in base 2. Negative numbers get a leading minus sign rather than a two's
+/// complement form, which would depend on the integer width: `to_binary(-2) == "-10"`
+fn to_binary(arg: &Value) -> Result<Value, String> {
+    to_radix("to_binary", arg, 2)
+}
+/// Integer digits in base 16, lowercase, negative numbers are signed as in `to_binary`
+fn to_hex(arg: &Value) -> Result<Value, String> {
+    to_radix("to_hex", arg, 16)
+}
+fn to_radix(name: &str, arg: &Value, radix: u32) -> Result<Value, String> {
+    match arg {
+        Value::Int(v) => Ok(Value::String(BigInt::from(*v).to_str_radix(radix))),
+        Value::BigInt(v) => Ok(Value::String(v.to_str_radix(radix))),
+        a => not_defined_for_arg(name, a),
+    }
+}
+
+fn is_nan(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Float(v) => Ok(Value::Bool(v.is_nan())),
+        Value::Int(_) | Value::BigInt(_) | Value::Rational { .. } => Ok(Value::Bool(false)),
+        a => not_defined_for_arg("is_nan", a),
+    }
+}
+
+fn sign(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Int(v) => Ok(Value::Int(v.signum())),
+        Value::BigInt(v) => Ok(Value::Int(if v.is_positive() { 1 } else { -1 })),
+        Value::Rational { num, .. } => Ok(Value::Int(num.signum())),
+        Value::Float(v) if v.is_nan() => Err("\"sign\" is not defined for nan".into()),
+        Value::Float(v) if *v == 0.0 => Ok(Value::Int(0)),
+        Value::Float(v) => Ok(Value::Int(if *v > 0.0 { 1 } else { -1 })),
+        a => not_defined_for_arg("sign", a),
+    }
+}
+
+/// Linear interpolation `a + (b - a) * t` from `a` to `b`, as a float. `t` outside of [0, 1]
+/// extrapolates past the ends instead of being clamped: `lerp((0, 10, 2)) == 20.0`
+fn lerp(arg: &Value) -> Result<Value, String> {
+    let numbers: Option<Vec<f32>> = match arg {
+        Value::Tuple(elements) if elements.len() == 3 => elements
+            .iter()
+            .map(|element| match float(element) {
+                Ok(Value::Float(v)) => Some(v),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    };
+    match numbers.as_deref() {
+        Some(&[a, b, t]) => Ok(Value::Float(a + (b - a) * t)),
+        _ => Err("\"lerp\" accepts three numbers: the start, the end and the position".into()),
+    }
+}
+
+fn take(arg: &Value) -> Result<Value, String> {
+    take_or_drop("take", arg, true)
+}
+fn drop(arg: &Value) -> Result<Value, String> {
+    take_or_drop("drop", arg, false)
+}
+fn take_or_drop(func_name: &str, arg: &Value, take: bool) -> Result<Value, String> {
+    let Some((collection, Value::Int(n))) = args_pair(arg) else {
+        return Err(format!(
+            "\"{}\" accepts a collection and an integer count",
+            func_name
+        ));
+    };
+    let n =
+        usize::try_from(*n).map_err(|_| format!("\"{}\" count can't be negative", func_name))?;
+    match collection {
+        Value::Tuple(elements) => {
+            let (head, tail) = elements.split_at(n.min(elements.len()));
+            Ok(Value::Tuple(if take { head } else { tail }.to_vec()))
+        }
+        Value::List(elements) => {
+            let (head, tail) = elements.split_at(n.min(elements.len()));
+            Ok(Value::List(if take { head } else { tail }.to_vec()))
+        }
+        Value::String(s) => {
+            let chars = s.chars();
+            Ok(Value::String(if take {
+                chars.take(n).collect()
+            } else {
+                chars.skip(n).collect()
+            }))
+        }
+        a => not_defined_for_arg(func_name, a),
+    }
+}
+
+fn count(arg: &Value) -> Result<Value, String> {
+    let Some((collection, target)) = args_pair(arg) else {
+        return Err("\"count\" accepts a collection and a value to look for".into());
+    };
+    let count = match (collection, target) {
+        (Value::Tuple(elements) | Value::List(elements), target) => elements
+            .iter()
+            .filter(|element| values_equal(element, target))
+            .count(),
+        (Value::String(s), Value::String(substring)) => s.matches(substring.as_str()).count(),
+        (Value::String(_), t) => {
+            return Err(format!(
+                "only a string can be looked for in a string, got {}",
+                t.type_name()
+            ))
+        }
+        (a, _) => return not_defined_for_arg("count", a),
+    };
+    Ok(Value::Int(count as Int))
+}
+
+fn float(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Float(v) => Ok(Value::Float(*v)),
+        Value::Int(v) => Ok(Value::Float(*v as f32)),
+        Value::BigInt(v) => Ok(Value::Float(v.to_f32().unwrap_or(f32::NAN))),
+        Value::Rational { num, den } => Ok(Value::Float(*num as f32 / *den as f32)),
+        a => not_defined_for_arg("float", a),
+    }
+}
+
+/// How `int` rounds numbers that aren't whole
+#[derive(Clone, Copy)]
+enum Rounding {
+    Truncate,
+    Floor,
+    Ceil,
+    /// To the nearest integer, halves away from zero
+    HalfUp,
+    /// To the nearest integer, halves to the even one
+    HalfEven,
+}
+
+/// Integer part of a number, `int((x, mode))` rounds with the mode instead: "floor", "ceil",
+/// "up" and "even" for the nearest integer with halves rounded away from zero or to even, or
+/// "truncate"
+fn int(arg: &Value) -> Result<Value, String> {
+    let (number, rounding) = match args_pair(arg) {
+        Some((number, Value::String(mode))) => {
+            let rounding = match mode.as_str() {
+                "truncate" => Rounding::Truncate,
+                "floor" => Rounding::Floor,
+                "ceil" => Rounding::Ceil,
+                "up" => Rounding::HalfUp,
+                "even" => Rounding::HalfEven,
+                _ => {
+                    return Err(format!(
+                        "unknown rounding mode \"{}\", expected truncate, floor, ceil, up or even",
+                        mode
+                    ))
+                }
+            };
+            (number, rounding)
+        }
+        _ => (arg, Rounding::Truncate),
+    };
+    match number {
+        Value::Int(_) | Value::BigInt(_) => Ok(number.clone()),
+        Value::Rational { num, den } => Ok(int_or_big(BigInt::from(round_rational(
+            i128::from(*num),
+            i128::from(*den),
+            rounding,
+        )))),
+        Value::Float(v) => BigInt::from_f32(round_float(*v, rounding))
+            .map(int_or_big)
+            .ok_or_else(|| format!("\"int\" can't convert {} to an integer", number)),
+        a => not_defined_for_arg("int", a),
+    }
+}
+
+/// Exact rounding of `num / den`, the denominator is positive
+fn round_rational(num: i128, den: i128, rounding: Rounding) -> i128 {
+    let floor = num.div_euclid(den);
+    let rem = num.rem_euclid(den);
+    match rounding {
+        Rounding::Truncate => num / den,
+        Rounding::Floor => floor,
+        Rounding::Ceil => floor + i128::from(rem != 0),
+        Rounding::HalfUp => floor + i128::from(2 * rem > den || (2 * rem == den && num >= 0)),
+        Rounding::HalfEven => {
+            floor + i128::from(2 * rem > den || (2 * rem == den && floor % 2 != 0))
+        }
+    }
+}
+
+fn round_float(v: f32, rounding: Rounding) -> f32 {
+    match rounding {
+        Rounding::Truncate => v.trunc(),
+        Rounding::Floor => v.floor(),
+        Rounding::Ceil => v.ceil(),
+        Rounding::HalfUp => v.round(),
+        Rounding::HalfEven => v.round_ties_even(),
+    }
+}
+
+/// Strict parsing of an integer string, with an optional radix: `parse_int(("ff", 16))`. Unlike
+/// conversions, anything but digits with an optional sign is an error.
+fn parse_int(arg: &Value) -> Result<Value, String> {
+    let (string, radix) = match (arg, args_pair(arg)) {
+        (Value::String(string), _) => (string, 10),
+        (_, Some((Value::String(string), Value::Int(radix)))) => (string, *radix),
+        _ => return Err("\"parse_int\" accepts a string and an optional radix".into()),
+    };
+    let radix = u32::try_from(radix)
+        .ok()
+        .filter(|radix| (2..=36).contains(radix))
+        .ok_or_else(|| format!("\"parse_int\" radix must be from 2 to 36, got {}", radix))?;
+    let parse_error = || {
+        format!(
+            "\"parse_int\" can't parse \"{}\" as an integer in base {}",
+            string, radix
+        )
+    };
+    // checked here rather than left to the parsers, as the big integer one also accepts `_`
+    let digits = string.strip_prefix(['+', '-']).unwrap_or(string);
+    if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+        return Err(parse_error());
+    }
+    match Int::from_str_radix(string, radix) {
+        Ok(i) => Ok(Value::Int(i)),
+        Err(_) => BigInt::from_str_radix(string, radix)
+            .map(Value::BigInt)
+            .map_err(|_| parse_error()),
+    }
+}
+
+/// Strict parsing of a float string, like "1.5", "-2e3" or "inf"
+fn parse_float(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::String(string) => string
+            .parse::<f32>()
+            .map(Value::Float)
+            .map_err(|_| format!("\"parse_float\" can't parse \"{}\" as a float", string)),
+        a => not_defined_for_arg("parse_float", a),
+    }
+}
+
+fn any(arg: &Value) -> Result<Value, String> {
+    any_or_all("any", arg, true)
+}
+fn all(arg: &Value) -> Result<Value, String> {
+    any_or_all("all", arg, false)
+}
+/// Looks for the first element equal to `decisive`, which is then the result
+fn any_or_all(func_name: &str, arg: &Value, decisive: bool) -> Result<Value, String> {
+    let elements = match arg {
+        Value::Tuple(elements) | Value::List(elements) => &elements[..],
+        Value::Nothing => &[], // empty round brackets
+        a => return not_defined_for_arg(func_name, a),
+    };
+    for element in elements {
+        match element.as_ref() {
+            Value::Bool(b) if *b == decisive => return Ok(Value::Bool(decisive)),
+            Value::Bool(_) => {}
+            v => {
+                return Err(format!(
+                    "\"{}\" expects a collection of bools, got {} element",
+                    func_name,
+                    v.type_name()
+                ))
             }
         }
     }
-    Err("\"mod\" accepts two integer arguments".into())
+    Ok(Value::Bool(!decisive))
+}
+
+/// Copy of a collection that shares no elements with the original, at any nesting level
+fn copy(arg: &Value) -> Result<Value, String> {
+    let copy_elements = |elements: &[Rc<Value>]| -> Result<Vec<Rc<Value>>, String> {
+        elements.iter().map(|e| copy(e).map(Rc::new)).collect()
+    };
+    match arg {
+        Value::Tuple(elements) => Ok(Value::Tuple(copy_elements(elements)?)),
+        Value::List(elements) => Ok(Value::List(copy_elements(elements)?)),
+        other => Ok(other.clone()),
+    }
+}
+
+/// `(index, element)` pairs, in a collection of the same kind; strings give a tuple of chars
+fn enumerate(arg: &Value) -> Result<Value, String> {
+    let pairs = |elements: Vec<Rc<Value>>| -> Vec<Rc<Value>> {
+        elements
+            .into_iter()
+            .enumerate()
+            .map(|(idx, element)| {
+                Rc::new(Value::Tuple(vec![Rc::new(Value::Int(idx as Int)), element]))
+            })
+            .collect()
+    };
+    match arg {
+        Value::Tuple(elements) => Ok(Value::Tuple(pairs(elements.clone()))),
+        Value::List(elements) => Ok(Value::List(pairs(elements.clone()))),
+        Value::String(s) => Ok(Value::Tuple(pairs(
+            s.chars()
+                .map(|ch| Rc::new(Value::String(ch.to_string())))
+                .collect(),
+        ))),
+        a => not_defined_for_arg("enumerate", a),
+    }
+}
+/// Elements without repeats, in the order of their first occurrences. Repeats are found with
+/// `==`, so `unique((1, 1.0))` is `(1,)`
+fn unique(arg: &Value) -> Result<Value, String> {
+    let first_occurrences = |elements: &[Rc<Value>]| {
+        let mut unique_elements: Vec<Rc<Value>> = Vec::new();
+        for element in elements {
+            if !unique_elements
+                .iter()
+                .any(|seen| values_equal(seen, element))
+            {
+                unique_elements.push(element.clone());
+            }
+        }
+        unique_elements
+    };
+    match arg {
+        Value::Tuple(elements) => Ok(Value::Tuple(first_occurrences(elements))),
+        Value::List(elements) => Ok(Value::List(first_occurrences(elements))),
+        Value::Nothing => Ok(Value::Nothing), // empty round brackets
+        a => not_defined_for_arg("unique", a),
+    }
+}
+/// Elements of nested tuples and lists, in a collection of the outer kind:
+/// `flatten(((1, 2), [3, (4, 5)])) == (1, 2, 3, 4, 5)`. `flatten(c, depth)` only flattens
+/// `depth` levels of nesting; a pair of a collection and an integer is always taken as this form.
+fn flatten(arg: &Value) -> Result<Value, String> {
+    fn flatten_into(elements: &[Rc<Value>], depth: usize, flat: &mut Vec<Rc<Value>>) {
+        for element in elements {
+            match element.as_ref() {
+                Value::Tuple(nested) | Value::List(nested) if depth > 0 => {
+                    flatten_into(nested, depth - 1, flat)
+                }
+                _ => flat.push(element.clone()),
+            }
+        }
+    }
+    let (collection, depth) = match args_pair(arg) {
+        Some((collection @ (Value::Tuple(_) | Value::List(_)), Value::Int(depth))) => (
+            collection,
+            usize::try_from(*depth).map_err(|_| "\"flatten\" depth can't be negative")?,
+        ),
+        _ => (arg, usize::MAX),
+    };
+    let flattened = |elements: &[Rc<Value>]| {
+        let mut flat = Vec::with_capacity(elements.len());
+        flatten_into(elements, depth, &mut flat);
+        flat
+    };
+    match collection {
+        Value::Tuple(elements) => Ok(Value::Tuple(flattened(elements))),
+        Value::List(elements) => Ok(Value::List(flattened(elements))),
+        Value::Nothing => Ok(Value::Nothing), // empty round brackets
+        a => not_defined_for_arg("flatten", a),
+    }
+}
+/// Element of a collection, char of a string or byte of bytes at the index like with `c[i]`,
+/// but out of range indices give the default instead of an error: `get(c, i, default)`
+fn get(arg: &Value) -> Result<Value, String> {
+    let (collection, index, default) = match arg {
+        Value::Tuple(elements) if elements.len() == 3 => (&elements[0], &elements[1], &elements[2]),
+        _ => return Err("\"get\" accepts a collection, an index and the default value".into()),
+    };
+    let elements = sliceable_elements(collection)
+        .ok_or_else(|| format!("{} can't be indexed", collection.type_name()))?;
+    let index = match index.as_ref() {
+        // `Int` is already `i64` with the int64 feature
+        #[allow(clippy::useless_conversion)]
+        Value::Int(index) => i64::from(*index),
+        // too big to be in range of any collection
+        Value::BigInt(_) => return Ok(default.as_ref().clone()),
+        v => return Err(format!("indices must be integers, got {}", v.type_name())),
+    };
+    Ok(lookup_element(&elements, index)
+        .unwrap_or_else(|| default.clone())
+        .as_ref()
+        .clone())
+}
+
+/// Checks that the actual value equals the expected one as with `==`, for test scripts
+fn assert_eq(arg: &Value) -> Result<Value, String> {
+    match args_pair(arg) {
+        Some((actual, expected)) if values_equal(actual, expected) => Ok(Value::Nothing),
+        Some((actual, expected)) => Err(format!(
+            "assertion failed: expected {}, got {}",
+            expected, actual
+        )),
+        None => Err("\"assert_eq\" accepts the actual and the expected values".into()),
+    }
+}
+/// Same as `==`, which compares tuples and lists elementwise with numeric coercion
+fn deep_eq(arg: &Value) -> Result<Value, String> {
+    match args_pair(arg) {
+        Some((a, b)) => Ok(Value::Bool(values_equal(a, b))),
+        None => Err("\"deep_eq\" accepts two values to compare".into()),
+    }
+}
+/// Same as the `in` operator with swapped operands
+fn contains(arg: &Value) -> Result<Value, String> {
+    match args_pair(arg) {
+        Some((collection, value)) => collection_contains(collection, value).map(Value::Bool),
+        None => Err("\"contains\" accepts a collection and a value to look for".into()),
+    }
+}
+fn index_of(arg: &Value) -> Result<Value, String> {
+    let Some((collection, target)) = args_pair(arg) else {
+        return Err("\"index_of\" accepts a collection and a value to look for".into());
+    };
+    let index = match (collection, target) {
+        (Value::Tuple(elements) | Value::List(elements), target) => elements
+            .iter()
+            .position(|element| values_equal(element, target)),
+        (Value::String(s), Value::String(substring)) => s
+            .find(substring.as_str())
+            .map(|byte_idx| s[..byte_idx].chars().count()),
+        (Value::String(_), t) => {
+            return Err(format!(
+                "only a string can be looked for in a string, got {}",
+                t.type_name()
+            ))
+        }
+        (a, _) => return not_defined_for_arg("index_of", a),
+    };
+    Ok(Value::Int(index.map(|idx| idx as Int).unwrap_or(-1)))
+}
+
+fn filter(arg: &Value, call: &mut Call) -> Result<Value, String> {
+    let Some((Value::Function(predicate), collection)) = args_pair(arg) else {
+        return Err("\"filter\" accepts a predicate function and a collection".into());
+    };
+    let mut is_kept = |element: Value| -> Result<bool, String> {
+        match call(predicate, Rc::new(element))?.as_ref() {
+            Value::Bool(b) => Ok(*b),
+            v => Err(format!(
+                "\"filter\" predicate must return bool, got {}",
+                v.type_name()
+            )),
+        }
+    };
+    match collection {
+        Value::Tuple(elements) | Value::List(elements) => {
+            let mut kept = Vec::new();
+            for element in elements {
+                if is_kept(element.as_ref().clone())? {
+                    kept.push(element.clone());
+                }
+            }
+            Ok(match collection {
+                Value::List(_) => Value::List(kept),
+                _ => Value::Tuple(kept),
+            })
+        }
+        Value::String(s) => {
+            let mut kept = String::new();
+            for ch in s.chars() {
+                if is_kept(Value::String(ch.to_string()))? {
+                    kept.push(ch);
+                }
+            }
+            Ok(Value::String(kept))
+        }
+        c => not_defined_for_arg("filter", c),
+    }
+}
+
+fn sort_by(arg: &Value, call: &mut Call) -> Result<Value, String> {
+    let Some((collection, Value::Function(comparator))) = args_pair(arg) else {
+        return Err("\"sort_by\" accepts a collection and a comparator function".into());
+    };
+    // comparator returns either whether the first argument goes before the second,
+    // or a negative/zero/positive integer like the "sign" built-in
+    let mut is_before = |a: &Rc<Value>, b: &Rc<Value>| -> Result<bool, String> {
+        match call(
+            comparator,
+            Rc::new(Value::Tuple(vec![a.clone(), b.clone()])),
+        )?
+        .as_ref()
+        {
+            Value::Bool(before) => Ok(*before),
+            Value::Int(sign) => Ok(*sign < 0),
+            v => Err(format!(
+                "\"sort_by\" comparator must return bool or integer, got {}",
+                v.type_name()
+            )),
+        }
+    };
+    match collection {
+        Value::Tuple(elements) => Ok(Value::Tuple(merge_sort(elements, &mut is_before)?)),
+        Value::List(elements) => Ok(Value::List(merge_sort(elements, &mut is_before)?)),
+        c => not_defined_for_arg("sort_by", c),
+    }
+}
+
+fn min_by(arg: &Value, call: &mut Call) -> Result<Value, String> {
+    extremum_by("min_by", arg, call, false)
+}
+fn max_by(arg: &Value, call: &mut Call) -> Result<Value, String> {
+    extremum_by("max_by", arg, call, true)
+}
+/// Element with the smallest or the largest key per the `<` operator, the first one on ties
+fn extremum_by(
+    func_name: &str,
+    arg: &Value,
+    call: &mut Call,
+    is_max: bool,
+) -> Result<Value, String> {
+    let Some((collection, Value::Function(key_func))) = args_pair(arg) else {
+        return Err(format!(
+            "\"{}\" accepts a collection and a key function",
+            func_name
+        ));
+    };
+    let (Value::Tuple(elements) | Value::List(elements)) = collection else {
+        return not_defined_for_arg(func_name, collection);
+    };
+    let mut best: Option<(&Rc<Value>, Rc<Value>)> = None;
+    for element in elements {
+        let key = call(key_func, element.clone())?;
+        let is_better = match &best {
+            None => true,
+            Some((_, best_key)) => {
+                let (smaller, larger) = if is_max {
+                    (best_key, &key)
+                } else {
+                    (&key, best_key)
+                };
+                values_less(smaller, larger).ok_or(format!(
+                    "\"{}\" keys can't be compared: {} and {}",
+                    func_name,
+                    key.type_name(),
+                    best_key.type_name()
+                ))?
+            }
+        };
+        if is_better {
+            best = Some((element, key));
+        }
+    }
+    best.map(|(element, _)| element.as_ref().clone())
+        .ok_or(format!(
+            "\"{}\" is not defined for an empty collection",
+            func_name
+        ))
+}
+
+/// Stable sort with a fallible comparator, which doesn't have to be a consistent ordering
+fn merge_sort(
+    elements: &[Rc<Value>],
+    is_before: &mut impl FnMut(&Rc<Value>, &Rc<Value>) -> Result<bool, String>,
+) -> Result<Vec<Rc<Value>>, String> {
+    if elements.len() <= 1 {
+        return Ok(elements.to_vec());
+    }
+    let (left, right) = elements.split_at(elements.len() / 2);
+    let left = merge_sort(left, is_before)?;
+    let right = merge_sort(right, is_before)?;
+    let mut merged = Vec::with_capacity(elements.len());
+    let (mut left_iter, mut right_iter) =
+        (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left_iter.peek(), right_iter.peek()) {
+        // equal elements are taken from the left half first to keep the sort stable
+        if is_before(r, l)? {
+            merged.extend(right_iter.next());
+        } else {
+            merged.extend(left_iter.next());
+        }
+    }
+    merged.extend(left_iter);
+    merged.extend(right_iter);
+    Ok(merged)
+}
+
+/// Hash of the value as a non-negative integer, the same for equal values of the same type within
+/// a run (`1` and `1.0` hash differently). Different values may collide, so equal hashes don't
+/// guarantee equal values.
+fn hash(arg: &Value) -> Result<Value, String> {
+    let hash = arg.try_hash().ok_or_else(|| match arg {
+        Value::Function(_) => "functions can't be hashed".to_string(),
+        other => format!("{} containing functions can't be hashed", other.type_name()),
+    })?;
+    Ok(Int::try_from(hash)
+        .map(Value::Int)
+        .unwrap_or_else(|_| Value::BigInt(hash.into())))
+}
+
+fn type_(arg: &Value) -> Result<Value, String> {
+    Ok(Value::String(arg.type_name().into()))
+}
+
+fn memoize(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Function(func) => Ok(Value::Function(Function::Memoized(MemoizedFunction::new(
+            func.clone(),
+        )))),
+        a => not_defined_for_arg("memoize", a),
+    }
 }
 
+/// Partial application, `curry(f, a)(b, c)` is the same as `f(a, b, c)`
+fn curry(arg: &Value) -> Result<Value, String> {
+    match args_pair(arg) {
+        Some((Value::Function(func), first_arg)) => {
+            Ok(Value::Function(Function::Curried(CurriedFunction {
+                func: Rc::new(func.clone()),
+                first_arg: Rc::new(first_arg.clone()),
+            })))
+        }
+        _ => Err("\"curry\" accepts a function and its first argument".into()),
+    }
+}
+
+/// Version of the interpreter, like "0.1.0", for feature detection in scripts
+fn version(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Nothing => Ok(Value::String(env!("CARGO_PKG_VERSION").into())),
+        _ => Err("\"version\" built-in function accepts no arguments".into()),
+    }
+}
+
+/// Seconds since an arbitrary moment, only the difference between two calls is meaningful:
+/// `t0 = now(); work(); now() - t0`
+fn now(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    match arg {
+        Value::Nothing => Ok(Value::Float((ctx.clock)().as_secs_f32())),
+        _ => Err("\"now\" built-in function accepts no arguments".into()),
+    }
+}
+
+/// Pauses for the number of seconds, fractional ones included, and returns nothing
+fn sleep(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    let seconds = match float(arg) {
+        Ok(Value::Float(seconds)) => seconds,
+        _ => return not_defined_for_arg("sleep", arg),
+    };
+    if seconds < 0.0 {
+        return Err("\"sleep\" duration can't be negative".into());
+    }
+    let duration = Duration::try_from_secs_f32(seconds)
+        .map_err(|_| format!("\"sleep\" can't pause for {} seconds", seconds))?;
+    (ctx.sleep)(duration);
+    Ok(Value::Nothing)
+}
+
+/// Names of all built-in functions with `help()`, or a short description of one with
+/// `help(log)`
+fn help(arg: &Value, ctx: &mut Context) -> Result<Value, String> {
+    match arg {
+        Value::Nothing => Ok(Value::String(format!(
+            "built-in functions: {}",
+            builtin_names(ctx).join(", ")
+        ))),
+        Value::Function(func) => {
+            let name = builtin_name(func)
+                .ok_or_else(|| format!("\"help\" describes built-in functions, got {}", func))?;
+            let description = BUILTIN_DESCRIPTIONS
+                .iter()
+                .find(|(described_name, _)| *described_name == name)
+                .map_or("no description available", |(_, description)| description);
+            Ok(Value::String(format!("{}: {}", name, description)))
+        }
+        a => not_defined_for_arg("help", a),
+    }
+}
+
+const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("log", log),
+    ("exp", exp),
+    ("str", str),
+    ("length", length),
+    ("random", random),
+    ("mod", mod_),
+    ("pad_left", pad_left),
+    ("pad_right", pad_right),
+    ("char_at", char_at),
+    ("byte_at", byte_at),
+    ("ord", ord),
+    ("chr", chr),
+    ("abs", abs),
+    ("sqrt", sqrt),
+    ("to_binary", to_binary),
+    ("to_hex", to_hex),
+    ("is_nan", is_nan),
+    ("sign", sign),
+    ("lerp", lerp),
+    ("take", take),
+    ("drop", drop),
+    ("index_of", index_of),
+    ("contains", contains),
+    ("enumerate", enumerate),
+    ("deep_eq", deep_eq),
+    ("assert_eq", assert_eq),
+    ("unique", unique),
+    ("flatten", flatten),
+    ("get", get),
+    ("float", float),
+    ("int", int),
+    ("parse_int", parse_int),
+    ("parse_float", parse_float),
+    ("copy", copy),
+    ("count", count),
+    ("hash", hash),
+    ("type", type_),
+    ("memoize", memoize),
+    ("curry", curry),
+    ("any", any),
+    ("all", all),
+    ("version", version),
+];
+
+const HIGHER_ORDER_BUILTINS: &[(&str, HigherOrderBuiltinFunction)] = &[
+    ("filter", filter),
+    ("sort_by", sort_by),
+    ("min_by", min_by),
+    ("max_by", max_by),
+];
+
+const CONTEXT_BUILTINS: &[(&str, ContextBuiltinFunction)] = &[
+    ("print", print),
+    ("debug", debug),
+    ("read_file", read_file),
+    ("write_file", write_file),
+    ("help", help),
+    ("now", now),
+    ("sleep", sleep),
+];
+
+/// Shown by `help(func)`, registered built-ins have no description
+const BUILTIN_DESCRIPTIONS: &[(&str, &str)] = &[
+    ("log", "natural logarithm"),
+    ("exp", "e raised to the power"),
+    ("print", "prints the value and returns nothing"),
+    (
+        "debug",
+        "prints the internal representation and returns the value",
+    ),
+    ("read_file", "contents of the file at the path, as a string"),
+    (
+        "write_file",
+        "write_file(path, contents) writes a string or bytes to the file",
+    ),
+    ("str", "the value as it's printed, as a string"),
+    (
+        "length",
+        "number of elements in a list, chars in a string or bytes in bytes",
+    ),
+    ("random", "random float between 0 and 1, random()"),
+    ("mod", "mod(a, b) is the remainder of integer division"),
+    (
+        "pad_left",
+        "pad_left(s, width, fill) pads the string on the left",
+    ),
+    (
+        "pad_right",
+        "pad_right(s, width, fill) pads the string on the right",
+    ),
+    ("char_at", "char_at(s, i) is the char at the index"),
+    (
+        "byte_at",
+        "byte_at(b, i) is the byte at the index, as an integer",
+    ),
+    ("ord", "code point of a single char string"),
+    ("chr", "single char string with the code point"),
+    (
+        "abs",
+        "absolute value, magnitude of complex numbers and tuples of numbers",
+    ),
+    ("sqrt", "square root, complex for negative numbers"),
+    ("to_binary", "integer digits in base 2, as a string"),
+    ("to_hex", "integer digits in base 16, as a string"),
+    ("is_nan", "whether the number is nan"),
+    ("sign", "-1, 0 or 1 depending on the sign of the number"),
+    ("lerp", "lerp(a, b, t) is a + (b - a) * t, as a float"),
+    (
+        "take",
+        "take(c, n) is the first n elements of a tuple or list or chars of a string",
+    ),
+    (
+        "drop",
+        "drop(c, n) is all but the first n elements of a tuple or list or chars of a string",
+    ),
+    (
+        "index_of",
+        "index_of(c, v) is the index of the first occurrence, or -1",
+    ),
+    ("contains", "contains(c, v) is the same as v in c"),
+    (
+        "enumerate",
+        "(index, element) pairs of a collection or string",
+    ),
+    ("deep_eq", "deep_eq(a, b) is the same as a == b"),
+    (
+        "unique",
+        "elements of a collection without repeats, in the order of first occurrence",
+    ),
+    (
+        "flatten",
+        "elements of nested collections, flatten(c, depth) limits the nesting levels",
+    ),
+    (
+        "get",
+        "get(c, i, default) is c[i], or the default if the index is out of range",
+    ),
+    (
+        "assert_eq",
+        "assert_eq(actual, expected) is an error showing both values if they differ",
+    ),
+    ("float", "the number as a float"),
+    (
+        "int",
+        "the number truncated to an integer, int((x, mode)) rounds with floor, ceil, up or even",
+    ),
+    (
+        "parse_int",
+        "parse_int(s, radix) is the integer in the string, an error if it's malformed",
+    ),
+    (
+        "parse_float",
+        "the float in the string, an error if it's malformed",
+    ),
+    (
+        "copy",
+        "copy of a collection sharing no elements with the original",
+    ),
+    ("count", "count(c, v) is the number of occurrences"),
+    ("hash", "hash of the value as a non-negative integer"),
+    ("type", "name of the value type, as a string"),
+    (
+        "memoize",
+        "function that caches the results of the given one",
+    ),
+    (
+        "curry",
+        "curry(f, a) is a function of the rest of the arguments of f",
+    ),
+    ("any", "whether any of the bools in a collection is true"),
+    ("all", "whether all of the bools in a collection are true"),
+    (
+        "help",
+        "names of built-in functions, or a description of one with help(func)",
+    ),
+    ("version", "version of the interpreter, as a string"),
+    (
+        "now",
+        "seconds since an arbitrary moment, for timing with now() - t0",
+    ),
+    (
+        "sleep",
+        "pauses for the number of seconds and returns nothing",
+    ),
+    ("filter", "filter(c, f) is the elements for which f is true"),
+    ("sort_by", "sort_by(c, f) sorts by the key f, stably"),
+    (
+        "min_by",
+        "min_by(c, f) is the element with the smallest key f",
+    ),
+    (
+        "max_by",
+        "max_by(c, f) is the element with the largest key f",
+    ),
+];
+
 pub fn builtin(name: &str) -> Option<Function> {
+    if let Some(func) = REGISTERED_BUILTINS.with(|registry| registry.borrow().get(name).copied()) {
+        return Some(Function::Builtin(func));
+    }
+    if let Some((_, func)) = BUILTINS
+        .iter()
+        .find(|(builtin_name, _)| *builtin_name == name)
+    {
+        return Some(Function::Builtin(*func));
+    }
+    if let Some((_, func)) = HIGHER_ORDER_BUILTINS
+        .iter()
+        .find(|(builtin_name, _)| *builtin_name == name)
+    {
+        return Some(Function::HigherOrderBuiltin(*func));
+    }
+    CONTEXT_BUILTINS
+        .iter()
+        .find(|(builtin_name, _)| *builtin_name == name)
+        .map(|(_, func)| Function::ContextBuiltin(*func))
+}
+
+/// Name under which a built-in function is available, used for display
+pub fn builtin_name(func: &Function) -> Option<String> {
+    match func {
+        Function::Builtin(func) => REGISTERED_BUILTINS
+            .with(|registry| {
+                registry
+                    .borrow()
+                    .iter()
+                    .find(|(_, registered)| std::ptr::fn_addr_eq(**registered, *func))
+                    .map(|(name, _)| name.clone())
+            })
+            .or_else(|| {
+                BUILTINS
+                    .iter()
+                    .find(|(_, builtin)| std::ptr::fn_addr_eq(*builtin, *func))
+                    .map(|(name, _)| name.to_string())
+            }),
+        Function::HigherOrderBuiltin(func) => HIGHER_ORDER_BUILTINS
+            .iter()
+            .find(|(_, builtin)| std::ptr::fn_addr_eq(*builtin, *func))
+            .map(|(name, _)| name.to_string()),
+        Function::ContextBuiltin(func) => CONTEXT_BUILTINS
+            .iter()
+            .find(|(_, builtin)| std::ptr::fn_addr_eq(*builtin, *func))
+            .map(|(name, _)| name.to_string()),
+        _ => None,
+    }
+}
+
+/// Built-in named values, can be shadowed by variables
+pub fn constant(name: &str) -> Option<Value> {
     match name {
-        "log" => Some(Function::Builtin(log)),
-        "exp" => Some(Function::Builtin(exp)),
-        "print" => Some(Function::Builtin(print)),
-        "length" => Some(Function::Builtin(length)),
-        "random" => Some(Function::Builtin(random)),
-        "mod" => Some(Function::Builtin(mod_)),
+        "inf" => Some(Value::Float(f32::INFINITY)),
+        "nan" => Some(Value::Float(f32::NAN)),
+        _ => None,
+    }
+}
+
+/// Names of all available built-in functions, including registered ones, sorted
+pub fn builtin_names(ctx: &Context) -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS.iter().map(|(name, _)| name.to_string()).collect();
+    names.extend(
+        HIGHER_ORDER_BUILTINS
+            .iter()
+            .map(|(name, _)| name.to_string()),
+    );
+    names.extend(CONTEXT_BUILTINS.iter().map(|(name, _)| name.to_string()));
+    REGISTERED_BUILTINS.with(|registry| names.extend(registry.borrow().keys().cloned()));
+    names.retain(|name| !is_disabled_in_sandbox(name, ctx));
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Arguments of a two-argument built-in function, which are passed as a tuple
+fn args_pair(arg: &Value) -> Option<(&Value, &Value)> {
+    match arg {
+        Value::Tuple(elements) => match &elements[..] {
+            [a, b] => Some((a.as_ref(), b.as_ref())),
+            _ => None,
+        },
         _ => None,
     }
 }
@@ -1,5 +1,11 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::debug::format_pattern;
 use crate::parser::Expression;
-use crate::values::builtins::BuiltinFunction;
+use crate::values::builtins::{
+    builtin_name, BuiltinFunction, ContextBuiltinFunction, HigherOrderBuiltinFunction,
+};
+use crate::values::Value;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct UserDefinedFunction {
@@ -8,8 +14,125 @@ pub struct UserDefinedFunction {
     pub body: Expression,
 }
 
+type ResultCache = HashMap<u64, Vec<(Rc<Value>, Rc<Value>)>>;
+
+/// Function wrapped by the `memoize` builtin. Results are cached per argument value and
+/// the cache is shared by all copies of the wrapper, living as long as any of them does.
+/// Calls with unhashable arguments (see `Value::try_hash`) go straight to the wrapped function.
+#[derive(Debug, Clone)]
+pub struct MemoizedFunction {
+    pub func: Rc<Function>,
+    cache: Rc<RefCell<ResultCache>>,
+}
+
+impl MemoizedFunction {
+    pub fn new(func: Function) -> Self {
+        MemoizedFunction {
+            func: Rc::new(func),
+            cache: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    pub fn lookup(&self, arg_hash: u64, arg: &Value) -> Option<Rc<Value>> {
+        self.cache
+            .borrow()
+            .get(&arg_hash)?
+            .iter()
+            .find(|(cached_arg, _)| cached_arg.as_ref() == arg)
+            .map(|(_, result)| result.clone())
+    }
+
+    pub fn store(&self, arg_hash: u64, arg: Rc<Value>, result: Rc<Value>) {
+        self.cache
+            .borrow_mut()
+            .entry(arg_hash)
+            .or_default()
+            .push((arg, result));
+    }
+}
+
+/// Function with its first argument fixed by the `curry` builtin, the arguments of a call
+/// are appended after it
 #[derive(Debug, Clone, PartialEq)]
+pub struct CurriedFunction {
+    pub func: Rc<Function>,
+    pub first_arg: Rc<Value>,
+}
+
+impl CurriedFunction {
+    /// Full argument for the wrapped function: `(first_arg, a, b)` for a call with `(a, b)`
+    pub fn full_arg(&self, arg: Rc<Value>) -> Rc<Value> {
+        match arg.as_ref() {
+            Value::Nothing => self.first_arg.clone(),
+            Value::Tuple(elements) => Rc::new(Value::Tuple(
+                [vec![self.first_arg.clone()], elements.clone()].concat(),
+            )),
+            _ => Rc::new(Value::Tuple(vec![self.first_arg.clone(), arg])),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Function {
     Builtin(BuiltinFunction),
+    HigherOrderBuiltin(HigherOrderBuiltinFunction),
+    ContextBuiltin(ContextBuiltinFunction),
     UserDefined(UserDefinedFunction),
+    /// Function declared in several clauses with literals in parameters, like
+    /// `func f(0) 1; func f(n) n * f(n - 1)`. A call runs the first clause whose parameters
+    /// match the argument.
+    Clauses(Vec<UserDefinedFunction>),
+    Memoized(MemoizedFunction),
+    Curried(CurriedFunction),
+}
+
+/// Signature-like form, e.g. `<function f(a, b)>` or `<builtin log>`
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Function::UserDefined(func) => {
+                write!(
+                    f,
+                    "<function {}({})>",
+                    func.name,
+                    format_pattern(&func.params)
+                )
+            }
+            Function::Clauses(clauses) => {
+                let signatures: Vec<String> = clauses
+                    .iter()
+                    .map(|clause| format!("{}({})", clause.name, format_pattern(&clause.params)))
+                    .collect();
+                write!(f, "<function {}>", signatures.join(" | "))
+            }
+            // memoization is transparent, the wrapper shows as the wrapped function
+            Function::Memoized(memoized) => write!(f, "{}", memoized.func),
+            Function::Curried(curried) => {
+                write!(f, "<curried {} with {}>", curried.func, curried.first_arg)
+            }
+            builtin => match builtin_name(builtin) {
+                Some(name) => write!(f, "<builtin {}>", name),
+                None => write!(f, "<builtin>"),
+            },
+        }
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Function::Builtin(f1), Function::Builtin(f2)) => std::ptr::fn_addr_eq(*f1, *f2),
+            (Function::HigherOrderBuiltin(f1), Function::HigherOrderBuiltin(f2)) => {
+                std::ptr::fn_addr_eq(*f1, *f2)
+            }
+            (Function::ContextBuiltin(f1), Function::ContextBuiltin(f2)) => {
+                std::ptr::fn_addr_eq(*f1, *f2)
+            }
+            (Function::UserDefined(f1), Function::UserDefined(f2)) => f1 == f2,
+            (Function::Clauses(f1), Function::Clauses(f2)) => f1 == f2,
+            (Function::Memoized(f1), Function::Memoized(f2)) => Rc::ptr_eq(&f1.cache, &f2.cache),
+            (Function::Curried(f1), Function::Curried(f2)) => f1 == f2,
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,204 @@
+use std::rc::Rc;
+
+use crate::{
+    parser::{BinaryOp, Expression, UnaryOp},
+    span::Span,
+    values::{function::Function, Value},
+};
+
+/// Depth-first traversal of the AST with a method per `Expression` variant. Every method
+/// walks into the node's children by default, so passes only override the variants they
+/// care about and call `visit` on the children themselves if they still need the traversal.
+pub trait Visitor {
+    fn visit(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Value(value, span) => self.visit_value(value, *span),
+            Expression::Variable(name, span) => self.visit_variable(name, *span),
+            Expression::BinaryOperation {
+                op,
+                left,
+                right,
+                span,
+            } => self.visit_binary_operation(*op, left, right, *span),
+            Expression::UnaryOperation { op, operand, span } => {
+                self.visit_unary_operation(*op, operand, *span)
+            }
+            Expression::Scope {
+                body,
+                is_returnable,
+                span,
+            } => self.visit_scope(body, *is_returnable, *span),
+            Expression::List { elements, span } => self.visit_list(elements, *span),
+            Expression::If {
+                condition,
+                if_true,
+                if_false,
+                span,
+            } => self.visit_if(condition, if_true, if_false.as_deref(), *span),
+            Expression::While {
+                label,
+                condition,
+                body,
+                if_completed,
+                span,
+            } => self.visit_while(
+                label.as_deref(),
+                condition,
+                body,
+                if_completed.as_deref(),
+                *span,
+            ),
+            Expression::Repeat {
+                label,
+                count,
+                body,
+                span,
+            } => self.visit_repeat(label.as_deref(), count, body, *span),
+            Expression::TypeSwitch { value, arms, span } => {
+                self.visit_typeswitch(value, arms, *span)
+            }
+            Expression::Slice {
+                collection,
+                start,
+                end,
+                step,
+                is_slice,
+                span,
+            } => self.visit_slice(
+                collection,
+                [start.as_deref(), end.as_deref(), step.as_deref()],
+                *is_slice,
+                *span,
+            ),
+            Expression::Break { label, span } => self.visit_break(label.as_deref(), *span),
+        }
+    }
+
+    /// Walks into the bodies of user-defined functions, but not into their parameters
+    fn visit_value(&mut self, value: &Rc<Value>, _span: Span) {
+        if let Value::Function(Function::UserDefined(func)) = value.as_ref() {
+            self.visit(&func.body);
+        }
+    }
+
+    fn visit_variable(&mut self, _name: &str, _span: Span) {}
+
+    fn visit_binary_operation(
+        &mut self,
+        _op: BinaryOp,
+        left: &Expression,
+        right: &Expression,
+        _span: Span,
+    ) {
+        self.visit(left);
+        self.visit(right);
+    }
+
+    fn visit_unary_operation(&mut self, _op: UnaryOp, operand: &Expression, _span: Span) {
+        self.visit(operand);
+    }
+
+    fn visit_scope(&mut self, body: &[Expression], _is_returnable: bool, _span: Span) {
+        for expr in body {
+            self.visit(expr);
+        }
+    }
+
+    fn visit_list(&mut self, elements: &[Expression], _span: Span) {
+        for element in elements {
+            self.visit(element);
+        }
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        if_true: &Expression,
+        if_false: Option<&Expression>,
+        _span: Span,
+    ) {
+        self.visit(condition);
+        self.visit(if_true);
+        if let Some(if_false) = if_false {
+            self.visit(if_false);
+        }
+    }
+
+    fn visit_while(
+        &mut self,
+        _label: Option<&str>,
+        condition: &Expression,
+        body: &Expression,
+        if_completed: Option<&Expression>,
+        _span: Span,
+    ) {
+        self.visit(condition);
+        self.visit(body);
+        if let Some(if_completed) = if_completed {
+            self.visit(if_completed);
+        }
+    }
+
+    fn visit_repeat(
+        &mut self,
+        _label: Option<&str>,
+        count: &Expression,
+        body: &Expression,
+        _span: Span,
+    ) {
+        self.visit(count);
+        self.visit(body);
+    }
+
+    fn visit_typeswitch(&mut self, value: &Expression, arms: &[(String, Expression)], _span: Span) {
+        self.visit(value);
+        for (_, body) in arms {
+            self.visit(body);
+        }
+    }
+
+    /// Slice parts are the start, the end and the step, an index is passed as the start
+    fn visit_slice(
+        &mut self,
+        collection: &Expression,
+        parts: [Option<&Expression>; 3],
+        _is_slice: bool,
+        _span: Span,
+    ) {
+        self.visit(collection);
+        for part in parts.into_iter().flatten() {
+            self.visit(part);
+        }
+    }
+
+    fn visit_break(&mut self, _label: Option<&str>, _span: Span) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, tokenize};
+
+    #[derive(Default)]
+    struct VariableCounter {
+        count: usize,
+    }
+
+    impl Visitor for VariableCounter {
+        fn visit_variable(&mut self, _name: &str, _span: Span) {
+            self.count += 1;
+        }
+    }
+
+    #[test]
+    fn test_count_variables() {
+        let code = "func f(x) { x + y }; a = [b, 1]; if (a) { c } else { f(a) }; repeat(n) { d }";
+        let tokens = tokenize(code).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let mut counter = VariableCounter::default();
+        counter.visit(&ast);
+        // f as the assignment target, x and y in its body (parameters aren't visited),
+        // then a, b, a, c, f, a, n, d
+        assert_eq!(counter.count, 11);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static SCRIPT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn run_calculator(code: &str, args: &[&str]) -> String {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!(
+        "cli_{}_{}.clc",
+        std::process::id(),
+        SCRIPT_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::write(&path, code).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .expect("failed to run calculator binary");
+    fs::remove_file(&path).unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_dump_scope() {
+    let stdout = run_calculator("b = 2; a = 1", &["--dump-scope"]);
+    assert_eq!(stdout, "a = 1\nb = 2\n");
+}
+
+#[test]
+fn test_color_never() {
+    let stdout = run_calculator("x = 1;\n1 / (x - 1)", &["--color", "never"]);
+    assert!(stdout.contains("division by zero"));
+    assert!(!stdout.contains('\x1b'));
+}
+
+#[test]
+fn test_color_always() {
+    let stdout = run_calculator("x = 1;\n1 / (x - 1)", &["--color", "always"]);
+    assert!(stdout.contains("\x1b[31mdivision by zero\x1b[0m"));
+    assert!(stdout.contains("\x1b[93m^"));
+}
+
+#[test]
+fn test_repeat_prints() {
+    let stdout = run_calculator("repeat(3) { print(\"x\") }", &[]);
+    assert_eq!(stdout, "x\nx\nx\n");
+}
+
+#[test]
+fn test_debug_prints_internal_representation() {
+    let stdout = run_calculator("x = debug(1.0) * 2; debug((1, \"a\")); print(x)", &[]);
+    assert_eq!(stdout, "Float(1.0)\nTuple([Int(1), String(\"a\")])\n2.0\n");
+}
+
+#[test]
+fn test_dump_tokens_with_spans() {
+    let stdout = run_calculator("1 + 1", &["--dump-tokens-with-spans"]);
+    assert_eq!(
+        stdout,
+        "Number \"1\" 0..1\nPlus \"+\" 2..3\nNumber \"1\" 4..5\n"
+    );
+}
+
+#[test]
+fn test_check_brackets_reports_all_errors() {
+    let stdout = run_calculator("a = (1 + 2));\nb = [1, 2;\nprint(a)", &["--check-brackets"]);
+    assert_eq!(stdout.matches("Parser error").count(), 2);
+    assert!(stdout.contains("unmatched closing bracket"));
+    assert!(stdout.contains("unclosed bracket"));
+
+    let stdout = run_calculator("print((1 + 2))", &["--check-brackets"]);
+    assert_eq!(stdout, "3\n");
+}
+
+#[test]
+fn test_newline_separators() {
+    let code = "x = 1\nprint(x +\n1)";
+    assert_eq!(run_calculator(code, &["--newline-separators"]), "2\n");
+    assert!(run_calculator(code, &[]).starts_with("Parser error"));
+}
+
+#[test]
+fn test_file_io_round_trip() {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("io_{}.txt", std::process::id()));
+    let code = format!(
+        "path = \"{}\"; write_file(path, \"line 1\\nline 2\"); print(read_file(path))",
+        path.display()
+    );
+    assert_eq!(run_calculator(&code, &[]), "line 1\nline 2\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "line 1\nline 2");
+
+    let stdout = run_calculator(&format!("read_file(\"{}\")", path.display()), &["--no-io"]);
+    assert!(stdout.contains("\"read_file\" is not available, file access is disabled"));
+    fs::remove_file(&path).unwrap();
+
+    let stdout = run_calculator(&format!("read_file(\"{}\")", path.display()), &[]);
+    assert!(stdout.contains("can't read file"));
+}
+
+#[test]
+fn test_sandbox_disables_side_effects() {
+    let stdout = run_calculator("print(\"x\")", &["--sandbox"]);
+    assert!(!stdout.starts_with("x\n"));
+    assert!(stdout.contains("\"print\" built-in function is disabled in sandbox mode"));
+    assert_eq!(
+        run_calculator("print = 2; x = print * 3", &["--sandbox", "--dump-scope"]),
+        "print = 2\nx = 6\n"
+    );
+}
+
+#[test]
+fn test_per_line() {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("per_line_{}.clc", std::process::id()));
+    fs::write(&path, "previous = LINE; print(pad_left(LINE, 6, \".\"))").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(["--per-line", "--dump-scope"])
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run calculator binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"first\nsecond\nthird\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        ".first\nsecond\n.third\nLINE = third\nprevious = third\n"
+    );
+}
+
+#[test]
+fn test_memory_limit_per_line() {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("memory_per_line_{}.clc", std::process::id()));
+    fs::write(&path, "print(length(LINE * 300))").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(["--per-line", "--memory-limit", "1000"])
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run calculator binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"ab\ncd\nef\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    // each line builds 600 characters, only the sum of all lines would exceed the limit
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "600\n600\n600\n");
+}
+
+#[test]
+fn test_explain_error_code() {
+    let stdout = run_calculator("", &["--explain", "E0005"]);
+    assert!(stdout.starts_with("Runtime error: division by zero.\n"));
+    assert_eq!(run_calculator("", &["--explain", "e0005"]), stdout);
+    assert_eq!(
+        run_calculator("", &["--explain", "E9999"]),
+        "no such error code \"E9999\"\n"
+    );
+}
+
+#[test]
+fn test_version_flag() {
+    let stdout = run_calculator("print(1)", &["--version"]);
+    assert_eq!(
+        stdout,
+        format!("calculator {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn test_ast_dot_doesnt_run_program() {
+    let stdout = run_calculator("print(1)", &["--ast-dot"]);
+    assert!(stdout.starts_with("digraph AST {\n"));
+    assert!(stdout.contains("[label=\"FunctionCall\"]"));
+    assert!(!stdout.contains("\n1\n"));
+}
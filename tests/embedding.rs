@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+use calculator::{eval, parse, register_builtin, tokenize, values::Value, Context};
+
+fn run(code: &str) -> Value {
+    let tokens = tokenize(code).unwrap();
+    let ast = parse(&tokens).unwrap();
+    eval(&ast, &mut HashMap::new(), &mut Context::default())
+        .unwrap()
+        .as_ref()
+        .clone()
+}
+
+fn double(arg: &Value) -> Result<Value, String> {
+    match arg {
+        Value::Int(i) => Ok(Value::Int(i * 2)),
+        _ => Err("double accepts an integer".into()),
+    }
+}
+
+#[test]
+fn test_register_builtin() {
+    register_builtin("double", double);
+    assert_eq!(run("double(21)"), Value::Int(42));
+    assert_eq!(run("f = double; f(f(1))"), Value::Int(4));
+}
+
+#[test]
+fn test_registered_builtin_overrides_standard_one() {
+    register_builtin("log", double);
+    assert_eq!(run("log(2)"), Value::Int(4));
+}
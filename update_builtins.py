@@ -6,7 +6,7 @@ import re
 if __name__ == "__main__":
     builtins_rs_file = Path(__name__).parent / "src/values/builtins.rs"
     builtins_rs_source = builtins_rs_file.read_text()
-    builtin_func_patt = r"\"(?P<builtin_name>\w+)\" => Some\("
+    builtin_func_patt = r"\(\"(?P<builtin_name>\w+)\", \w+\),"
     builtin_funcs = []
     for line in builtins_rs_source.splitlines():
         match = re.match(builtin_func_patt, line.strip())